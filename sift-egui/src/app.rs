@@ -1,29 +1,58 @@
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use eframe::egui::{self, Button, ScrollArea};
-use sift_persist::{MemoryStore, Store as _, Task, TaskId};
+use sift_persist::{Store as _, Task, TaskId, Watcher};
 use sift_state::State;
 
 pub struct App {
     state: State,
     save_path: PathBuf,
     editing_task: Option<TaskId>,
+    // The task whose details are shown in the side panel, with its notes as
+    // edited so far.  They are kept once the editor loses focus or closes.
+    details: Option<(TaskId, String)>,
+    // Set by the watcher's thread when the save file changes on disk.
+    file_changed: Arc<AtomicBool>,
+    _watcher: Watcher,
 }
 
 impl App {
-    pub fn load(path: &Path) -> anyhow::Result<App> {
+    pub fn load(ctx: &egui::Context, path: &Path) -> anyhow::Result<App> {
+        let file_changed = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let file_changed = Arc::clone(&file_changed);
+            let ctx = ctx.clone();
+            Watcher::new(path, Duration::from_secs(1), move || {
+                file_changed.store(true, Ordering::Relaxed);
+                ctx.request_repaint();
+            })
+        };
         Ok(Self {
-            state: State::new(MemoryStore::load(path)?),
+            state: State::new(sift_state::open_store(path)?),
             save_path: path.to_path_buf(),
             editing_task: None,
+            details: None,
+            file_changed,
+            _watcher: watcher,
         })
     }
 
-    fn sift_save(&self) {
+    // Keep the notes being edited, as one change, and save if they changed.
+    fn commit_notes(&mut self) {
+        if let Some((id, notes)) = self.details.clone() {
+            if self.state.set_notes(&id, &notes) {
+                self.sift_save();
+            }
+        }
+    }
+
+    fn sift_save(&mut self) {
         // TODO: there is an eframe::App::save() method that provides a key/value
         // storage API. Consider using that here.
         self.state
-            .store
             .save(&self.save_path)
             .expect("TODO: handle error");
     }
@@ -31,19 +60,81 @@ impl App {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.file_changed.swap(false, Ordering::Relaxed) {
+            if let Err(error) = self.state.reload() {
+                log::warn!("reloading todos failed: {}", error);
+            }
+        }
+        // A file opened read-only is shown but cannot be edited.
+        let editable = !self.state.store.is_read_only();
+
+        let detail_task = self
+            .details
+            .as_ref()
+            .and_then(|(id, _)| self.state.get_task(id));
+        if let Some(task) = detail_task {
+            let mut close = false;
+            let mut commit = false;
+            egui::SidePanel::right("details").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading(task.title());
+                    close = ui.small_button("Close").clicked();
+                });
+                ui.label("Notes");
+                if let Some((_, notes)) = &mut self.details {
+                    let response = ui.add_enabled(
+                        editable,
+                        egui::TextEdit::multiline(notes)
+                            .desired_width(f32::INFINITY)
+                            .desired_rows(12),
+                    );
+                    commit = response.lost_focus();
+                }
+            });
+            if close || commit {
+                self.commit_notes();
+            }
+            if close {
+                self.details = None;
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("Todos");
+            ui.horizontal(|ui| {
+                if self.state.store.is_read_only() {
+                    ui.heading("Todos (read-only)");
+                } else {
+                    ui.heading("Todos");
+                }
+                let current = self.state.current_list();
+                egui::ComboBox::from_id_source("list")
+                    .selected_text(current.name)
+                    .show_ui(ui, |ui| {
+                        for list in self.state.lists() {
+                            if ui
+                                .selectable_label(list.id == self.state.list, list.name.as_str())
+                                .clicked()
+                            {
+                                self.editing_task = None;
+                                self.state.select_list(list.id);
+                            }
+                        }
+                    });
+            });
 
             ScrollArea::vertical().show(ui, |ui| {
-                let add_task_clicked = ui.add(Button::new("Add a task")).clicked();
+                let add_task_clicked = ui
+                    .add_enabled(editable, Button::new("Add a task"))
+                    .clicked();
                 if add_task_clicked {
-                    let task = Task::new(Task::new_id(), String::new(), None, None, None);
+                    let mut task = Task::new(Task::new_id(), String::new(), None, None, None);
+                    task.set_list(self.state.list);
                     self.editing_task = Some(task.id());
                     self.state
                         .store
                         .with_transaction(|txn| {
                             let previous = None;
-                            txn.insert_task(previous, &task)
+                            txn.insert_task(None, previous, &task)
                         })
                         .expect("FIXME: handle error");
                 }
@@ -65,16 +156,26 @@ impl eframe::App for App {
                         }
                         if response.lost_focus() {
                             self.editing_task = None;
+                            self.state.take_tags_from_title(&task.id());
                             self.sift_save();
                         }
                     } else {
-                        let checked = task.completed().is_some();
-                        let mut checkbox_checked = checked;
-                        ui.checkbox(&mut checkbox_checked, task.title());
-                        if checkbox_checked != checked {
-                            self.state.toggle_id(&task.id());
-                            self.sift_save();
-                        }
+                        ui.horizontal(|ui| {
+                            let checked = task.completed().is_some();
+                            let mut checkbox_checked = checked;
+                            ui.add_enabled(
+                                editable,
+                                egui::Checkbox::new(&mut checkbox_checked, task.title_with_tags()),
+                            );
+                            if checkbox_checked != checked {
+                                self.state.toggle_id(&task.id());
+                                self.sift_save();
+                            }
+                            if ui.small_button("Details").clicked() {
+                                self.commit_notes();
+                                self.details = Some((task.id(), task.notes().to_string()));
+                            }
+                        });
                     }
                 }
             });
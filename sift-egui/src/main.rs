@@ -18,7 +18,7 @@ fn main() -> eframe::Result {
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
 
-            Ok(Box::new(App::load(&save_name())?))
+            Ok(Box::new(App::load(&cc.egui_ctx, &save_name())?))
         }),
     )
 }
@@ -1,15 +1,18 @@
 use std::sync::LazyLock;
+use std::time::Duration;
 
 use chrono::NaiveDate;
+use iced::futures::Stream;
 use iced::widget::{
-    button, center, checkbox, column, container, keyed_column, row, scrollable, text, text_input,
+    button, center, checkbox, column, container, keyed_column, pick_list, row, scrollable, text,
+    text_editor, text_input,
 };
 use iced::Alignment::Center;
-use iced::Element;
 use iced::Length::Fill;
+use iced::{Element, Subscription};
 use iced_aw::date_picker;
 use sift_core::save_name;
-use sift_persist::{MemoryStore, Store as _, Task, TaskId};
+use sift_persist::{MemoryStore, NamedList, Store as _, Task, TaskId, Watcher};
 use sift_state::State;
 
 pub struct App {
@@ -25,6 +28,7 @@ pub enum AppMessage {
 #[derive(Debug, Clone)]
 pub enum LoadedMessage {
     // TODO: make this message specific to LoadedApp.
+    SelectList(NamedList),
     CompleteToggled(TaskId, bool),
     CreateTaskInputChanged(String),
     CreateTask,
@@ -32,6 +36,10 @@ pub enum LoadedMessage {
     EditDueDate(TaskId),
     EditDueSubmit(date_picker::Date),
     EditDueCancel,
+    ShowDetails(TaskId),
+    HideDetails,
+    EditNotes(text_editor::Action),
+    FileChanged,
 }
 
 impl App {
@@ -43,7 +51,7 @@ impl App {
     }
 
     async fn load() -> anyhow::Result<MemoryStore> {
-        MemoryStore::load(&save_name())
+        sift_state::open_store(&save_name())
     }
 
     pub fn update(&mut self, message: AppMessage) {
@@ -53,6 +61,7 @@ impl App {
                     self.loaded = Some(LoadedApp {
                         create_task_name: String::new(),
                         editing_due_date: None,
+                        details: None,
                         state: State::new(store),
                     })
                 }
@@ -74,11 +83,33 @@ impl App {
     fn view_loading(&self) -> Element<AppMessage> {
         center(text("Loading...").width(Fill).align_x(Center).size(50)).into()
     }
+
+    pub fn subscription(&self) -> Subscription<AppMessage> {
+        match &self.loaded {
+            None => Subscription::none(),
+            Some(_) => Subscription::run(watch_save_file).map(AppMessage::Loaded),
+        }
+    }
+}
+
+// Send a message each time the save file changes on disk, for as long as the
+// subscription lasts.
+fn watch_save_file() -> impl Stream<Item = LoadedMessage> {
+    iced::stream::channel(1, |mut output| async move {
+        let _watcher = Watcher::new(&save_name(), Duration::from_secs(1), move || {
+            // A full channel already holds a message to reload.
+            let _ = output.try_send(LoadedMessage::FileChanged);
+        });
+        std::future::pending::<()>().await;
+    })
 }
 
 pub struct LoadedApp {
     create_task_name: String,
     editing_due_date: Option<TaskId>,
+    // The task whose details are shown beside the list, and its notes as
+    // edited so far.  They are kept when the details are closed.
+    details: Option<(TaskId, text_editor::Content)>,
     state: State,
 }
 
@@ -86,33 +117,55 @@ static INPUT_ID: LazyLock<text_input::Id> = LazyLock::new(text_input::Id::unique
 
 impl LoadedApp {
     fn view(&self) -> Element<LoadedMessage> {
-        let title = text("todos")
+        // Widgets without a message to send are disabled, as they all are in
+        // a file opened read-only.
+        let editable = !self.state.store.is_read_only();
+        let title = if !editable {
+            "todos (read-only)"
+        } else {
+            "todos"
+        };
+        let title = text(title)
             .width(Fill)
             .size(100)
             .color([0.5, 0.5, 0.5])
             .align_x(Center);
 
-        let input = text_input("What needs to be done?", &self.create_task_name)
+        let lists = pick_list(
+            self.state.lists(),
+            Some(self.state.current_list()),
+            LoadedMessage::SelectList,
+        )
+        .padding(10);
+
+        let mut input = text_input("What needs to be done?", &self.create_task_name)
             .id(INPUT_ID.clone())
-            .on_input(LoadedMessage::CreateTaskInputChanged)
-            .on_submit(LoadedMessage::CreateTask)
             .padding(15)
             .size(30)
             .align_x(Center);
+        if editable {
+            input = input
+                .on_input(LoadedMessage::CreateTaskInputChanged)
+                .on_submit(LoadedMessage::CreateTask);
+        }
 
         let tasks = self.state.list_tasks_for_display();
         let tasks: Element<_> = if tasks.is_empty() {
-            unimplemented!("implement display of zero tasks; see https://github.com/iced-rs/iced/blob/9b99b932bced46047ec2e18c2b6ec5a6c5b3636f/examples/todos/src/main.rs#L229");
+            // A new list has no tasks yet.
+            center(text("Nothing to do").color([0.7, 0.7, 0.7]))
+                .height(200)
+                .into()
         } else {
             keyed_column(tasks.iter().map(|task| {
                 let id = task.id();
-                let checkbox = checkbox(task.title().to_string(), task.completed().is_some())
-                    .on_toggle(move |complete| LoadedMessage::CompleteToggled(id, complete));
+                let on_toggle = move |complete| LoadedMessage::CompleteToggled(id, complete);
+                let checkbox = checkbox(task.title_with_tags(), task.completed().is_some())
+                    .on_toggle_maybe(editable.then_some(on_toggle));
 
                 let picker = {
                     let editing = self.editing_due_date == Some(id);
-                    let button =
-                        button("Due").on_press_with(move || LoadedMessage::EditDueDate(id));
+                    let button = button("Due")
+                        .on_press_maybe(editable.then_some(LoadedMessage::EditDueDate(id)));
                     date_picker(
                         editing,
                         date_picker::Date::default(),
@@ -122,20 +175,52 @@ impl LoadedApp {
                     )
                 };
 
-                let delete = button("Delete").on_press_with(move || LoadedMessage::Delete(id));
+                let details =
+                    button("Details").on_press_with(move || LoadedMessage::ShowDetails(id));
+                let delete =
+                    button("Delete").on_press_maybe(editable.then_some(LoadedMessage::Delete(id)));
 
-                let row = row![checkbox, picker, delete];
+                let row = row![checkbox, picker, details, delete];
                 (task.id(), row.into())
             }))
             .into()
         };
 
-        let content = column![title, input, tasks];
-        scrollable(container(content).center_x(Fill).padding(20)).into()
+        let content = column![title, lists, input, tasks].spacing(20);
+        let list = scrollable(container(content).center_x(Fill).padding(20));
+        match self.view_details() {
+            Some(details) => row![list, details].into(),
+            None => list.into(),
+        }
+    }
+
+    fn view_details(&self) -> Option<Element<LoadedMessage>> {
+        let (id, notes) = self.details.as_ref()?;
+        let task = self.state.get_task(id)?;
+        let heading = row![
+            text(task.title().to_string()).size(30).width(Fill),
+            button("Close").on_press(LoadedMessage::HideDetails),
+        ];
+        let mut notes = text_editor(notes).placeholder("Notes").height(300);
+        if !self.state.store.is_read_only() {
+            notes = notes.on_action(LoadedMessage::EditNotes);
+        }
+        Some(
+            container(column![heading, notes].spacing(10))
+                .width(400)
+                .padding(20)
+                .into(),
+        )
     }
 
     fn update(&mut self, message: LoadedMessage) {
         match message {
+            LoadedMessage::SelectList(list) => {
+                self.editing_due_date = None;
+                self.commit_notes();
+                self.details = None;
+                self.state.select_list(list.id);
+            }
             LoadedMessage::CompleteToggled(id, checked) => {
                 // TODO: add a method to state that sets completion given a bool.
                 if let Some(task) = self.state.get_task(&id) {
@@ -149,18 +234,14 @@ impl LoadedApp {
                 self.create_task_name = s;
             }
             LoadedMessage::CreateTask => {
-                let task = Task::new(
-                    Task::new_id(),
-                    std::mem::take(&mut self.create_task_name),
-                    None,
-                    None,
-                    None,
-                );
+                let mut task = Task::new(Task::new_id(), String::new(), None, None, None);
+                task.set_title_with_tags(&std::mem::take(&mut self.create_task_name));
+                task.set_list(self.state.list);
                 self.state
                     .store
                     .with_transaction(|txn| {
                         let previous_task = None;
-                        txn.insert_task(previous_task, &task)
+                        txn.insert_task(None, previous_task, &task)
                     })
                     .expect("FIXME: handle error");
             }
@@ -183,11 +264,43 @@ impl LoadedApp {
                 self.editing_due_date = None;
             }
             LoadedMessage::EditDueCancel => self.editing_due_date = None,
+            LoadedMessage::ShowDetails(id) => {
+                self.commit_notes();
+                let notes = self
+                    .state
+                    .get_task(&id)
+                    .map(|task| text_editor::Content::with_text(task.notes()));
+                self.details = notes.map(|notes| (id, notes));
+            }
+            LoadedMessage::HideDetails => {
+                self.commit_notes();
+                self.details = None;
+            }
+            LoadedMessage::EditNotes(action) => {
+                if let Some((_, notes)) = &mut self.details {
+                    notes.perform(action);
+                }
+            }
+            LoadedMessage::FileChanged => {
+                if let Err(error) = self.state.reload() {
+                    eprintln!("reloading todos failed: {}", error);
+                }
+            }
+        }
+    }
+
+    // Keep the notes being edited, as one change, and save if they changed.
+    fn commit_notes(&mut self) {
+        if let Some((id, notes)) = &self.details {
+            let (id, notes) = (*id, notes.text());
+            if self.state.set_notes(&id, &notes) {
+                self.save();
+            }
         }
     }
 
-    fn save(&self) {
+    fn save(&mut self) {
         // TODO: don't use unwrap() below.
-        self.state.store.save(&save_name()).unwrap();
+        self.state.flush().unwrap();
     }
 }
@@ -2,6 +2,7 @@ use sift_iced::App;
 
 pub fn main() -> iced::Result {
     iced::application("Sift", App::update, App::view)
+        .subscription(App::subscription)
         .font(iced_aw::BOOTSTRAP_FONT_BYTES)
         .run_with(App::new)
 }
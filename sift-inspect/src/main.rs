@@ -0,0 +1,152 @@
+/*!
+Print the structure and contents of a Sift task file, to diagnose files that
+fail to load.  Exits with a non-zero status if the file is damaged.
+*/
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::uninlined_format_args)]
+#![deny(unused_crate_dependencies)]
+#![deny(unused_extern_crates)]
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Result;
+use automerge::{AutoCommit, AutoSerde};
+use clap::Parser;
+use sift_core::save_name;
+use sift_persist::{inspect_file, Inspection, Passphrase};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// The task file to inspect.  Defaults to the file the Sift apps use.
+    file: Option<PathBuf>,
+
+    /// Ask for the passphrase of an encrypted file, to look inside it.
+    #[arg(long)]
+    passphrase: bool,
+
+    /// Leave out the document's contents.
+    #[arg(long)]
+    no_document: bool,
+
+    /// Leave out the document's change history.
+    #[arg(long)]
+    no_history: bool,
+}
+
+fn print_chunks(inspection: &Inspection) {
+    match (inspection.format_version, &inspection.writer) {
+        (Some(version), Some(writer)) => {
+            println!("Format version {}, written by {}", version, writer);
+        }
+        (Some(version), None) => println!("Format version {}", version),
+        (None, _) => {}
+    }
+    println!();
+    println!(
+        "{:>10}  {:4}  {:>10}  {:8}  CRC",
+        "Offset", "Type", "Length", "Stored"
+    );
+    for chunk in &inspection.chunks {
+        let mut notes = Vec::new();
+        if !chunk.is_critical() {
+            notes.push("ancillary");
+        }
+        if chunk.is_transformed() {
+            notes.push("compressed or encrypted");
+        }
+        println!(
+            "{:>10}  {:4}  {:>10}  {:08x}  {}{}",
+            chunk.offset,
+            chunk.chunk_type.escape_ascii().to_string(),
+            chunk.length,
+            chunk.crc,
+            if chunk.crc_ok { "ok" } else { "MISMATCH" },
+            if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", notes.join(", "))
+            }
+        );
+    }
+}
+
+fn print_document(document: &AutoCommit) -> Result<()> {
+    println!();
+    println!("Document:");
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&AutoSerde::from(document))?
+    );
+    Ok(())
+}
+
+fn print_history(document: &mut AutoCommit) {
+    let changes = document.get_changes(&[]);
+    println!();
+    println!("History ({} changes):", changes.len());
+    for change in changes {
+        // Sift records commit times in seconds; zero means none was recorded.
+        let time = match chrono::DateTime::from_timestamp(change.timestamp(), 0) {
+            Some(time) if change.timestamp() != 0 => time.to_rfc3339(),
+            _ => String::from("-"),
+        };
+        let message = change.message().map_or("", |message| message.as_str());
+        println!(
+            "{}  {}  actor {} #{}  {}",
+            change.hash(),
+            time,
+            change.actor_id(),
+            change.seq(),
+            message.replace('\n', "; ")
+        );
+    }
+}
+
+// Describe `error` along with the errors that caused it.
+fn describe(error: &dyn Error) -> String {
+    let mut description = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        description.push_str(": ");
+        description.push_str(&error.to_string());
+        source = error.source();
+    }
+    description
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    let path = args.file.unwrap_or_else(save_name);
+    let passphrase = if args.passphrase {
+        let prompt = format!("Passphrase for {}: ", path.display());
+        Some(Passphrase::from(rpassword::prompt_password(prompt)?))
+    } else {
+        None
+    };
+
+    let mut inspection = inspect_file(&path, passphrase)?;
+    println!("{}", path.display());
+    print_chunks(&inspection);
+    if let Some(document) = &mut inspection.document {
+        if !args.no_document {
+            print_document(document)?;
+        }
+        if !args.no_history {
+            print_history(document);
+        }
+    }
+
+    if let Some(failure) = &inspection.failure {
+        eprintln!();
+        eprintln!(
+            "error at byte {}: {}",
+            failure.offset,
+            describe(&failure.error)
+        );
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
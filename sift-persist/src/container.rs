@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 use std::io::{Read, Write};
 
+use crate::transform::{self, Compression, Encryption};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     //#[error("Cannot open `{1}`")]
@@ -15,8 +17,40 @@ pub enum Error {
     WriteInvalidChunkSize(usize),
     #[error("CRC mismatch when reading data")]
     ReadCrcMismatch,
+    #[error("Unknown critical chunk `{}`", .0.escape_ascii())]
+    UnknownCriticalChunk([u8; 4]),
+    #[error("Unexpected chunk `{}`", .0.escape_ascii())]
+    UnexpectedChunk([u8; 4]),
+    #[error("Missing `{}` chunk", .0.escape_ascii())]
+    MissingChunk([u8; 4]),
+    #[error("Invalid `SHDR` chunk")]
+    InvalidFileHeader,
+    #[error("Unsupported file format version {0}; the newest supported version is {1}")]
+    UnsupportedVersion(u32, u32),
+    #[error("Invalid chunk transform header")]
+    InvalidTransformHeader,
+    #[error("Unknown chunk compression {0}")]
+    UnknownCompression(u8),
+    #[error("Unknown chunk encryption {0}")]
+    UnknownEncryption(u8),
+    #[error("Cannot compress chunk")]
+    Compress(#[source] std::io::Error),
+    #[error("Cannot decompress chunk")]
+    Decompress(#[source] std::io::Error),
+    #[error("Decompressed chunk is larger than {0} bytes")]
+    DecompressedChunkTooLarge(u32),
+    #[error("Cannot derive a key from the passphrase: {0}")]
+    KeyDerivation(argon2::Error),
+    #[error("Cannot encrypt chunk")]
+    Encrypt,
+    #[error("The file is encrypted and no passphrase was given")]
+    PassphraseRequired,
+    #[error("Cannot decrypt chunk: wrong passphrase, or the file was tampered with")]
+    Decrypt,
 }
 
+pub(crate) const CHUNK_SIZE_LIMIT: u32 = 1024 * 1024 * 1024;
+
 fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
     let mut bytes = [0; 4];
     reader.read_exact(&mut bytes).map_err(Error::Read)?;
@@ -31,7 +65,12 @@ fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<(), Error> {
     Ok(())
 }
 
-// Define a struct for the chunk
+// Define a struct for the chunk.
+//
+// Chunk types are four ASCII letters.  As in PNG, the case of the first letter
+// says whether a reader must understand the chunk to read the file correctly:
+// uppercase for critical chunks, lowercase for ancillary chunks that a reader
+// may skip if it does not know them.
 #[derive(Debug)]
 pub(crate) struct Chunk {
     pub(crate) chunk_type: [u8; 4],
@@ -44,25 +83,25 @@ impl Chunk {
         Self { chunk_type, data }
     }
 
-    fn read<R: Read>(reader: &mut R) -> Result<Chunk, Error> {
-        let data_length = read_u32(reader)?;
-        let chunk_size_limit = 1024 * 1024 * 1024;
-        if data_length > chunk_size_limit {
-            return Err(Error::ReadInvalidChunkSize(data_length, chunk_size_limit));
-        }
-        let mut chunk_type = [0; 4];
-        reader.read_exact(&mut chunk_type).map_err(Error::Read)?;
-        let mut data = vec![0; usize::try_from(data_length).expect("u32 must fit in a usize")];
-        reader.read_exact(&mut data).map_err(Error::Read)?;
-
-        let chunk = Chunk { chunk_type, data };
-
-        let crc = read_u32(reader)?;
-        if crc != chunk.compute_crc() {
+    fn read<R: Read>(reader: &mut R, encryption: Option<&mut Encryption>) -> Result<Chunk, Error> {
+        let stored = StoredChunk::read(reader)?;
+        // The CRC covers the chunk as stored, so it is checked before any
+        // transform is undone.
+        if !stored.crc_matches() {
             return Err(Error::ReadCrcMismatch);
         }
+        stored.chunk.decode(encryption)
+    }
 
-        Ok(chunk)
+    // Undo any transform of the payload of a chunk as stored.
+    pub(crate) fn decode(self, encryption: Option<&mut Encryption>) -> Result<Chunk, Error> {
+        if !transform::is_transformed(self.chunk_type) {
+            return Ok(self);
+        }
+        Ok(Chunk {
+            chunk_type: transform::plain_type(self.chunk_type),
+            data: transform::decode(self.chunk_type, &self.data, encryption)?,
+        })
     }
 
     fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
@@ -76,6 +115,26 @@ impl Chunk {
         Ok(())
     }
 
+    // Write the chunk with its payload compressed and, given `encryption`,
+    // encrypted.  Without either, the chunk is written as is.
+    fn write_transformed<W: Write>(
+        &self,
+        writer: &mut W,
+        compression: Compression,
+        encryption: Option<&mut Encryption>,
+    ) -> Result<(), Error> {
+        if compression == Compression::None && encryption.is_none() {
+            return self.write(writer);
+        }
+        let chunk_type = transform::transformed_type(self.chunk_type);
+        let data = transform::encode(chunk_type, &self.data, compression, encryption)?;
+        Chunk { chunk_type, data }.write(writer)
+    }
+
+    pub(crate) fn is_critical(&self) -> bool {
+        self.chunk_type[0].is_ascii_uppercase()
+    }
+
     fn compute_crc(&self) -> u32 {
         let mut digest = crate::crc::Digest::new();
         digest.update(&self.chunk_type);
@@ -95,6 +154,36 @@ impl Chunk {
     }
 }
 
+// A chunk as stored, along with the CRC stored after it, which has not been
+// checked.
+#[derive(Debug)]
+pub(crate) struct StoredChunk {
+    pub(crate) chunk: Chunk,
+    pub(crate) crc: u32,
+}
+
+impl StoredChunk {
+    pub(crate) fn read<R: Read>(reader: &mut R) -> Result<StoredChunk, Error> {
+        let data_length = read_u32(reader)?;
+        if data_length > CHUNK_SIZE_LIMIT {
+            return Err(Error::ReadInvalidChunkSize(data_length, CHUNK_SIZE_LIMIT));
+        }
+        let mut chunk_type = [0; 4];
+        reader.read_exact(&mut chunk_type).map_err(Error::Read)?;
+        let mut data = vec![0; usize::try_from(data_length).expect("u32 must fit in a usize")];
+        reader.read_exact(&mut data).map_err(Error::Read)?;
+        let crc = read_u32(reader)?;
+        Ok(StoredChunk {
+            chunk: Chunk { chunk_type, data },
+            crc,
+        })
+    }
+
+    pub(crate) fn crc_matches(&self) -> bool {
+        self.crc == self.chunk.compute_crc()
+    }
+}
+
 // The following byte array represents the header of the file. The same value as
 // a C-style string is "\x89SIFT\r\n\x1A\n". This format is taken from PNG and
 // has useful properties, primarily the leading byte which is outside the ASCII
@@ -103,6 +192,53 @@ impl Chunk {
 // between different line endings.
 const HEADER: &[u8] = &[0x89, 0x53, 0x49, 0x46, 0x54, 0x0D, 0x0A, 0x1A, 0x0A];
 
+pub(crate) const HEADER_CHUNK: [u8; 4] = *b"SHDR";
+
+// The version of the container layout written by this build.  Files written
+// before the `SHDR` chunk existed are version 0.  The version only changes for
+// incompatible changes; new chunks that older builds can safely ignore are
+// added as ancillary chunks instead.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+// The contents of the `SHDR` chunk, which comes first in the file: the format
+// version as a big-endian u32, followed by the UTF-8 name and version of the
+// program that wrote the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileHeader {
+    pub(crate) version: u32,
+    pub(crate) writer: String,
+}
+
+impl FileHeader {
+    pub(crate) fn current() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            writer: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    pub(crate) fn to_chunk(&self) -> Chunk {
+        let mut data = self.version.to_be_bytes().to_vec();
+        data.extend_from_slice(self.writer.as_bytes());
+        Chunk::new(HEADER_CHUNK, data)
+    }
+
+    // Parse a `SHDR` chunk, rejecting versions newer than this build
+    // understands.
+    pub(crate) fn from_chunk(chunk: &Chunk) -> Result<Self, Error> {
+        if chunk.chunk_type != HEADER_CHUNK || chunk.data.len() < 4 {
+            return Err(Error::InvalidFileHeader);
+        }
+        let (version, writer) = chunk.data.split_at(4);
+        let version = u32::from_be_bytes(version.try_into().expect("split at 4 bytes"));
+        if version > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version, FORMAT_VERSION));
+        }
+        let writer = String::from_utf8(writer.to_vec()).map_err(|_| Error::InvalidFileHeader)?;
+        Ok(Self { version, writer })
+    }
+}
+
 // Function to read the header of the file
 pub(crate) fn read_header<R: Read>(reader: &mut R) -> Result<(), Error> {
     let mut header = [0; HEADER.len()];
@@ -125,12 +261,26 @@ pub(crate) fn write_header<W: Write>(writer: &mut W) -> Result<(), Error> {
     writer.write_all(HEADER).map_err(Error::Write)
 }
 
-// Function to read a single PNG chunk
-pub(crate) fn read_chunk<R: Read>(reader: &mut R) -> Result<Chunk, Error> {
-    Chunk::read(reader)
+// Function to read a single PNG chunk, undoing any transform of its payload.
+// `encryption` is needed only for encrypted chunks.
+pub(crate) fn read_chunk<R: Read>(
+    reader: &mut R,
+    encryption: Option<&mut Encryption>,
+) -> Result<Chunk, Error> {
+    Chunk::read(reader, encryption)
 }
 
 // Function to write a single PNG chunk
 pub(crate) fn write_chunk<W: Write>(chunk: &Chunk, writer: &mut W) -> Result<(), Error> {
     chunk.write(writer)
 }
+
+// Function to write a single PNG chunk with its payload transformed.
+pub(crate) fn write_transformed_chunk<W: Write>(
+    chunk: &Chunk,
+    writer: &mut W,
+    compression: Compression,
+    encryption: Option<&mut Encryption>,
+) -> Result<(), Error> {
+    chunk.write_transformed(writer, compression, encryption)
+}
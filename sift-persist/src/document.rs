@@ -1,12 +1,19 @@
-use std::fs::File;
+use std::ffi::OsString;
+use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
-use automerge::Automerge;
+use automerge::{AutoCommit, ROOT};
 
-use super::container::{self, read_chunk, read_header, write_chunk, write_header, Chunk};
+use super::container::{
+    self, read_chunk, read_header, write_chunk, write_header, write_transformed_chunk, Chunk,
+    FileHeader, HEADER_CHUNK,
+};
 use super::Task;
-use crate::serialization::SerializableTaskList;
+use crate::list::{with_default, NamedList};
+use crate::lock::LockOwner;
+use crate::serialization::{stored_lists, SerializableTaskList};
+use crate::transform::{Compression, Encryption, Passphrase};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -14,18 +21,60 @@ pub enum Error {
     CreateFile(#[source] std::io::Error, PathBuf),
     #[error("Cannot open file `{1}`")]
     OpenFile(#[source] std::io::Error, PathBuf),
+    #[error("Cannot read from file")]
+    Read(#[source] std::io::Error),
     #[error("Cannot write to file")]
     Write(#[source] std::io::Error),
+    #[error("Cannot replace file `{1}`")]
+    Replace(#[source] std::io::Error, PathBuf),
+    #[error("Cannot sync directory `{1}`")]
+    SyncDirectory(#[source] std::io::Error, PathBuf),
+    #[error("Cannot back up file to `{1}`")]
+    Backup(#[source] std::io::Error, PathBuf),
+    #[error("Backup `{0}` does not exist")]
+    MissingBackup(PathBuf),
+    #[error("Cannot move damaged file aside to `{1}`")]
+    MoveAside(#[source] std::io::Error, PathBuf),
+    #[error("Nothing could be salvaged from `{0}` or its backups")]
+    NothingToSalvage(PathBuf),
+    #[error("`{path}` is locked by process {} on {}", .owner.pid, .owner.hostname)]
+    Locked { path: PathBuf, owner: LockOwner },
+    #[error("Cannot lock file `{1}`")]
+    Lock(#[source] std::io::Error, PathBuf),
+    #[error("Cannot save `{0}`: it was opened read-only")]
+    ReadOnly(PathBuf),
     #[error("Cannot write container item to file")]
     ContainerWrite(#[source] container::Error),
     #[error("Cannot read container item to file")]
     ContainerRead(#[source] container::Error),
+    #[error("The file is encrypted; a passphrase is required to open it")]
+    PassphraseRequired,
+    #[error("Wrong passphrase, or the file was tampered with")]
+    WrongPassphrase,
     #[error("Cannot load automerge document")]
     AutomergeLoad(#[source] automerge::AutomergeError),
+    #[error("Cannot merge automerge documents")]
+    AutomergeMerge(#[source] automerge::AutomergeError),
     #[error("Cannot reconcile program state as an automerge document")]
     Reconcile(#[source] autosurgeon::ReconcileError),
     #[error("Cannot hydrate from automerge document")]
     Hydrate(#[source] autosurgeon::HydrateError),
+    #[error("Unknown format `{0}`; expected json, csv, md, todotxt or ics")]
+    UnknownFormat(String),
+    #[error("Cannot read or write JSON")]
+    Json(#[source] serde_json::Error),
+    #[error("Cannot read or write CSV")]
+    Csv(#[source] csv::Error),
+    #[error("Cannot parse line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("Cannot exchange sync messages with the peer")]
+    Peer(#[source] std::io::Error),
+    #[error("Invalid sync message from the peer")]
+    SyncMessage(#[source] automerge::sync::ReadMessageError),
+    #[error("Sync message of {0} bytes is too large")]
+    SyncMessageTooLarge(u32),
+    #[error("Cannot apply sync message from the peer")]
+    SyncApply(#[source] automerge::AutomergeError),
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -33,71 +82,324 @@ pub struct TaskList {
     pub tasks: Vec<Task>,
 }
 
-fn encode_document(tasks: &TaskList) -> Result<Vec<u8>, Error> {
-    let mut doc = automerge::AutoCommit::new();
+/// Options controlling how task files are saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveOptions {
+    /// The number of previous versions of a file to keep.  The most recent
+    /// is kept in `<file>.1`, the one before that in `<file>.2`, and so on.
+    pub backups: usize,
+    /// How the document is compressed.
+    pub compression: Compression,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            backups: 3,
+            compression: Compression::None,
+        }
+    }
+}
+
+pub(crate) fn new_document(tasks: &TaskList) -> Result<AutoCommit, Error> {
+    let mut doc = AutoCommit::new();
     let tasks: SerializableTaskList = tasks.clone().into();
     autosurgeon::reconcile(&mut doc, tasks).map_err(Error::Reconcile)?;
-    Ok(doc.save())
+    Ok(doc)
 }
 
-fn decode_document(binary: &[u8]) -> Result<TaskList, Error> {
-    let doc = Automerge::load(binary).map_err(Error::AutomergeLoad)?;
-    let tasks: SerializableTaskList = autosurgeon::hydrate(&doc).map_err(Error::Hydrate)?;
+pub(crate) fn hydrate_tasks(doc: &AutoCommit) -> Result<TaskList, Error> {
+    let tasks: SerializableTaskList = autosurgeon::hydrate(doc).map_err(Error::Hydrate)?;
     let tasks: TaskList = tasks.into();
     Ok(tasks)
 }
 
-const AUTOMERGE_CHUNK: [u8; 4] = [b'A', b'M', b'R', b'G'];
-const END_CHUNK: [u8; 4] = [b'S', b'E', b'N', b'D'];
+// Return the lists in the document, the default list included.
+pub(crate) fn hydrate_lists(doc: &AutoCommit) -> Result<Vec<NamedList>, Error> {
+    let list_map = autosurgeon::hydrate_prop(doc, ROOT, "list_map").map_err(Error::Hydrate)?;
+    let list_order = autosurgeon::hydrate_prop(doc, ROOT, "list_order").map_err(Error::Hydrate)?;
+    Ok(with_default(stored_lists(&list_map, &list_order)))
+}
+
+pub(crate) const AUTOMERGE_CHUNK: [u8; 4] = [b'A', b'M', b'R', b'G'];
+pub(crate) const END_CHUNK: [u8; 4] = [b'S', b'E', b'N', b'D'];
 
-fn write_document<W: Write>(tasks: &TaskList, writer: &mut W) -> Result<(), Error> {
+// Only the document is compressed and encrypted; the other chunks hold nothing
+// worth hiding, and the `SHDR` chunk must stay readable to tell a reader what
+// it is looking at.
+fn write_document<W: Write>(
+    doc: &mut AutoCommit,
+    writer: &mut W,
+    compression: Compression,
+    encryption: Option<&mut Encryption>,
+) -> Result<(), Error> {
     write_header(writer).map_err(Error::ContainerWrite)?;
-    let chunk = Chunk::new(AUTOMERGE_CHUNK, encode_document(tasks)?);
+    let chunk = FileHeader::current().to_chunk();
     write_chunk(&chunk, writer).map_err(Error::ContainerWrite)?;
+    let chunk = Chunk::new(AUTOMERGE_CHUNK, doc.save());
+    write_transformed_chunk(&chunk, writer, compression, encryption)
+        .map_err(Error::ContainerWrite)?;
     let chunk = Chunk::new(END_CHUNK, vec![]);
     write_chunk(&chunk, writer).map_err(Error::ContainerWrite)?;
     Ok(())
 }
 
-fn read_document<R: Read>(reader: &mut R) -> Result<TaskList, Error> {
+// Report problems with the passphrase as such, rather than as a container
+// error, so that callers can ask for another one.
+pub(crate) fn read_error(error: container::Error) -> Error {
+    match error {
+        container::Error::PassphraseRequired => Error::PassphraseRequired,
+        container::Error::Decrypt => Error::WrongPassphrase,
+        error => Error::ContainerRead(error),
+    }
+}
+
+// What has been read from a file so far, one chunk at a time.
+#[derive(Default)]
+pub(crate) struct Contents {
+    pub(crate) header: Option<FileHeader>,
+    pub(crate) document: Option<AutoCommit>,
+    chunks: usize,
+}
+
+impl Contents {
+    // Take in the next chunk of the file, returning whether it was the last.
+    pub(crate) fn add(&mut self, chunk: Chunk) -> Result<bool, Error> {
+        let first = self.chunks == 0;
+        self.chunks += 1;
+        match chunk.chunk_type {
+            // Files written before the `SHDR` chunk existed start with the
+            // document.
+            HEADER_CHUNK if first => {
+                let header = FileHeader::from_chunk(&chunk).map_err(Error::ContainerRead)?;
+                self.header = Some(header);
+            }
+            AUTOMERGE_CHUNK if self.document.is_none() => {
+                let document = AutoCommit::load(&chunk.data).map_err(Error::AutomergeLoad)?;
+                self.document = Some(document);
+            }
+            END_CHUNK => return Ok(true),
+            // Chunks added by newer builds that are safe to ignore.
+            _ if !chunk.is_critical() => {}
+            HEADER_CHUNK | AUTOMERGE_CHUNK => {
+                return Err(Error::ContainerRead(container::Error::UnexpectedChunk(
+                    chunk.chunk_type,
+                )));
+            }
+            chunk_type => {
+                return Err(Error::ContainerRead(
+                    container::Error::UnknownCriticalChunk(chunk_type),
+                ));
+            }
+        }
+        Ok(false)
+    }
+
+    pub(crate) fn into_document(self) -> Result<AutoCommit, Error> {
+        self.document
+            .ok_or(Error::ContainerRead(container::Error::MissingChunk(
+                AUTOMERGE_CHUNK,
+            )))
+    }
+}
+
+fn read_document<R: Read>(
+    reader: &mut R,
+    mut encryption: Option<&mut Encryption>,
+) -> Result<AutoCommit, Error> {
     read_header(reader).map_err(Error::ContainerRead)?;
-    let automerge_chunk = read_chunk(reader).map_err(Error::ContainerRead)?;
-    automerge_chunk
-        .expect_type(AUTOMERGE_CHUNK)
-        .map_err(Error::ContainerRead)?;
+    let mut contents = Contents::default();
+    loop {
+        let chunk = read_chunk(reader, encryption.as_deref_mut()).map_err(read_error)?;
+        if contents.add(chunk)? {
+            break;
+        }
+    }
+    contents.into_document()
+}
 
-    let tasks = decode_document(&automerge_chunk.data)?;
+// Return `filename` with `suffix` appended, e.g. `.sift.sift` becomes
+// `.sift.sift.tmp`.
+pub(crate) fn with_suffix(filename: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(filename.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
 
-    let end_chunk = read_chunk(reader).map_err(Error::ContainerRead)?;
-    end_chunk
-        .expect_type(END_CHUNK)
-        .map_err(Error::ContainerRead)?;
+/// Return the path of the `n`th most recent backup of `filename`.
+#[must_use]
+pub fn backup_path(filename: &Path, n: usize) -> PathBuf {
+    with_suffix(filename, &format!(".{}", n))
+}
 
-    Ok(tasks)
+// Shift `<file>.1` to `<file>.2` and so on, dropping the oldest, then make the
+// current contents of `filename` the new `<file>.1`.  `filename` itself is
+// left in place so that there is never a moment without it.
+fn rotate_backups(filename: &Path, backups: usize) -> Result<(), Error> {
+    if backups == 0 || !filename.exists() {
+        return Ok(());
+    }
+    for n in (1..backups).rev() {
+        let from = backup_path(filename, n);
+        if from.exists() {
+            let to = backup_path(filename, n + 1);
+            fs::rename(&from, &to).map_err(|e| Error::Backup(e, to))?;
+        }
+    }
+    let newest = backup_path(filename, 1);
+    if newest.exists() {
+        fs::remove_file(&newest).map_err(|e| Error::Backup(e, newest.clone()))?;
+    }
+    // A hard link is cheap and atomic.  Not every file system supports them.
+    fs::hard_link(filename, &newest)
+        .or_else(|_| fs::copy(filename, &newest).map(|_| ()))
+        .map_err(|e| Error::Backup(e, newest))
 }
 
-pub fn save_tasks(filename: &Path, tasks: &TaskList) -> Result<(), Error> {
-    let mut file = File::create(filename).map_err(|e| Error::CreateFile(e, filename.to_owned()))?;
-    write_document(tasks, &mut file)?;
-    file.sync_all().map_err(Error::Write)?;
+#[cfg(unix)]
+fn sync_directory(filename: &Path) -> Result<(), Error> {
+    let directory = match filename.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory)
+        .and_then(|directory| directory.sync_all())
+        .map_err(|e| Error::SyncDirectory(e, directory.to_owned()))
+}
+
+// Directories can not be opened, and need not be synced, on other platforms.
+#[cfg(not(unix))]
+fn sync_directory(_filename: &Path) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn load_tasks(filename: &Path) -> Result<TaskList, Error> {
+// Replace `filename` with the output of `write` without ever leaving a
+// partially written file in its place.  The output goes to a sibling temporary
+// file that is synced to disk and then renamed over `filename`.  If anything
+// fails before the rename, `filename` is untouched.
+fn write_atomically<F>(filename: &Path, options: &SaveOptions, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut File) -> Result<(), Error>,
+{
+    let temp = with_suffix(filename, ".tmp");
+    let result = File::create(&temp)
+        .map_err(|e| Error::CreateFile(e, temp.clone()))
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all().map_err(Error::Write)
+        });
+    if let Err(error) = result {
+        // The original error is more useful than any error removing the
+        // temporary file.
+        let _ = fs::remove_file(&temp);
+        return Err(error);
+    }
+    rotate_backups(filename, options.backups)?;
+    fs::rename(&temp, filename).map_err(|e| Error::Replace(e, filename.to_owned()))?;
+    sync_directory(filename)
+}
+
+/// Save an Automerge document, including its full change history, to
+/// `filename`.  The document is encrypted if `encryption` is given.
+pub(crate) fn save_document(
+    filename: &Path,
+    doc: &mut AutoCommit,
+    options: &SaveOptions,
+    encryption: Option<&mut Encryption>,
+) -> Result<(), Error> {
+    write_atomically(filename, options, |file| {
+        write_document(doc, file, options.compression, encryption)
+    })
+}
+
+/// Load an Automerge document, including its full change history, from
+/// `filename`.  `encryption` is needed only if the file is encrypted.
+pub(crate) fn load_document(
+    filename: &Path,
+    encryption: Option<&mut Encryption>,
+) -> Result<AutoCommit, Error> {
     let mut file = File::open(filename).map_err(|e| Error::OpenFile(e, filename.to_owned()))?;
     // TODO: the file name is not reported for errors returned by read_document.
     // It would probably be better for the container module to return only
     // std::io::Error, and wrap all std::io::Error in more general Read and
     // Write errors in this module.
-    read_document(&mut file)
+    read_document(&mut file, encryption)
+}
+
+/// Save `tasks` to `filename` as a brand new document.
+///
+/// The saved document has no history in common with any other document, so
+/// it can not be meaningfully merged with one.  Prefer saving through a
+/// [`crate::Store`], which preserves history.
+pub fn save_tasks(filename: &Path, tasks: &TaskList) -> Result<(), Error> {
+    let mut doc = new_document(tasks)?;
+    save_document(filename, &mut doc, &SaveOptions::default(), None)
+}
+
+pub fn load_tasks(filename: &Path) -> Result<TaskList, Error> {
+    let doc = load_document(filename, None)?;
+    hydrate_tasks(&doc)
+}
+
+/// Merge the documents saved in `a` and `b`, typically two copies of the
+/// same file that were edited independently, and save the result to `out`.
+///
+/// `out` may name one of the inputs.  Encrypted copies are read with
+/// `passphrase`, and if it is given the result is encrypted with it too.
+pub fn merge_files(
+    a: &Path,
+    b: &Path,
+    out: &Path,
+    passphrase: Option<Passphrase>,
+) -> Result<(), Error> {
+    let mut encryption = passphrase.map(Encryption::new);
+    let mut doc = load_document(a, encryption.as_mut())?;
+    let mut other = load_document(b, encryption.as_mut())?;
+    doc.merge(&mut other).map_err(Error::AutomergeMerge)?;
+    // Refuse to write a merged document that can not be read back.
+    hydrate_tasks(&doc)?;
+    save_document(out, &mut doc, &SaveOptions::default(), encryption.as_mut())
+}
+
+/// Replace `filename` with its `n`th most recent backup.
+///
+/// The backup is checked to be readable first.  The replaced file becomes the
+/// newest backup, so a restore can itself be undone.  An encrypted backup is
+/// read with `passphrase`, and stays encrypted with it once restored.
+pub fn restore_backup(
+    filename: &Path,
+    n: usize,
+    passphrase: Option<Passphrase>,
+) -> Result<(), Error> {
+    let backup = backup_path(filename, n);
+    if !backup.exists() {
+        return Err(Error::MissingBackup(backup));
+    }
+    let mut encryption = passphrase.map(Encryption::new);
+    let mut doc = load_document(&backup, encryption.as_mut())?;
+    hydrate_tasks(&doc)?;
+    save_document(
+        filename,
+        &mut doc,
+        &SaveOptions::default(),
+        encryption.as_mut(),
+    )
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::path::Path;
+
     use automerge_test::{assert_doc, list, map};
 
-    use crate::document::{Task, TaskList};
+    use crate::document::{
+        backup_path, hydrate_tasks, load_tasks, merge_files, restore_backup, save_tasks,
+        write_atomically, Error, SaveOptions, Task, TaskList,
+    };
     use crate::serialization::SerializableTaskList;
+    use crate::transform::{Compression, Encryption, Passphrase};
+    use crate::{container, MemoryStore, Priority, Store, TaskId};
 
     #[test]
     fn test() {
@@ -105,14 +407,16 @@ mod tests {
             chrono::NaiveDate::from_ymd_opt(year, month, day).expect("date must parse")
         };
 
+        let mut first = Task::new(
+            Task::new_id(),
+            "first title".to_string(),
+            None,
+            Some(new_date(2022, 1, 1)),
+            None,
+        );
+        first.set_priority(Some(Priority::High));
         let tasks = vec![
-            Task::new(
-                Task::new_id(),
-                "first title".to_string(),
-                None,
-                Some(new_date(2022, 1, 1)),
-                None,
-            ),
+            first,
             Task::new(
                 Task::new_id(),
                 "second title".to_string(),
@@ -142,6 +446,7 @@ mod tests {
                             map!{
                                 "title" => {"first title"},
                                 "due_date" => {"2022-01-01"},
+                                "priority" => {"high"},
                             }
                         },
                         tasks[1].id() => {
@@ -170,4 +475,394 @@ mod tests {
         let todo_list2: TaskList = todo_list2.into();
         assert_eq!(task_list, todo_list2);
     }
+
+    // Save a store holding `titles` and load two independent copies of it, as
+    // if the file had been synced to two machines.
+    fn diverge(dir: &Path, titles: &[&str]) -> (MemoryStore, MemoryStore, Vec<TaskId>) {
+        let mut store = MemoryStore::new();
+        let mut ids = Vec::new();
+        for title in titles {
+            let task = Task::new(Task::new_id(), (*title).to_string(), None, None, None);
+            let previous = ids.last().copied();
+            store
+                .with_transaction(|txn| txn.insert_task(None, previous.as_ref(), &task))
+                .unwrap();
+            ids.push(task.id());
+        }
+        let base = dir.join("base.sift");
+        store.save(&base).unwrap();
+        drop(store);
+        // Each side needs its own file, since a loaded store locks it.
+        let copy = dir.join("copy.sift");
+        fs::copy(&base, &copy).unwrap();
+        let a = MemoryStore::load(&base).unwrap();
+        let b = MemoryStore::load(&copy).unwrap();
+        (a, b, ids)
+    }
+
+    fn merge(dir: &Path, mut a: MemoryStore, mut b: MemoryStore) -> Vec<String> {
+        let (a_path, b_path, out) = (dir.join("a.sift"), dir.join("b.sift"), dir.join("out.sift"));
+        a.save(&a_path).unwrap();
+        b.save(&b_path).unwrap();
+        merge_files(&a_path, &b_path, &out, None).unwrap();
+        load_tasks(&out)
+            .unwrap()
+            .tasks
+            .iter()
+            .map(|task| task.title().to_string())
+            .collect()
+    }
+
+    fn set_title(store: &mut MemoryStore, id: &TaskId, title: &str) {
+        store
+            .with_transaction(|txn| {
+                let mut task = txn.get_task(id)?;
+                task.set_title(title.to_string());
+                txn.put_task(&task)
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn merge_concurrent_title_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut a, mut b, ids) = diverge(dir.path(), &["one", "two"]);
+        set_title(&mut a, &ids[0], "one from a");
+        set_title(&mut b, &ids[1], "two from b");
+        assert_eq!(merge(dir.path(), a, b), ["one from a", "two from b"]);
+    }
+
+    #[test]
+    fn merge_concurrent_edits_of_one_title() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut a, mut b, ids) = diverge(dir.path(), &["one"]);
+        set_title(&mut a, &ids[0], "from a");
+        set_title(&mut b, &ids[0], "from b");
+        let titles = merge(dir.path(), a, b);
+        assert!(
+            titles == ["from a"] || titles == ["from b"],
+            "unexpected titles {:?}",
+            titles
+        );
+    }
+
+    #[test]
+    fn merge_concurrent_moves() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut a, mut b, ids) = diverge(dir.path(), &["one", "two", "three"]);
+        // a moves "three" to the top, b moves "one" to the bottom.
+        a.with_transaction(|txn| txn.move_task(None, None, &ids[2]))
+            .unwrap();
+        b.with_transaction(|txn| txn.move_task(None, Some(&ids[2]), &ids[0]))
+            .unwrap();
+        assert_eq!(merge(dir.path(), a, b), ["three", "two", "one"]);
+    }
+
+    #[test]
+    fn merge_concurrent_moves_of_one_task() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut a, mut b, ids) = diverge(dir.path(), &["one", "two", "three"]);
+        a.with_transaction(|txn| txn.move_task(None, None, &ids[2]))
+            .unwrap();
+        b.with_transaction(|txn| txn.move_task(None, Some(&ids[0]), &ids[2]))
+            .unwrap();
+        let mut titles = merge(dir.path(), a, b);
+        // Either move may win, but the task must appear exactly once.
+        titles.sort();
+        assert_eq!(titles, ["one", "three", "two"]);
+    }
+
+    #[test]
+    fn merge_concurrent_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut a, mut b, ids) = diverge(dir.path(), &["one", "two", "three"]);
+        a.with_transaction(|txn| txn.delete_task(&ids[0])).unwrap();
+        b.with_transaction(|txn| txn.delete_task(&ids[2])).unwrap();
+        assert_eq!(merge(dir.path(), a, b), ["two"]);
+    }
+
+    #[test]
+    fn merge_delete_with_concurrent_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut a, mut b, ids) = diverge(dir.path(), &["one", "two"]);
+        a.with_transaction(|txn| txn.delete_task(&ids[1])).unwrap();
+        set_title(&mut b, &ids[1], "two from b");
+        b.with_transaction(|txn| txn.move_task(None, None, &ids[1]))
+            .unwrap();
+        assert_eq!(merge(dir.path(), a, b), ["one"]);
+    }
+
+    fn set_notes(store: &mut MemoryStore, id: &TaskId, notes: &str) {
+        store
+            .with_transaction(|txn| {
+                let mut task = txn.get_task(id)?;
+                task.set_notes(notes.to_string());
+                txn.put_task(&task)
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn merge_concurrent_notes_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut task = Task::new(Task::new_id(), "shopping".to_string(), None, None, None);
+        task.set_notes("milk\neggs\n".to_string());
+        let mut store = MemoryStore::new();
+        store
+            .with_transaction(|txn| txn.insert_task(None, None, &task))
+            .unwrap();
+        let (a_path, b_path) = (dir.path().join("a.sift"), dir.path().join("b.sift"));
+        store.save(&a_path).unwrap();
+        drop(store);
+        fs::copy(&a_path, &b_path).unwrap();
+        let mut a = MemoryStore::load(&a_path).unwrap();
+        let mut b = MemoryStore::load(&b_path).unwrap();
+
+        set_notes(&mut a, &task.id(), "milk\neggs\nbread\n");
+        set_notes(&mut b, &task.id(), "oat milk\neggs\n");
+        a.save(&a_path).unwrap();
+        b.save(&b_path).unwrap();
+        let out = dir.path().join("out.sift");
+        merge_files(&a_path, &b_path, &out, None).unwrap();
+        let tasks = load_tasks(&out).unwrap().tasks;
+        assert_eq!(tasks[0].notes(), "oat milk\neggs\nbread\n");
+    }
+
+    #[test]
+    fn merge_concurrent_tag_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut task = Task::new(Task::new_id(), String::new(), None, None, None);
+        task.set_title_with_tags("shopping #errands");
+        let mut store = MemoryStore::new();
+        store
+            .with_transaction(|txn| txn.insert_task(None, None, &task))
+            .unwrap();
+        let (a_path, b_path) = (dir.path().join("a.sift"), dir.path().join("b.sift"));
+        store.save(&a_path).unwrap();
+        drop(store);
+        fs::copy(&a_path, &b_path).unwrap();
+        let mut a = MemoryStore::load(&a_path).unwrap();
+        let mut b = MemoryStore::load(&b_path).unwrap();
+
+        let set_title_with_tags = |store: &mut MemoryStore, text: &str| {
+            store
+                .with_transaction(|txn| {
+                    let mut task = txn.get_task(&task.id())?;
+                    task.set_title_with_tags(text);
+                    txn.put_task(&task)
+                })
+                .unwrap();
+        };
+        set_title_with_tags(&mut a, "shopping #errands #home");
+        set_title_with_tags(&mut b, "shopping");
+        a.save(&a_path).unwrap();
+        b.save(&b_path).unwrap();
+        let out = dir.path().join("out.sift");
+        merge_files(&a_path, &b_path, &out, None).unwrap();
+        let tasks = load_tasks(&out).unwrap().tasks;
+        assert_eq!(tasks[0].title_with_tags(), "shopping #home");
+    }
+
+    fn titled(title: &str) -> TaskList {
+        TaskList {
+            tasks: vec![Task::new(
+                Task::new_id(),
+                title.to_string(),
+                None,
+                None,
+                None,
+            )],
+        }
+    }
+
+    fn title_in(path: &Path) -> String {
+        load_tasks(path).unwrap().tasks[0].title().to_string()
+    }
+
+    // Golden files, described in `testdata/README.md`, pin the container
+    // layouts older and newer builds write.
+    fn read_golden(bytes: &[u8]) -> Result<automerge::AutoCommit, Error> {
+        super::read_document(&mut &bytes[..], None)
+    }
+
+    #[test]
+    fn reads_files_without_a_header_chunk() {
+        read_golden(include_bytes!("../testdata/v0.sift")).unwrap();
+    }
+
+    #[test]
+    fn reads_files_with_a_header_chunk() {
+        read_golden(include_bytes!("../testdata/v1.sift")).unwrap();
+    }
+
+    #[test]
+    fn skips_unknown_ancillary_chunks() {
+        read_golden(include_bytes!("../testdata/v1-ancillary.sift")).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_critical_chunks() {
+        let error =
+            read_golden(include_bytes!("../testdata/v1-unknown-critical.sift")).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::ContainerRead(container::Error::UnknownCriticalChunk(chunk_type))
+                    if &chunk_type == b"NEWC"
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let error = read_golden(include_bytes!("../testdata/v2.sift")).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::ContainerRead(container::Error::UnsupportedVersion(2, 1))
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn writes_header_chunk_first() {
+        let mut bytes = Vec::new();
+        super::write_document(
+            &mut super::new_document(&TaskList::default()).unwrap(),
+            &mut bytes,
+            Compression::None,
+            None,
+        )
+        .unwrap();
+        let golden = include_bytes!("../testdata/v1.sift");
+        // The magic bytes, then the `SHDR` chunk's length, type and version,
+        // leaving out the writer's name and version.
+        assert_eq!(bytes[..9], golden[..9]);
+        assert_eq!(&bytes[13..17], b"SHDR");
+        assert_eq!(bytes[17..21], golden[17..21]);
+        let tasks = hydrate_tasks(&read_golden(&bytes).unwrap()).unwrap();
+        assert_eq!(tasks, TaskList::default());
+    }
+
+    #[test]
+    fn compressed_and_encrypted_documents_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let title = "Call Jane Customer about the renewal";
+        let options = SaveOptions {
+            compression: Compression::Zstd,
+            ..SaveOptions::default()
+        };
+        let mut doc = super::new_document(&titled(title)).unwrap();
+        let mut encryption = Encryption::cheap("correct horse");
+        super::save_document(&path, &mut doc, &options, Some(&mut encryption)).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert!(!bytes
+            .windows(title.len())
+            .any(|window| window == title.as_bytes()));
+        assert!(matches!(load_tasks(&path), Err(Error::PassphraseRequired)));
+        let mut wrong = Encryption::cheap("battery staple");
+        assert!(matches!(
+            super::load_document(&path, Some(&mut wrong)),
+            Err(Error::WrongPassphrase)
+        ));
+
+        let doc =
+            super::load_document(&path, Some(&mut Encryption::cheap("correct horse"))).unwrap();
+        assert_eq!(hydrate_tasks(&doc).unwrap().tasks[0].title(), title);
+    }
+
+    #[test]
+    fn failed_write_leaves_original_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        save_tasks(&path, &titled("original")).unwrap();
+
+        let result = write_atomically(&path, &SaveOptions::default(), |file| {
+            use std::io::Write;
+            file.write_all(b"\x89SIFT partial").map_err(Error::Write)?;
+            Err(Error::Write(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "simulated full disk",
+            )))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(title_in(&path), "original");
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["tasks.sift"]);
+    }
+
+    #[test]
+    fn saves_rotate_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let options = SaveOptions {
+            backups: 2,
+            ..SaveOptions::default()
+        };
+        for title in ["first", "second", "third", "fourth"] {
+            write_atomically(&path, &options, |file| {
+                super::write_document(
+                    &mut super::new_document(&titled(title))?,
+                    file,
+                    Compression::None,
+                    None,
+                )
+            })
+            .unwrap();
+        }
+
+        assert_eq!(title_in(&path), "fourth");
+        assert_eq!(title_in(&backup_path(&path, 1)), "third");
+        assert_eq!(title_in(&backup_path(&path, 2)), "second");
+        assert!(!backup_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn restore_a_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        save_tasks(&path, &titled("old")).unwrap();
+        save_tasks(&path, &titled("new")).unwrap();
+
+        restore_backup(&path, 1, None).unwrap();
+        assert_eq!(title_in(&path), "old");
+        assert_eq!(title_in(&backup_path(&path, 1)), "new");
+
+        assert!(matches!(
+            restore_backup(&path, 9, None),
+            Err(Error::MissingBackup(_))
+        ));
+    }
+
+    #[test]
+    fn restore_an_encrypted_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let mut encryption = Encryption::cheap("correct horse");
+        for title in ["old", "new"] {
+            let mut doc = super::new_document(&titled(title)).unwrap();
+            let options = SaveOptions::default();
+            super::save_document(&path, &mut doc, &options, Some(&mut encryption)).unwrap();
+        }
+
+        assert!(matches!(
+            restore_backup(&path, 1, None),
+            Err(Error::PassphraseRequired)
+        ));
+        let passphrase = Passphrase::from("correct horse".to_string());
+        restore_backup(&path, 1, Some(passphrase)).unwrap();
+        assert!(matches!(load_tasks(&path), Err(Error::PassphraseRequired)));
+        let doc = super::load_document(&path, Some(&mut encryption)).unwrap();
+        assert_eq!(hydrate_tasks(&doc).unwrap().tasks[0].title(), "old");
+    }
 }
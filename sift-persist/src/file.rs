@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use automerge::AutoCommit;
+
+use crate::document::{load_document, save_document, Error, SaveOptions};
+use crate::lock::FileLock;
+use crate::transform::{Encryption, Passphrase};
+
+/// How a store may use the file it is opened from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Access {
+    /// The file is locked against other writers and may be saved.
+    #[default]
+    ReadWrite,
+    /// The file is not locked and can not be saved.  Useful for looking at a
+    /// file another process has open.
+    ReadOnly,
+}
+
+// The file backing a store: where it is saved, how, and the lock held on it.
+#[derive(Debug, Default)]
+pub(crate) struct StoreFile {
+    path: Option<PathBuf>,
+    access: Access,
+    lock: Option<FileLock>,
+    save_options: SaveOptions,
+    encryption: Option<Encryption>,
+}
+
+impl StoreFile {
+    /// Open the file at `path`.  `passphrase` is needed only if the file is
+    /// encrypted, and if given, the file is encrypted when saved.
+    pub(crate) fn open(
+        path: &Path,
+        access: Access,
+        passphrase: Option<Passphrase>,
+    ) -> Result<(StoreFile, AutoCommit), Error> {
+        let lock = match access {
+            Access::ReadWrite => Some(FileLock::acquire(path)?),
+            Access::ReadOnly => None,
+        };
+        let mut encryption = passphrase.map(Encryption::new);
+        let document = load_document(path, encryption.as_mut())?;
+        let file = StoreFile {
+            path: Some(path.to_owned()),
+            access,
+            lock,
+            save_options: SaveOptions::default(),
+            encryption,
+        };
+        Ok((file, document))
+    }
+
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.access == Access::ReadOnly
+    }
+
+    pub(crate) fn set_save_options(&mut self, save_options: SaveOptions) {
+        self.save_options = save_options;
+    }
+
+    pub(crate) fn set_passphrase(&mut self, passphrase: Option<Passphrase>) {
+        self.encryption = passphrase.map(Encryption::new);
+    }
+
+    pub(crate) fn save(&mut self, path: &Path, document: &mut AutoCommit) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Err(Error::ReadOnly(path.to_owned()));
+        }
+        // A store takes the lock on a file when it is first saved there,
+        // releasing any lock on the file it was saved to before.
+        if self.lock.is_none() || self.path.as_deref() != Some(path) {
+            self.lock = Some(FileLock::acquire(path)?);
+        }
+        save_document(path, document, &self.save_options, self.encryption.as_mut())?;
+        self.path = Some(path.to_owned());
+        Ok(())
+    }
+
+    /// Merge changes made to the file by other processes into `document`,
+    /// returning whether `document` changed.  A store without a file, or
+    /// whose file was removed, has nothing to reload.
+    pub(crate) fn reload(&mut self, document: &mut AutoCommit) -> Result<bool, Error> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        if !path.exists() {
+            return Ok(false);
+        }
+        let mut on_disk = load_document(path, self.encryption.as_mut())?;
+        let before = document.get_heads();
+        document
+            .merge(&mut on_disk)
+            .map_err(Error::AutomergeMerge)?;
+        Ok(document.get_heads() != before)
+    }
+
+    pub(crate) fn flush(&mut self, document: &mut AutoCommit) -> anyhow::Result<()> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| anyhow!("the store has no file to flush to"))?;
+        self.save(&path, document)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! Walking a task file chunk by chunk, for diagnosing files that fail to load.
+//!
+//! Unlike loading, inspection carries on past a chunk whose CRC does not
+//! match, so that every chunk in a damaged file can be listed, and it records
+//! where in the file each problem was found.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use automerge::AutoCommit;
+
+use crate::container::{self, read_header, StoredChunk};
+use crate::document::{read_error, Contents, Error};
+use crate::transform::{self, Encryption, Passphrase};
+
+/// A chunk as found in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// Where the chunk starts, in bytes from the start of the file.
+    pub offset: u64,
+    /// The chunk type as stored, e.g. `AMrG` for a transformed `AMRG`.
+    pub chunk_type: [u8; 4],
+    /// The length of the payload as stored.
+    pub length: usize,
+    /// The CRC stored after the chunk.
+    pub crc: u32,
+    /// Whether the stored CRC matches the chunk.
+    pub crc_ok: bool,
+}
+
+impl ChunkInfo {
+    /// Whether a reader must understand the chunk to read the file.
+    #[must_use]
+    pub fn is_critical(&self) -> bool {
+        self.chunk_type[0].is_ascii_uppercase()
+    }
+
+    /// Whether the payload is compressed or encrypted.
+    #[must_use]
+    pub fn is_transformed(&self) -> bool {
+        transform::is_transformed(self.chunk_type)
+    }
+}
+
+/// A problem found in a file, and where.
+#[derive(Debug)]
+pub struct Failure {
+    /// The offset, in bytes from the start of the file, of the header or
+    /// chunk that could not be read.
+    pub offset: u64,
+    pub error: Error,
+}
+
+/// What was found in a file.
+#[derive(Debug, Default)]
+pub struct Inspection {
+    /// The container format version: from the `SHDR` chunk, or 0 for files
+    /// written before it existed.  `None` if no chunk could be read.
+    pub format_version: Option<u32>,
+    /// The program that wrote the file, from the `SHDR` chunk.
+    pub writer: Option<String>,
+    pub chunks: Vec<ChunkInfo>,
+    pub document: Option<AutoCommit>,
+    /// The first problem found, if any.
+    pub failure: Option<Failure>,
+}
+
+impl Inspection {
+    /// Whether the file was read without problems.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    fn fail(&mut self, offset: u64, error: Error) {
+        if self.failure.is_none() {
+            self.failure = Some(Failure { offset, error });
+        }
+    }
+}
+
+// Counts the bytes read, to report where problems are.
+struct Counting<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// Inspect the file at `path`.  `passphrase` is needed to look inside an
+/// encrypted file.
+///
+/// # Errors
+///
+/// Returns an error only if the file can not be opened; problems with its
+/// contents are reported in the [`Inspection`].
+pub fn inspect_file(path: &Path, passphrase: Option<Passphrase>) -> Result<Inspection, Error> {
+    let file = File::open(path).map_err(|e| Error::OpenFile(e, path.to_owned()))?;
+    Ok(inspect(BufReader::new(file), passphrase))
+}
+
+/// Inspect a file read from `reader`.
+pub fn inspect<R: Read>(reader: R, passphrase: Option<Passphrase>) -> Inspection {
+    let mut reader = Counting {
+        inner: reader,
+        offset: 0,
+    };
+    let mut encryption = passphrase.map(Encryption::new);
+    let mut inspection = Inspection::default();
+    if let Err(error) = read_header(&mut reader) {
+        inspection.fail(0, Error::ContainerRead(error));
+        return inspection;
+    }
+
+    let mut contents = Contents::default();
+    loop {
+        let offset = reader.offset;
+        let stored = match StoredChunk::read(&mut reader) {
+            Ok(stored) => stored,
+            // Without a readable length, there is no finding the next chunk.
+            Err(error) => {
+                inspection.fail(offset, Error::ContainerRead(error));
+                break;
+            }
+        };
+        let info = ChunkInfo {
+            offset,
+            chunk_type: stored.chunk.chunk_type,
+            length: stored.chunk.data.len(),
+            crc: stored.crc,
+            crc_ok: stored.crc_matches(),
+        };
+        inspection.chunks.push(info);
+        if !stored.crc_matches() {
+            inspection.fail(
+                offset,
+                Error::ContainerRead(container::Error::ReadCrcMismatch),
+            );
+            continue;
+        }
+        let done = stored
+            .chunk
+            .decode(encryption.as_mut())
+            .map_err(read_error)
+            .and_then(|chunk| contents.add(chunk));
+        match done {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(error) => inspection.fail(offset, error),
+        }
+    }
+
+    inspection.format_version = match (&contents.header, inspection.chunks.is_empty()) {
+        (Some(header), _) => Some(header.version),
+        (None, false) => Some(0),
+        (None, true) => None,
+    };
+    inspection.writer = contents.header.as_ref().map(|header| header.writer.clone());
+    let end = reader.offset;
+    match contents.into_document() {
+        Ok(document) => inspection.document = Some(document),
+        Err(error) => inspection.fail(end, error),
+    }
+    inspection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inspect_bytes(bytes: &[u8]) -> Inspection {
+        inspect(bytes, None)
+    }
+
+    fn types(inspection: &Inspection) -> Vec<[u8; 4]> {
+        inspection
+            .chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type)
+            .collect()
+    }
+
+    #[test]
+    fn lists_chunks_of_a_good_file() {
+        let inspection = inspect_bytes(include_bytes!("../testdata/v1-ancillary.sift"));
+        assert!(inspection.is_ok(), "{:?}", inspection.failure);
+        assert_eq!(types(&inspection), [*b"SHDR", *b"AMRG", *b"teXt", *b"SEND"]);
+        assert_eq!(inspection.chunks[1].offset, 9 + 12 + 22);
+        assert_eq!(inspection.format_version, Some(1));
+        assert_eq!(inspection.writer.as_deref(), Some("sift-persist 0.1.0"));
+        assert!(inspection.document.is_some());
+    }
+
+    #[test]
+    fn reports_old_files_as_version_0() {
+        let inspection = inspect_bytes(include_bytes!("../testdata/v0.sift"));
+        assert!(inspection.is_ok());
+        assert_eq!(inspection.format_version, Some(0));
+        assert_eq!(inspection.writer, None);
+    }
+
+    #[test]
+    fn carries_on_past_a_crc_mismatch() {
+        let mut bytes = include_bytes!("../testdata/v1-ancillary.sift").to_vec();
+        // Corrupt the payload of the `teXt` chunk.
+        let offset = 9 + 12 + 22 + 12;
+        bytes[offset + 8] ^= 0xff;
+
+        let inspection = inspect_bytes(&bytes);
+        assert_eq!(types(&inspection).len(), 4);
+        assert!(!inspection.chunks[2].crc_ok);
+        let failure = inspection.failure.unwrap();
+        assert_eq!(failure.offset, offset as u64);
+        assert!(matches!(
+            failure.error,
+            Error::ContainerRead(container::Error::ReadCrcMismatch)
+        ));
+    }
+
+    #[test]
+    fn reports_where_a_truncated_file_ends() {
+        let bytes = include_bytes!("../testdata/v1.sift");
+        let truncated = &bytes[..bytes.len() - 6];
+
+        let inspection = inspect_bytes(truncated);
+        let failure = inspection.failure.unwrap();
+        // The `SEND` chunk is cut short.
+        assert_eq!(failure.offset, (bytes.len() - 12) as u64);
+        assert!(inspection.document.is_some());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_sift_files() {
+        let inspection = inspect_bytes(b"not a sift file");
+        assert_eq!(inspection.failure.unwrap().offset, 0);
+        assert_eq!(inspection.format_version, None);
+    }
+}
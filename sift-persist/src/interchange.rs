@@ -0,0 +1,317 @@
+//! Converting task lists to and from formats other programs can read: JSON,
+//! CSV, Markdown checklists, todo.txt and iCalendar.
+//!
+//! Exports list tasks in their stored order, and imports keep the order tasks
+//! are read in.  JSON and CSV carry each task's id, title and dates; a
+//! Markdown checklist carries only titles and whether each task is done.
+//! todo.txt carries titles and dates but not ids, and keeps what Sift has no
+//! field for in the task's extensions.  iCalendar carries ids, titles and
+//! dates, for calendar apps to show.  No format carries notes, tags,
+//! priorities, recurrence or lists.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::document::{Error, TaskList};
+use crate::{Store, Task, TaskId};
+
+mod csv;
+mod ical;
+mod json;
+mod markdown;
+mod todotxt;
+
+/// A format tasks can be exported to and imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Markdown,
+    TodoTxt,
+    ICalendar,
+}
+
+impl Format {
+    /// Guess the format of the file at `path` from its extension.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "md" | "markdown" => Ok(Format::Markdown),
+            "txt" | "todotxt" => Ok(Format::TodoTxt),
+            "ics" | "ical" => Ok(Format::ICalendar),
+            _ => Err(Error::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+// A task as exported to JSON and CSV.  The field names are part of both
+// formats, so they must not change.  Only the title is required on import;
+// a task without an id is given a new one.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    id: Option<Uuid>,
+    title: String,
+    snoozed: Option<NaiveDate>,
+    due: Option<NaiveDate>,
+    completed: Option<DateTime<Utc>>,
+}
+
+impl From<&Task> for Record {
+    fn from(task: &Task) -> Self {
+        Record {
+            id: Some(task.id().into()),
+            title: task.title().to_string(),
+            snoozed: task.snoozed(),
+            due: task.due(),
+            completed: task.completed(),
+        }
+    }
+}
+
+impl From<Record> for Task {
+    fn from(record: Record) -> Self {
+        Task::new(
+            record.id.map_or_else(Task::new_id, TaskId::from),
+            record.title,
+            record.snoozed,
+            record.due,
+            record.completed,
+        )
+    }
+}
+
+/// Write `tasks` to `writer` in `format`.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub fn export_tasks<W: Write>(tasks: &TaskList, format: Format, writer: W) -> Result<(), Error> {
+    match format {
+        Format::Json => json::export(tasks, writer),
+        Format::Csv => csv::export(tasks, writer),
+        Format::Markdown => markdown::export(tasks, writer),
+        Format::TodoTxt => todotxt::export(tasks, writer),
+        Format::ICalendar => ical::export(tasks, writer),
+    }
+}
+
+/// Read tasks in `format` from `reader`.
+///
+/// # Errors
+///
+/// Returns an error if reading fails or the input is not valid `format`.
+pub fn import_tasks<R: Read>(reader: R, format: Format) -> Result<TaskList, Error> {
+    match format {
+        Format::Json => json::import(reader),
+        Format::Csv => csv::import(reader),
+        Format::Markdown => markdown::import(reader),
+        Format::TodoTxt => todotxt::import(reader),
+        Format::ICalendar => ical::import(reader),
+    }
+}
+
+/// Add `tasks` to the end of `store`, in order, as one undoable change.
+///
+/// A task with the id of a task already in the store replaces that task where
+/// it is instead, so importing an edited export updates tasks rather than
+/// duplicating them.  The replaced task keeps its notes, tags, priority,
+/// recurrence and list, which exports do not carry, and its extensions unless
+/// the new one has its own.
+///
+/// # Errors
+///
+/// Returns any error from the store.
+pub fn add_tasks<S: Store>(store: &mut S, tasks: &TaskList) -> anyhow::Result<()> {
+    let mut previous = store.list_tasks()?.last().map(Task::id);
+    store.with_transaction(|txn| {
+        for task in &tasks.tasks {
+            if let Ok(existing) = txn.get_task(&task.id()) {
+                let mut task = task.clone();
+                task.set_notes(existing.notes().to_string());
+                task.set_tags(existing.tags().clone());
+                task.set_priority(existing.priority());
+                task.set_recurrence(existing.recurrence().cloned());
+                task.set_list(existing.list());
+                task.keep_extensions_of(&existing);
+                txn.put_task(&task)?;
+            } else {
+                txn.insert_task(None, previous.as_ref(), task)?;
+                previous = Some(task.id());
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frequency, ListId, MemoryStore, Priority, Recurrence};
+
+    fn sample() -> TaskList {
+        let date = |s: &str| s.parse::<NaiveDate>().ok();
+        TaskList {
+            tasks: vec![
+                Task::new(
+                    Task::new_id(),
+                    "first, with a comma".to_string(),
+                    None,
+                    date("2024-07-01"),
+                    None,
+                ),
+                Task::new(
+                    Task::new_id(),
+                    "second \"quoted\"".to_string(),
+                    date("2024-06-30"),
+                    None,
+                    "2024-07-03T13:01:42Z".parse().ok(),
+                ),
+            ],
+        }
+    }
+
+    fn round_trip(tasks: &TaskList, format: Format) -> TaskList {
+        let mut bytes = Vec::new();
+        export_tasks(tasks, format, &mut bytes).unwrap();
+        import_tasks(bytes.as_slice(), format).unwrap()
+    }
+
+    #[test]
+    fn json_and_csv_round_trip() {
+        let tasks = sample();
+        assert_eq!(round_trip(&tasks, Format::Json), tasks);
+        assert_eq!(round_trip(&tasks, Format::Csv), tasks);
+    }
+
+    #[test]
+    fn json_schema_is_stable() {
+        let id = "01910c3b-6cc4-7d9e-8d2c-5f8a2e0d4b11";
+        let task = Task::new(
+            Uuid::parse_str(id).unwrap().into(),
+            "title".to_string(),
+            None,
+            "2024-07-01".parse().ok(),
+            "2024-07-03T13:01:42Z".parse().ok(),
+        );
+        let mut bytes = Vec::new();
+        let tasks = TaskList { tasks: vec![task] };
+        export_tasks(&tasks, Format::Json, &mut bytes).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "tasks": [{
+                    "id": id,
+                    "title": "title",
+                    "snoozed": null,
+                    "due": "2024-07-01",
+                    "completed": "2024-07-03T13:01:42Z",
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn imported_tasks_without_ids_get_new_ones() {
+        let csv = "title,due\nfirst,2024-07-01\nsecond,\n";
+        let tasks = import_tasks(csv.as_bytes(), Format::Csv).unwrap();
+        assert_eq!(tasks.tasks.len(), 2);
+        assert_ne!(tasks.tasks[0].id(), tasks.tasks[1].id());
+        assert_eq!(tasks.tasks[0].due(), "2024-07-01".parse().ok());
+        assert_eq!(tasks.tasks[1].due(), None);
+
+        let json = r#"{"tasks": [{"title": "only a title"}]}"#;
+        let tasks = import_tasks(json.as_bytes(), Format::Json).unwrap();
+        assert_eq!(tasks.tasks[0].title(), "only a title");
+    }
+
+    #[test]
+    fn formats_are_named_by_extension() {
+        assert_eq!(Format::from_path(Path::new("a.csv")), Some(Format::Csv));
+        assert_eq!(Format::from_path(Path::new("a.JSON")), Some(Format::Json));
+        assert_eq!(Format::from_path(Path::new("a.md")), Some(Format::Markdown));
+        assert_eq!(
+            Format::from_path(Path::new("todo.txt")),
+            Some(Format::TodoTxt)
+        );
+        assert_eq!(
+            Format::from_path(Path::new("a.ics")),
+            Some(Format::ICalendar)
+        );
+        assert_eq!(Format::from_path(Path::new("a.xml")), None);
+        assert!(matches!(
+            "xml".parse::<Format>(),
+            Err(Error::UnknownFormat(name)) if name == "xml"
+        ));
+    }
+
+    #[test]
+    fn add_tasks_appends_new_tasks_and_updates_known_ones() {
+        let mut store = MemoryStore::default();
+        let tasks = sample();
+        add_tasks(&mut store, &tasks).unwrap();
+        assert_eq!(store.list_tasks().unwrap(), tasks.tasks);
+
+        let mut edited = tasks.tasks[0].clone();
+        edited.set_title("edited".to_string());
+        let new = Task::new(Task::new_id(), "new".to_string(), None, None, None);
+        let more = TaskList {
+            tasks: vec![new.clone(), edited.clone()],
+        };
+        add_tasks(&mut store, &more).unwrap();
+        assert_eq!(
+            store.list_tasks().unwrap(),
+            [edited, tasks.tasks[1].clone(), new]
+        );
+
+        store.undo().unwrap();
+        assert_eq!(store.list_tasks().unwrap(), tasks.tasks);
+    }
+
+    #[test]
+    fn reimporting_an_export_keeps_what_it_does_not_carry() {
+        let mut task = sample().tasks[0].clone();
+        task.set_notes("call first".to_string());
+        task.set_title_with_tags("first #work");
+        task.set_priority(Some(Priority::High));
+        task.set_recurrence(Some(Recurrence::new(Frequency::Weekly, 1)));
+        task.set_list(ListId::new());
+        let mut store = MemoryStore::default();
+        let tasks = TaskList {
+            tasks: vec![task.clone()],
+        };
+        add_tasks(&mut store, &tasks).unwrap();
+
+        for format in [Format::Json, Format::Csv, Format::ICalendar] {
+            let mut bytes = Vec::new();
+            export_tasks(&tasks, format, &mut bytes).unwrap();
+            let mut imported = import_tasks(bytes.as_slice(), format).unwrap();
+            imported.tasks[0].set_title("edited".to_string());
+            add_tasks(&mut store, &imported).unwrap();
+
+            let stored = store.list_tasks().unwrap();
+            assert_eq!(stored.len(), 1, "{:?}", format);
+            assert_eq!(stored[0].title(), "edited", "{:?}", format);
+            assert_eq!(stored[0].notes(), task.notes(), "{:?}", format);
+            assert_eq!(stored[0].tags(), task.tags(), "{:?}", format);
+            assert_eq!(stored[0].priority(), task.priority(), "{:?}", format);
+            assert_eq!(stored[0].recurrence(), task.recurrence(), "{:?}", format);
+            assert_eq!(stored[0].list(), task.list(), "{:?}", format);
+        }
+    }
+}
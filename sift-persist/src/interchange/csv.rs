@@ -0,0 +1,24 @@
+//! Tasks as CSV: a header row naming the fields, then one row per task.  Empty
+//! cells are missing fields.
+
+use std::io::{Read, Write};
+
+use super::Record;
+use crate::document::{Error, TaskList};
+use crate::Task;
+
+pub(super) fn export<W: Write>(tasks: &TaskList, writer: W) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for task in &tasks.tasks {
+        writer.serialize(Record::from(task)).map_err(Error::Csv)?;
+    }
+    writer.flush().map_err(Error::Write)
+}
+
+pub(super) fn import<R: Read>(reader: R) -> Result<TaskList, Error> {
+    let tasks = csv::Reader::from_reader(reader)
+        .deserialize::<Record>()
+        .map(|record| record.map(Task::from).map_err(Error::Csv))
+        .collect::<Result<_, _>>()?;
+    Ok(TaskList { tasks })
+}
@@ -0,0 +1,335 @@
+//! Tasks as iCalendar (RFC 5545) `VTODO` components, for calendar apps.
+//!
+//! Each task's id is its `UID`, so importing a calendar exported earlier
+//! updates the tasks in it rather than duplicating them.  A `UID` that is not
+//! a UUID, as written by other apps, is turned into one by hashing, which
+//! keeps repeated imports of the same calendar stable.
+//!
+//! `SUMMARY` is the title, `DTSTART` the snooze date, `DUE` the due date and
+//! `COMPLETED` the completion time.  Dates with a time of day keep only the
+//! date, and other properties and components are skipped.
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use crate::document::{Error, TaskList};
+use crate::{Task, TaskId};
+
+// Lines longer than this many bytes are folded onto continuation lines.
+const LINE_LIMIT: usize = 75;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+// Write a content line, folding it at character boundaries.
+fn write_line<W: Write>(writer: &mut W, line: &str) -> Result<(), Error> {
+    let mut rest = line;
+    let mut limit = LINE_LIMIT;
+    loop {
+        let mut end = rest.len().min(limit);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (part, remainder) = rest.split_at(end);
+        writer.write_all(part.as_bytes()).map_err(Error::Write)?;
+        writer.write_all(b"\r\n").map_err(Error::Write)?;
+        if remainder.is_empty() {
+            return Ok(());
+        }
+        writer.write_all(b" ").map_err(Error::Write)?;
+        rest = remainder;
+        // The leading space counts towards the limit.
+        limit = LINE_LIMIT - 1;
+    }
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn format_date_time(date_time: DateTime<Utc>) -> String {
+    date_time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+pub(super) fn export<W: Write>(tasks: &TaskList, mut writer: W) -> Result<(), Error> {
+    let stamp = format_date_time(Utc::now());
+    write_line(&mut writer, "BEGIN:VCALENDAR")?;
+    write_line(&mut writer, "VERSION:2.0")?;
+    write_line(
+        &mut writer,
+        &format!(
+            "PRODID:-//Sift//{} {}//EN",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ),
+    )?;
+    for task in &tasks.tasks {
+        write_line(&mut writer, "BEGIN:VTODO")?;
+        write_line(&mut writer, &format!("UID:{}", task.id()))?;
+        write_line(&mut writer, &format!("DTSTAMP:{}", stamp))?;
+        write_line(&mut writer, &format!("SUMMARY:{}", escape(task.title())))?;
+        if let Some(snoozed) = task.snoozed() {
+            let line = format!("DTSTART;VALUE=DATE:{}", format_date(snoozed));
+            write_line(&mut writer, &line)?;
+        }
+        if let Some(due) = task.due() {
+            write_line(&mut writer, &format!("DUE;VALUE=DATE:{}", format_date(due)))?;
+        }
+        if let Some(completed) = task.completed() {
+            write_line(&mut writer, "STATUS:COMPLETED")?;
+            let line = format!("COMPLETED:{}", format_date_time(completed));
+            write_line(&mut writer, &line)?;
+        } else {
+            write_line(&mut writer, "STATUS:NEEDS-ACTION")?;
+        }
+        write_line(&mut writer, "END:VTODO")?;
+    }
+    write_line(&mut writer, "END:VCALENDAR")
+}
+
+// A content line, unfolded, and where it starts in the input.
+struct Line {
+    number: usize,
+    name: String,
+    value: String,
+}
+
+// Unfold and split the content lines of `reader`.  Parameters are dropped:
+// dates are recognised by their value alone.
+fn read_lines<R: Read>(reader: R) -> Result<Vec<Line>, Error> {
+    let mut unfolded: Vec<(usize, String)> = Vec::new();
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.map_err(Error::Read)?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if let (Some(continuation), Some((_, previous))) =
+            (line.strip_prefix([' ', '\t']), unfolded.last_mut())
+        {
+            previous.push_str(continuation);
+        } else if !line.is_empty() {
+            unfolded.push((index + 1, line.to_string()));
+        }
+    }
+    unfolded
+        .into_iter()
+        .map(|(number, line)| {
+            let (name, value) = line.split_once(':').ok_or_else(|| Error::Parse {
+                line: number,
+                message: "expected `NAME:value`".to_string(),
+            })?;
+            let name = name.split(';').next().unwrap_or_default();
+            Ok(Line {
+                number,
+                name: name.to_ascii_uppercase(),
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_error(line: &Line, what: &str) -> Error {
+    Error::Parse {
+        line: line.number,
+        message: format!("invalid {} `{}`", what, line.value),
+    }
+}
+
+// Parse a `DATE` or `DATE-TIME` value, keeping only the date.
+fn parse_date(line: &Line) -> Result<NaiveDate, Error> {
+    line.value
+        .get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| parse_error(line, "date"))
+}
+
+// Parse a `DATE-TIME` value.  Times without a `Z` are taken to be UTC.
+fn parse_date_time(line: &Line) -> Result<DateTime<Utc>, Error> {
+    let value = line.value.strip_suffix('Z').unwrap_or(&line.value);
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Ok(date_time.and_utc());
+    }
+    Ok(parse_date(line)?.and_time(chrono::NaiveTime::MIN).and_utc())
+}
+
+fn parse_uid(uid: &str) -> TaskId {
+    Uuid::parse_str(uid)
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.as_bytes()))
+        .into()
+}
+
+// The properties of a `VTODO` read so far.
+#[derive(Default)]
+struct Todo {
+    id: Option<TaskId>,
+    title: String,
+    snoozed: Option<NaiveDate>,
+    due: Option<NaiveDate>,
+    completed: Option<DateTime<Utc>>,
+    status_completed: bool,
+    // The depth of components nested inside the task, such as alarms, whose
+    // properties are not the task's.
+    depth: usize,
+}
+
+impl Todo {
+    fn into_task(self, now: DateTime<Utc>) -> Task {
+        let completed = match (self.completed, self.status_completed) {
+            (None, true) => Some(now),
+            (completed, _) => completed,
+        };
+        Task::new(
+            self.id.unwrap_or_else(Task::new_id),
+            self.title,
+            self.snoozed,
+            self.due,
+            completed,
+        )
+    }
+}
+
+pub(super) fn import<R: Read>(reader: R) -> Result<TaskList, Error> {
+    let now = Utc::now();
+    let mut tasks = Vec::new();
+    let mut current: Option<Todo> = None;
+    let lines = read_lines(reader)?;
+    for line in &lines {
+        let Some(todo) = &mut current else {
+            if line.name == "BEGIN" && line.value.eq_ignore_ascii_case("VTODO") {
+                current = Some(Todo::default());
+            }
+            continue;
+        };
+        match line.name.as_str() {
+            "BEGIN" => todo.depth += 1,
+            "END" if todo.depth > 0 => todo.depth -= 1,
+            "END" => tasks.push(current.take().expect("a task is open").into_task(now)),
+            _ if todo.depth > 0 => {}
+            "UID" => todo.id = Some(parse_uid(&line.value)),
+            "SUMMARY" => todo.title = unescape(&line.value),
+            "DTSTART" => todo.snoozed = Some(parse_date(line)?),
+            "DUE" => todo.due = Some(parse_date(line)?),
+            "COMPLETED" => todo.completed = Some(parse_date_time(line)?),
+            "STATUS" => todo.status_completed = line.value.eq_ignore_ascii_case("COMPLETED"),
+            _ => {}
+        }
+    }
+    if current.is_some() {
+        return Err(Error::Parse {
+            line: lines.last().map_or(0, |line| line.number),
+            message: "the last VTODO has no END".to_string(),
+        });
+    }
+    Ok(TaskList { tasks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{add_tasks, MemoryStore, Store};
+
+    const SAMPLE: &[u8] = include_bytes!("../../testdata/tasks.ics");
+
+    fn date(s: &str) -> Option<NaiveDate> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn imports_the_sample_calendar() {
+        let tasks = import(SAMPLE).unwrap().tasks;
+        assert_eq!(tasks.len(), 3);
+
+        assert_eq!(
+            tasks[0].id().to_string(),
+            "01910c3b-6cc4-7d9e-8d2c-5f8a2e0d4b11"
+        );
+        assert_eq!(tasks[0].title(), "Buy milk, eggs; and bread");
+        assert_eq!(tasks[0].snoozed(), date("2024-07-01"));
+        assert_eq!(tasks[0].due(), date("2024-07-05"));
+        assert!(!tasks[0].is_completed());
+
+        assert_eq!(
+            tasks[1].title(),
+            "Write the quarterly report, which is long enough that its summary line \
+             has to be folded"
+        );
+        assert_eq!(tasks[1].due(), date("2024-07-10"));
+        assert_eq!(tasks[1].completed(), "2024-07-03T13:01:42Z".parse().ok());
+
+        // A task from another app, with an alarm whose properties are not
+        // the task's.
+        assert_eq!(tasks[2].title(), "Call the plumber\nabout the sink");
+        assert_eq!(tasks[2].due(), date("2024-07-20"));
+        assert_eq!(import(SAMPLE).unwrap().tasks[2].id(), tasks[2].id());
+    }
+
+    #[test]
+    fn round_trips_the_sample_calendar() {
+        let tasks = import(SAMPLE).unwrap();
+        let mut bytes = Vec::new();
+        export(&tasks, &mut bytes).unwrap();
+        assert!(bytes
+            .split(|&b| b == b'\n')
+            .all(|line| line.len() <= LINE_LIMIT + 1));
+        assert_eq!(import(bytes.as_slice()).unwrap(), tasks);
+    }
+
+    #[test]
+    fn reimporting_updates_tasks() {
+        let mut store = MemoryStore::default();
+        let tasks = import(SAMPLE).unwrap();
+        add_tasks(&mut store, &tasks).unwrap();
+        add_tasks(&mut store, &tasks).unwrap();
+        assert_eq!(store.list_tasks().unwrap(), tasks.tasks);
+    }
+
+    #[test]
+    fn folds_long_lines_between_characters() {
+        let mut bytes = Vec::new();
+        let line = format!("SUMMARY:{}", "é".repeat(50));
+        write_line(&mut bytes, &line).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<_> = text.split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(lines[0].to_string() + &lines[1][1..], line);
+    }
+
+    #[test]
+    fn rejects_unterminated_tasks() {
+        let calendar = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY:x\r\n";
+        assert!(matches!(
+            import(calendar.as_bytes()),
+            Err(Error::Parse { .. })
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+//! Tasks as JSON: an object whose `tasks` array holds one object per task.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::Record;
+use crate::document::{Error, TaskList};
+use crate::Task;
+
+// The top level is an object rather than a bare array so that fields can be
+// added alongside `tasks` later without breaking readers.
+#[derive(Serialize, Deserialize)]
+struct Document {
+    tasks: Vec<Record>,
+}
+
+pub(super) fn export<W: Write>(tasks: &TaskList, mut writer: W) -> Result<(), Error> {
+    let document = Document {
+        tasks: tasks.tasks.iter().map(Record::from).collect(),
+    };
+    serde_json::to_writer_pretty(&mut writer, &document).map_err(Error::Json)?;
+    writer.write_all(b"\n").map_err(Error::Write)
+}
+
+pub(super) fn import<R: Read>(reader: R) -> Result<TaskList, Error> {
+    let document: Document = serde_json::from_reader(reader).map_err(Error::Json)?;
+    Ok(TaskList {
+        tasks: document.tasks.into_iter().map(Task::from).collect(),
+    })
+}
@@ -0,0 +1,100 @@
+//! Tasks as a GitHub-style Markdown checklist: `- [ ] title` for each task, or
+//! `- [x] title` once it is done.
+//!
+//! Lines that are not checklist items are skipped on import, so a checklist
+//! can be taken from the middle of a larger document.  Tasks imported as done
+//! are completed at the time of the import.
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use crate::document::{Error, TaskList};
+use crate::Task;
+
+pub(super) fn export<W: Write>(tasks: &TaskList, mut writer: W) -> Result<(), Error> {
+    for task in &tasks.tasks {
+        let mark = if task.is_completed() { 'x' } else { ' ' };
+        writeln!(writer, "- [{}] {}", mark, task.title()).map_err(Error::Write)?;
+    }
+    Ok(())
+}
+
+// Parse a checklist item, returning whether it is checked and its title.
+fn parse_item(line: &str) -> Option<(bool, &str)> {
+    let item = line.trim_start().strip_prefix(['-', '*', '+'])?;
+    let item = item.strip_prefix(' ')?;
+    let (checked, title) = if let Some(title) = item.strip_prefix("[ ]") {
+        (false, title)
+    } else {
+        let title = item
+            .strip_prefix("[x]")
+            .or_else(|| item.strip_prefix("[X]"))?;
+        (true, title)
+    };
+    if !title.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let title = title.trim();
+    (!title.is_empty()).then_some((checked, title))
+}
+
+pub(super) fn import<R: Read>(reader: R) -> Result<TaskList, Error> {
+    let now = chrono::Utc::now();
+    let mut tasks = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(Error::Read)?;
+        if let Some((checked, title)) = parse_item(&line) {
+            let completed = checked.then_some(now);
+            tasks.push(Task::new(
+                Task::new_id(),
+                title.to_string(),
+                None,
+                None,
+                completed,
+            ));
+        }
+    }
+    Ok(TaskList { tasks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_a_checklist() {
+        let mut done = Task::new(Task::new_id(), "done".to_string(), None, None, None);
+        done.set_completed(Some(chrono::Utc::now()));
+        let tasks = TaskList {
+            tasks: vec![
+                Task::new(Task::new_id(), "to do".to_string(), None, None, None),
+                done,
+            ],
+        };
+        let mut bytes = Vec::new();
+        export(&tasks, &mut bytes).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "- [ ] to do\n- [x] done\n"
+        );
+    }
+
+    #[test]
+    fn imports_only_checklist_items() {
+        let markdown = "\
+# Groceries
+
+- [ ] milk
+  * [X] eggs
+- plain item
+- [ ]
+-  [x] two spaces
++ [x] bread
+";
+        let tasks = import(markdown.as_bytes()).unwrap().tasks;
+        let items: Vec<_> = tasks
+            .iter()
+            .map(|task| (task.is_completed(), task.title()))
+            .collect();
+        assert_eq!(items, [(false, "milk"), (true, "eggs"), (true, "bread")]);
+    }
+}
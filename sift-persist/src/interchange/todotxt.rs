@@ -0,0 +1,247 @@
+//! Tasks in the [todo.txt](https://github.com/todotxt/todo.txt) format, one
+//! task per line:
+//!
+//! ```text
+//! x 2024-07-03 2024-07-01 Call the plumber +house @phone due:2024-07-05 t:2024-07-02
+//! ```
+//!
+//! A leading `x` and date complete the task, `due:` is its due date and `t:`
+//! (threshold) its snooze date.  Sift has no field for the rest, so it is kept
+//! in the task's extensions and written back out on export:
+//!
+//! * `todotxt.priority`: the priority letter, from `(A)`.
+//! * `todotxt.created`: the creation date.
+//! * `todotxt.projects` and `todotxt.contexts`: the names of `+project` and
+//!   `@context` tags, separated by spaces.
+//! * `todotxt.pairs`: any other `key:value` pairs, separated by spaces.
+//!
+//! Tags and pairs are written after the title, wherever they were in the
+//! line, and runs of spaces in the title become one.  Completed tasks keep
+//! their priority as a `pri:` pair, as todo.txt clients do.
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use chrono::{NaiveDate, Utc};
+
+use crate::document::{Error, TaskList};
+use crate::Task;
+
+const PRIORITY: &str = "todotxt.priority";
+const CREATED: &str = "todotxt.created";
+const PROJECTS: &str = "todotxt.projects";
+const CONTEXTS: &str = "todotxt.contexts";
+const PAIRS: &str = "todotxt.pairs";
+
+fn parse_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, "%Y-%m-%d").ok()
+}
+
+fn parse_priority(word: &str) -> Option<char> {
+    match word.as_bytes() {
+        [b'(', priority @ b'A'..=b'Z', b')'] => Some(char::from(*priority)),
+        _ => None,
+    }
+}
+
+// Split a `key:value` pair.  Words like `http://example.com` are left alone,
+// so that links stay in the title.
+fn parse_pair(word: &str) -> Option<(&str, &str)> {
+    let (key, value) = word.split_once(':')?;
+    let valid = !key.is_empty()
+        && !value.is_empty()
+        && !key.contains(['/', '+', '@'])
+        && !value.starts_with('/');
+    valid.then_some((key, value))
+}
+
+// Append `word` to the space separated list under `key`.
+fn push_word(task: &mut Task, key: &str, word: &str) {
+    let words = task.extensions_mut().entry(key.to_string()).or_default();
+    if !words.is_empty() {
+        words.push(' ');
+    }
+    words.push_str(word);
+}
+
+fn parse_line(line: &str, now: chrono::DateTime<Utc>) -> Task {
+    let mut words = line.split_whitespace().peekable();
+    let mut completed = None;
+    let mut priority = None;
+    if words.peek() == Some(&"x") {
+        words.next();
+        completed = Some(
+            words
+                .next_if(|word| parse_date(word).is_some())
+                .and_then(parse_date)
+                .map_or(now, |date| date.and_time(chrono::NaiveTime::MIN).and_utc()),
+        );
+    } else if let Some(word) = words.next_if(|word| parse_priority(word).is_some()) {
+        priority = parse_priority(word);
+    }
+    let created = words
+        .next_if(|word| parse_date(word).is_some())
+        .map(str::to_string);
+
+    let mut task = Task::new(Task::new_id(), String::new(), None, None, completed);
+    let mut title = Vec::new();
+    for word in words {
+        match word.split_at(word.chars().next().map_or(0, char::len_utf8)) {
+            ("+", project) if !project.is_empty() => push_word(&mut task, PROJECTS, project),
+            ("@", context) if !context.is_empty() => push_word(&mut task, CONTEXTS, context),
+            _ => match parse_pair(word) {
+                Some(("due", date)) if parse_date(date).is_some() => {
+                    task.set_due(parse_date(date));
+                }
+                Some(("t", date)) if parse_date(date).is_some() => {
+                    task.set_snoozed(parse_date(date));
+                }
+                Some(_) => push_word(&mut task, PAIRS, word),
+                None => title.push(word),
+            },
+        }
+    }
+    task.set_title(title.join(" "));
+    if let Some(priority) = priority {
+        task.extensions_mut()
+            .insert(PRIORITY.to_string(), priority.to_string());
+    }
+    if let Some(created) = created {
+        task.extensions_mut().insert(CREATED.to_string(), created);
+    }
+    task
+}
+
+fn format_line(task: &Task) -> String {
+    let extension = |key: &str| task.extensions().get(key).filter(|value| !value.is_empty());
+    let mut words = Vec::new();
+    if let Some(completed) = task.completed() {
+        words.push(format!("x {}", completed.date_naive().format("%Y-%m-%d")));
+    } else if let Some(priority) = extension(PRIORITY) {
+        words.push(format!("({})", priority));
+    }
+    if let Some(created) = extension(CREATED) {
+        words.push(created.clone());
+    }
+    if !task.title().is_empty() {
+        words.push(task.title().to_string());
+    }
+    for (key, sigil) in [(PROJECTS, '+'), (CONTEXTS, '@')] {
+        if let Some(names) = extension(key) {
+            words.extend(names.split(' ').map(|name| format!("{}{}", sigil, name)));
+        }
+    }
+    if let Some(due) = task.due() {
+        words.push(format!("due:{}", due.format("%Y-%m-%d")));
+    }
+    if let Some(snoozed) = task.snoozed() {
+        words.push(format!("t:{}", snoozed.format("%Y-%m-%d")));
+    }
+    if let Some(pairs) = extension(PAIRS) {
+        words.push(pairs.clone());
+    }
+    if task.is_completed() {
+        if let Some(priority) = extension(PRIORITY) {
+            words.push(format!("pri:{}", priority));
+        }
+    }
+    words.join(" ")
+}
+
+pub(super) fn export<W: Write>(tasks: &TaskList, mut writer: W) -> Result<(), Error> {
+    for task in &tasks.tasks {
+        writeln!(writer, "{}", format_line(task)).map_err(Error::Write)?;
+    }
+    Ok(())
+}
+
+pub(super) fn import<R: Read>(reader: R) -> Result<TaskList, Error> {
+    let now = Utc::now();
+    let mut tasks = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(Error::Read)?;
+        if !line.trim().is_empty() {
+            tasks.push(parse_line(&line, now));
+        }
+    }
+    Ok(TaskList { tasks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{add_tasks, MemoryStore, Store};
+
+    fn parse(line: &str) -> Task {
+        parse_line(line, Utc::now())
+    }
+
+    fn extension<'a>(task: &'a Task, key: &str) -> Option<&'a str> {
+        task.extensions().get(key).map(String::as_str)
+    }
+
+    #[test]
+    fn parses_fields_and_tags() {
+        let task = parse(
+            "x 2024-07-03 2024-07-01 Call +house the plumber @phone due:2024-07-05 \
+             t:2024-07-02 rec:1w https://example.com",
+        );
+        assert_eq!(task.title(), "Call the plumber https://example.com");
+        assert_eq!(task.completed(), "2024-07-03T00:00:00Z".parse().ok());
+        assert_eq!(task.due(), parse_date("2024-07-05"));
+        assert_eq!(task.snoozed(), parse_date("2024-07-02"));
+        assert_eq!(extension(&task, CREATED), Some("2024-07-01"));
+        assert_eq!(extension(&task, PROJECTS), Some("house"));
+        assert_eq!(extension(&task, CONTEXTS), Some("phone"));
+        assert_eq!(extension(&task, PAIRS), Some("rec:1w"));
+    }
+
+    #[test]
+    fn parses_priority_of_incomplete_tasks() {
+        let task = parse("(A) 2024-07-01 Write the report +work +q3");
+        assert!(!task.is_completed());
+        assert_eq!(task.title(), "Write the report");
+        assert_eq!(extension(&task, PRIORITY), Some("A"));
+        assert_eq!(extension(&task, PROJECTS), Some("work q3"));
+
+        let task = parse("(a) not a priority");
+        assert_eq!(task.title(), "(a) not a priority");
+    }
+
+    #[test]
+    fn invalid_dates_are_kept_as_pairs() {
+        let task = parse("Renew passport due:soon");
+        assert_eq!(task.due(), None);
+        assert_eq!(extension(&task, PAIRS), Some("due:soon"));
+    }
+
+    #[test]
+    fn completed_tasks_keep_their_priority_as_a_pair() {
+        let mut task = parse("(B) Water the plants");
+        task.set_completed("2024-07-03T18:00:00Z".parse().ok());
+        assert_eq!(format_line(&task), "x 2024-07-03 Water the plants pri:B");
+    }
+
+    const TODO_TXT: &str = "\
+(A) 2024-07-01 Write the report +work @office due:2024-07-10
+x 2024-07-03 2024-07-01 Call the plumber +house @phone rec:1w
+Plant tomatoes t:2024-08-01 weather:dry
+";
+
+    #[test]
+    fn round_trips_through_a_saved_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let mut store = MemoryStore::default();
+        add_tasks(&mut store, &import(TODO_TXT.as_bytes()).unwrap()).unwrap();
+        store.save(&path).unwrap();
+        drop(store);
+
+        let store = MemoryStore::load(&path).unwrap();
+        let tasks = TaskList {
+            tasks: store.list_tasks().unwrap(),
+        };
+        let mut bytes = Vec::new();
+        export(&tasks, &mut bytes).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), TODO_TXT);
+    }
+}
@@ -1,12 +1,40 @@
-pub use document::{load_tasks, save_tasks, TaskList};
-pub use task::{Task, TaskId};
+pub use automerge::ChangeHash;
+pub use document::{
+    backup_path, load_tasks, merge_files, restore_backup, save_tasks, Error, SaveOptions, TaskList,
+};
+pub use file::Access;
+pub use inspect::{inspect, inspect_file, ChunkInfo, Failure, Inspection};
+pub use interchange::{add_tasks, export_tasks, import_tasks, Format};
+pub use list::{ListId, NamedList, DEFAULT_LIST_NAME};
+pub use lock::{lock_path, LockOwner};
+pub use peer::{sync_over, sync_with_peer, PeerReport, Side, SyncChannel};
+pub use recurrence::{Frequency, ParseRecurrenceError, Recurrence, RepeatFrom};
+pub use salvage::{salvage_file, Recovered, SalvageReport};
+pub use tag::{join_tags, split_tags};
+pub use task::{ParsePriorityError, Priority, Task, TaskId};
+pub use transform::{Compression, Passphrase};
+pub use tree::{task_tree, TaskNode};
+pub use watch::{FileWatch, Watcher};
 
+pub use self::store::autocommit::AutomergeStore;
 pub use self::store::memory::MemoryStore;
 pub use self::store::{Store, Transaction};
 
 mod container;
 mod crc;
 mod document;
+mod file;
+mod inspect;
+mod interchange;
+mod list;
+mod lock;
+mod peer;
+mod recurrence;
+mod salvage;
 mod serialization;
 mod store;
+mod tag;
 mod task;
+mod transform;
+mod tree;
+mod watch;
@@ -0,0 +1,152 @@
+//! Named lists of tasks, such as Work, Home and Shopping.
+//!
+//! Each task names the list it is in, and each list keeps its tasks in the
+//! order they have in the store.  A task that names no list, as every task
+//! written before lists existed does, or names a list that was deleted
+//! concurrently, is in the default list.  The default list is in every
+//! document, whether or not it was ever stored, and cannot be deleted.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use anyhow::bail;
+use uuid::Uuid;
+
+/// The name of the default list until it is renamed.
+pub const DEFAULT_LIST_NAME: &str = "Tasks";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ListId(Uuid);
+
+impl ListId {
+    /// The id of the default list.
+    pub const DEFAULT: ListId = ListId(Uuid::nil());
+
+    #[must_use]
+    pub fn new() -> ListId {
+        let context = uuid::NoContext;
+        let ts = uuid::Timestamp::now(context);
+        ListId(Uuid::new_v7(ts))
+    }
+
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::DEFAULT
+    }
+}
+
+impl Default for ListId {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Display for ListId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ListId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(ListId)
+    }
+}
+
+/// A list of tasks and its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedList {
+    pub id: ListId,
+    pub name: String,
+}
+
+impl NamedList {
+    /// A new list named `name`, with a fresh id.
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        NamedList {
+            id: ListId::new(),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Display for NamedList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+// Return the stored `lists` with the default list first if it was never
+// stored.
+pub(crate) fn with_default(mut lists: Vec<NamedList>) -> Vec<NamedList> {
+    if !lists.iter().any(|list| list.id.is_default()) {
+        lists.insert(
+            0,
+            NamedList {
+                id: ListId::DEFAULT,
+                name: DEFAULT_LIST_NAME.to_string(),
+            },
+        );
+    }
+    lists
+}
+
+// The operations below edit the lists as a store presents them, default
+// list included; the store then keeps the result.
+
+fn position(lists: &[NamedList], id: &ListId) -> anyhow::Result<usize> {
+    match lists.iter().position(|list| list.id == *id) {
+        Some(index) => Ok(index),
+        None => bail!("list not found"),
+    }
+}
+
+// Return the index just after `previous`, or 0 if there is no previous list.
+fn index_after(lists: &[NamedList], previous: Option<&ListId>) -> anyhow::Result<usize> {
+    previous.map_or(Ok(0), |previous| Ok(position(lists, previous)? + 1))
+}
+
+pub(crate) fn create(
+    lists: &mut Vec<NamedList>,
+    previous: Option<&ListId>,
+    list: &NamedList,
+) -> anyhow::Result<()> {
+    if lists.iter().any(|other| other.id == list.id) {
+        bail!("list already exists");
+    }
+    let index = index_after(lists, previous)?;
+    lists.insert(index, list.clone());
+    Ok(())
+}
+
+pub(crate) fn rename(lists: &mut [NamedList], id: &ListId, name: &str) -> anyhow::Result<()> {
+    let index = position(lists, id)?;
+    lists[index].name = name.to_string();
+    Ok(())
+}
+
+pub(crate) fn remove(lists: &mut Vec<NamedList>, id: &ListId) -> anyhow::Result<()> {
+    if id.is_default() {
+        bail!("the default list cannot be deleted");
+    }
+    let index = position(lists, id)?;
+    lists.remove(index);
+    Ok(())
+}
+
+pub(crate) fn reorder(
+    lists: &mut Vec<NamedList>,
+    previous: Option<&ListId>,
+    id: &ListId,
+) -> anyhow::Result<()> {
+    if previous == Some(id) {
+        bail!("cannot move a list after itself");
+    }
+    let list = lists.remove(position(lists, id)?);
+    let index = index_after(lists, previous)?;
+    lists.insert(index, list);
+    Ok(())
+}
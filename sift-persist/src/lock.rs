@@ -0,0 +1,200 @@
+//! Advisory locking of task files.
+//!
+//! A process that intends to write a task file creates a lock file next to
+//! it, named `<file>.lock`, holding its process id and host name.  Other
+//! processes refuse to take the lock while the lock file exists, unless it
+//! was left behind by a process on the same host that is no longer running.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::document::{with_suffix, Error};
+
+/// The process holding a lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub hostname: String,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: hostname(),
+        }
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        let pid = lines.next()?.parse().ok()?;
+        let hostname = lines.next()?.to_string();
+        Some(Self { pid, hostname })
+    }
+
+    fn is_stale(&self) -> bool {
+        // The liveness of processes on other hosts can not be checked.
+        self.hostname == hostname() && !process_is_running(self.pid)
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn process_is_running(pid: u32) -> bool {
+    // Zero and negative values have special meanings to kill(2).
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: signal 0 only checks whether the process exists; nothing is
+    // sent to it.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// Without a way to check, assume the owner is running, so that locks are
+// never broken by mistake.
+#[cfg(not(unix))]
+fn process_is_running(_pid: u32) -> bool {
+    true
+}
+
+/// Return the path of the lock file for `filename`.
+#[must_use]
+pub fn lock_path(filename: &Path) -> PathBuf {
+    with_suffix(filename, ".lock")
+}
+
+/// A held lock on a task file.  The lock is released when this is dropped.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Lock `filename` for writing by this process.
+    ///
+    /// Returns [`Error::Locked`] if another running process holds the lock.
+    /// A lock left behind by a process on this host that is no longer running
+    /// is broken and taken over.
+    pub(crate) fn acquire(filename: &Path) -> Result<FileLock, Error> {
+        let path = lock_path(filename);
+        match Self::create(&path) {
+            Err(Error::Locked { owner, .. }) if owner.is_stale() => {
+                fs::remove_file(&path).map_err(|e| Error::Lock(e, path.clone()))?;
+                Self::create(&path)
+            }
+            result => result,
+        }
+    }
+
+    fn create(path: &Path) -> Result<FileLock, Error> {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                let owner = LockOwner::current();
+                // Construct the lock first so that it is removed if writing
+                // fails.
+                let lock = FileLock {
+                    path: path.to_owned(),
+                };
+                writeln!(file, "{}\n{}", owner.pid, owner.hostname)
+                    .and_then(|()| file.sync_all())
+                    .map_err(|e| Error::Lock(e, path.to_owned()))?;
+                Ok(lock)
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let contents = fs::read_to_string(path).map_err(|e| Error::Lock(e, path.into()))?;
+                // A lock file that can not be parsed may be mid-creation by
+                // another process, so it is treated as held by an unknown
+                // owner rather than as stale.
+                let owner = LockOwner::parse(&contents).unwrap_or(LockOwner {
+                    pid: 0,
+                    hostname: String::from("unknown host"),
+                });
+                Err(Error::Locked {
+                    path: path.to_owned(),
+                    owner,
+                })
+            }
+            Err(e) => Err(Error::Lock(e, path.to_owned())),
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Nothing useful can be done if this fails; at worst the lock looks
+        // stale to the next process.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_lock(filename: &Path, pid: u32, hostname: &str) {
+        fs::write(lock_path(filename), format!("{}\n{}\n", pid, hostname)).unwrap();
+    }
+
+    #[test]
+    fn lock_is_exclusive_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+
+        let lock = FileLock::acquire(&path).unwrap();
+        match FileLock::acquire(&path) {
+            Err(Error::Locked { owner, .. }) => assert_eq!(owner, LockOwner::current()),
+            other => panic!("expected Error::Locked, got {:?}", other),
+        }
+
+        drop(lock);
+        assert!(!lock_path(&path).exists());
+        FileLock::acquire(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_lock_is_broken() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        write_lock(&path, child.id(), &hostname());
+
+        let _lock = FileLock::acquire(&path).unwrap();
+        let contents = fs::read_to_string(lock_path(&path)).unwrap();
+        assert_eq!(LockOwner::parse(&contents), Some(LockOwner::current()));
+    }
+
+    #[test]
+    fn lock_from_another_host_is_respected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        write_lock(&path, 1, "some-other-host.invalid");
+
+        assert!(matches!(
+            FileLock::acquire(&path),
+            Err(Error::Locked { .. })
+        ));
+    }
+}
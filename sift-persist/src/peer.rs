@@ -0,0 +1,245 @@
+//! Syncing a store directly with another Sift instance, without a server, by
+//! exchanging Automerge sync messages over a stream such as a TCP or Unix
+//! socket.
+//!
+//! The peers take turns: the one that connected sends a message, the other
+//! replies, and so on.  Each message is a big-endian `u32` length followed by
+//! that many bytes of an encoded [`automerge::sync::Message`]; a length of
+//! zero means the sender had nothing to send.  Once two empty messages pass
+//! in a row, neither side has anything more to send and the session ends,
+//! with both stores holding the same changes.
+//!
+//! Only stores that share history merge meaningfully.  A new instance should
+//! start from a copy of the other's file, or from an empty store, which takes
+//! on the other's document in its first session.
+
+use std::io::{Read, Write};
+
+use automerge::sync;
+
+use crate::document::Error;
+use crate::Store;
+
+// Larger messages are taken to be garbage rather than allocated for.
+const MAX_MESSAGE_LEN: u32 = 256 * 1024 * 1024;
+
+/// Which end of the stream a peer is on.  The connecting peer sends first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Connecting,
+    Listening,
+}
+
+/// What a sync session did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerReport {
+    /// Non-empty messages sent to the peer.
+    pub sent: usize,
+    /// Non-empty messages received from the peer.
+    pub received: usize,
+    /// Whether the peer's messages changed the store.
+    pub changed: bool,
+}
+
+/// Carries the messages of a sync session between two peers, which take
+/// turns to send.  An empty message means the sender had nothing to send.
+///
+/// [`sync_with_peer`] frames messages on a byte stream; other transports,
+/// such as WebSocket, implement this to run a session with [`sync_over`].
+pub trait SyncChannel {
+    /// Send an encoded sync message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message cannot be sent.
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()>;
+
+    /// Receive the peer's next encoded sync message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no message can be received.
+    fn receive(&mut self) -> anyhow::Result<Vec<u8>>;
+}
+
+// Messages on a byte stream, each preceded by its length.
+struct Framed<'a, T>(&'a mut T);
+
+impl<T: Read + Write> SyncChannel for Framed<'_, T> {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        let len = u32::try_from(message.len())
+            .ok()
+            .filter(|len| *len <= MAX_MESSAGE_LEN)
+            .ok_or(Error::SyncMessageTooLarge(u32::MAX))?;
+        self.0.write_all(&len.to_be_bytes()).map_err(Error::Peer)?;
+        self.0.write_all(message).map_err(Error::Peer)?;
+        self.0.flush().map_err(Error::Peer)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> anyhow::Result<Vec<u8>> {
+        let mut len = [0; 4];
+        self.0.read_exact(&mut len).map_err(Error::Peer)?;
+        let len = u32::from_be_bytes(len);
+        if len > MAX_MESSAGE_LEN {
+            return Err(Error::SyncMessageTooLarge(len).into());
+        }
+        let mut message = vec![0; len as usize];
+        self.0.read_exact(&mut message).map_err(Error::Peer)?;
+        Ok(message)
+    }
+}
+
+/// Sync `store` with the peer at the other end of `stream` until both have
+/// each other's changes.  The caller saves the store afterwards.
+///
+/// # Errors
+///
+/// Returns an error if the stream fails, the peer sends an invalid message,
+/// or its changes cannot be applied.
+pub fn sync_with_peer<S: Store, T: Read + Write>(
+    store: &mut S,
+    stream: &mut T,
+    side: Side,
+) -> anyhow::Result<PeerReport> {
+    sync_over(store, &mut Framed(stream), side)
+}
+
+/// Like [`sync_with_peer`], but exchanging messages over `channel`.
+///
+/// # Errors
+///
+/// Returns an error if the channel fails, the peer sends an invalid message,
+/// or its changes cannot be applied.
+pub fn sync_over<S: Store, C: SyncChannel>(
+    store: &mut S,
+    channel: &mut C,
+    side: Side,
+) -> anyhow::Result<PeerReport> {
+    let mut state = sync::State::new();
+    let mut report = PeerReport::default();
+    let start = store.heads();
+    // Whether the last message either way was empty.
+    let mut last_empty = false;
+    let mut sending = side == Side::Connecting;
+    loop {
+        let empty = if sending {
+            let message = store.generate_sync_message(&mut state);
+            let bytes = message.map(sync::Message::encode).unwrap_or_default();
+            channel.send(&bytes)?;
+            report.sent += usize::from(!bytes.is_empty());
+            bytes.is_empty()
+        } else {
+            let bytes = channel.receive()?;
+            if !bytes.is_empty() {
+                let message = sync::Message::decode(&bytes).map_err(Error::SyncMessage)?;
+                store.receive_sync_message(&mut state, message)?;
+                report.received += 1;
+            }
+            bytes.is_empty()
+        };
+        if empty && last_empty {
+            break;
+        }
+        last_empty = empty;
+        sending = !sending;
+    }
+    report.changed = store.heads() != start;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use super::*;
+    use crate::{MemoryStore, Task};
+
+    fn new_task(title: &str) -> Task {
+        Task::new(Task::new_id(), title.to_string(), None, None, None)
+    }
+
+    fn titles(store: &MemoryStore) -> Vec<String> {
+        let tasks = store.list_tasks().unwrap();
+        tasks.iter().map(|task| task.title().to_string()).collect()
+    }
+
+    // Sync `a` and `b` over a loopback TCP connection.
+    fn sync_over_tcp(a: &mut MemoryStore, b: &mut MemoryStore) -> (PeerReport, PeerReport) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::scope(|scope| {
+            let listening = scope.spawn(|| {
+                let (mut stream, _) = listener.accept().unwrap();
+                sync_with_peer(b, &mut stream, Side::Listening).unwrap()
+            });
+            let mut stream = TcpStream::connect(address).unwrap();
+            let connecting = sync_with_peer(a, &mut stream, Side::Connecting).unwrap();
+            (connecting, listening.join().unwrap())
+        })
+    }
+
+    #[test]
+    fn empty_store_takes_on_the_peers_tasks() {
+        let (one, two) = (new_task("one"), new_task("two"));
+        let mut a = MemoryStore::default();
+        a.with_transaction(|txn| {
+            txn.insert_task(None, None, &one)?;
+            txn.insert_task(None, Some(&one.id()), &two)
+        })
+        .unwrap();
+        let mut b = MemoryStore::default();
+
+        let (_, report) = sync_over_tcp(&mut a, &mut b);
+        assert!(report.changed);
+        assert_eq!(titles(&b), ["one", "two"]);
+        assert_eq!(a.heads(), b.heads());
+
+        let (a_report, b_report) = sync_over_tcp(&mut a, &mut b);
+        assert!(!a_report.changed && !b_report.changed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn concurrent_edits_converge_over_a_unix_socket() {
+        use std::os::unix::net::UnixStream;
+
+        let (one, two) = (new_task("one"), new_task("two"));
+        let mut a = MemoryStore::default();
+        a.with_transaction(|txn| txn.insert_task(None, None, &one))
+            .unwrap();
+        let mut b = MemoryStore::default();
+        sync_over_tcp(&mut a, &mut b);
+
+        a.with_transaction(|txn| txn.insert_task(None, Some(&one.id()), &two))
+            .unwrap();
+        let mut renamed = one.clone();
+        renamed.set_title("renamed".to_string());
+        b.with_transaction(|txn| txn.put_task(&renamed)).unwrap();
+
+        let (mut a_end, mut b_end) = UnixStream::pair().unwrap();
+        let (a_report, b_report) = thread::scope(|scope| {
+            let listening =
+                scope.spawn(|| sync_with_peer(&mut b, &mut b_end, Side::Listening).unwrap());
+            let connecting = sync_with_peer(&mut a, &mut a_end, Side::Connecting).unwrap();
+            (connecting, listening.join().unwrap())
+        });
+        assert!(a_report.changed && b_report.changed);
+        assert_eq!(titles(&a), ["renamed", "two"]);
+        assert_eq!(titles(&b), ["renamed", "two"]);
+        assert_eq!(a.heads(), b.heads());
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let mut stream = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let mut store = MemoryStore::default();
+        let error = sync_with_peer(&mut store, &mut stream, Side::Listening).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::SyncMessageTooLarge(u32::MAX))
+        ));
+    }
+}
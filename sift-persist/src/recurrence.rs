@@ -0,0 +1,514 @@
+//! Rules for tasks that repeat, such as weekly chores.
+//!
+//! A rule is written in the style of an iCalendar `RRULE`, which is also how
+//! it is stored: `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH` repeats every other
+//! week on Mondays and Thursdays.  `FROM=COMPLETION`, which `RRULE` has no
+//! equivalent of, repeats from the day the task was completed rather than
+//! from its due date.
+//!
+//! Repetition works on dates, not times, so a daylight saving change cannot
+//! move an occurrence to another day.  Monthly and yearly rules repeating
+//! from the due date keep to the day of the month the task was first due,
+//! stored as `BYMONTHDAY`.  In months without that day, such as the 31st or
+//! February 29th, they fall on the last day of the month instead, and return
+//! to the day in the months after.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, TimeZone, Weekday};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// What the next occurrence of a task is counted from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepeatFrom {
+    /// The due date of the task, or the completion date if it has none.
+    #[default]
+    Due,
+    /// The date the task was completed.
+    Completion,
+}
+
+/// How a task repeats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// How many days, weeks, months or years apart occurrences are, at least
+    /// 1.
+    pub interval: u32,
+    /// For daily and weekly rules, the days of the week occurrences fall on,
+    /// Monday first, or empty for any day.
+    pub weekdays: Vec<Weekday>,
+    /// For monthly and yearly rules, the day of the month occurrences fall
+    /// on, from 1 to 31, or `None` for the day of the date repeated from.
+    pub month_day: Option<u32>,
+    pub from: RepeatFrom,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("invalid recurrence rule `{0}`")]
+pub struct ParseRecurrenceError(String);
+
+impl Recurrence {
+    /// A rule repeating every `interval` days, weeks, months or years from
+    /// the due date.
+    #[must_use]
+    pub fn new(frequency: Frequency, interval: u32) -> Self {
+        Recurrence {
+            frequency,
+            interval: interval.max(1),
+            weekdays: Vec::new(),
+            month_day: None,
+            from: RepeatFrom::Due,
+        }
+    }
+
+    /// Repeat only on `weekdays`.
+    #[must_use]
+    pub fn on(mut self, weekdays: &[Weekday]) -> Self {
+        self.weekdays = weekdays.to_vec();
+        self.weekdays.sort_by_key(Weekday::num_days_from_monday);
+        self.weekdays.dedup();
+        self
+    }
+
+    /// Repeat on `day` of the month, or the last day of months without it.
+    #[must_use]
+    pub fn on_day(mut self, day: u32) -> Self {
+        self.month_day = Some(day.clamp(1, 31));
+        self
+    }
+
+    /// Repeat from `from`.
+    #[must_use]
+    pub fn repeat_from(mut self, from: RepeatFrom) -> Self {
+        self.from = from;
+        self
+    }
+
+    /// The due date of the occurrence after one due on `due` and completed
+    /// at `completed`.  The completion date is taken in the time zone of
+    /// `completed`, which should be the user's.  Returns `None` if that date
+    /// would be past the last one [`NaiveDate`] can hold.
+    #[must_use]
+    pub fn next_due<Tz: TimeZone>(
+        &self,
+        due: Option<NaiveDate>,
+        completed: &DateTime<Tz>,
+    ) -> Option<NaiveDate> {
+        let completed = completed.date_naive();
+        let start = match (self.from, due) {
+            (RepeatFrom::Due, Some(due)) => due,
+            _ => completed,
+        };
+        let month_day = self.month_day.unwrap_or(start.day());
+        let mut next = self.after(start, month_day)?;
+        // An overdue task repeating from its due date skips the occurrences
+        // already past, as a chore done late is not due again at once.
+        while next <= completed && self.from == RepeatFrom::Due && due.is_some() {
+            next = self.after(next, month_day)?;
+        }
+        Some(next)
+    }
+
+    // This rule as repeated from a task due on `due`: monthly and yearly
+    // rules from the due date keep to its day of the month from then on,
+    // rather than moving to the last day of the first short month.
+    pub(crate) fn anchored(&self, due: Option<NaiveDate>) -> Recurrence {
+        let mut rule = self.clone();
+        let by_month = matches!(self.frequency, Frequency::Monthly | Frequency::Yearly);
+        if by_month && self.from == RepeatFrom::Due && self.month_day.is_none() {
+            rule.month_day = due.map(|due| due.day());
+        }
+        rule
+    }
+
+    // The first occurrence after `date`, falling on `month_day` for monthly
+    // and yearly rules, or `None` if it is past the last date there is.
+    fn after(&self, date: NaiveDate, month_day: u32) -> Option<NaiveDate> {
+        let interval = self.interval.max(1);
+        match self.frequency {
+            Frequency::Daily if self.weekdays.is_empty() => {
+                date.checked_add_days(Days::new(interval.into()))
+            }
+            Frequency::Daily => {
+                // Stepping a week's worth of times visits every weekday the
+                // steps can reach.
+                let step = Days::new(interval.into());
+                let mut next = date.checked_add_days(step)?;
+                for _ in 0..7 {
+                    if self.weekdays.contains(&next.weekday()) {
+                        break;
+                    }
+                    next = next.checked_add_days(step)?;
+                }
+                Some(next)
+            }
+            Frequency::Weekly if self.weekdays.is_empty() => {
+                date.checked_add_days(Days::new(u64::from(interval) * 7))
+            }
+            Frequency::Weekly => {
+                // Later days in the same week come first, then the first day
+                // in the week `interval` weeks on.
+                let later = self
+                    .weekdays
+                    .iter()
+                    .find(|day| day.num_days_from_monday() > date.weekday().num_days_from_monday());
+                let monday =
+                    date.checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))?;
+                match later {
+                    Some(day) => {
+                        monday.checked_add_days(Days::new(day.num_days_from_monday().into()))
+                    }
+                    None => {
+                        let first = self.weekdays[0].num_days_from_monday();
+                        monday
+                            .checked_add_days(Days::new(u64::from(interval) * 7 + u64::from(first)))
+                    }
+                }
+            }
+            Frequency::Monthly => add_months(date, interval, month_day),
+            Frequency::Yearly => add_months(date, interval.saturating_mul(12), month_day),
+        }
+    }
+}
+
+// The month `months` after that of `date`, on `day` if the month has it and
+// on its last day if not, or `None` if it is past the last date there is.
+fn add_months(date: NaiveDate, months: u32, day: u32) -> Option<NaiveDate> {
+    let first = date.with_day(1).expect("every month has a first day");
+    let month = first.checked_add_months(Months::new(months))?;
+    month
+        .with_day(day)
+        .or_else(|| month.checked_add_months(Months::new(1))?.pred_opt())
+}
+
+const WEEKDAYS: [(Weekday, &str); 7] = [
+    (Weekday::Mon, "MO"),
+    (Weekday::Tue, "TU"),
+    (Weekday::Wed, "WE"),
+    (Weekday::Thu, "TH"),
+    (Weekday::Fri, "FR"),
+    (Weekday::Sat, "SA"),
+    (Weekday::Sun, "SU"),
+];
+
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={}", frequency)?;
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.weekdays.is_empty() {
+            let days: Vec<&str> = WEEKDAYS
+                .iter()
+                .filter(|(day, _)| self.weekdays.contains(day))
+                .map(|(_, name)| *name)
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(day) = self.month_day {
+            write!(f, ";BYMONTHDAY={}", day)?;
+        }
+        if self.from == RepeatFrom::Completion {
+            f.write_str(";FROM=COMPLETION")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = ParseRecurrenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseRecurrenceError(s.to_string());
+        let mut frequency = None;
+        let mut recurrence = Recurrence::new(Frequency::Daily, 1);
+        for part in s.split(';') {
+            let (key, value) = part.split_once('=').ok_or_else(error)?;
+            match key {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(error()),
+                    });
+                }
+                "INTERVAL" => {
+                    recurrence.interval = value.parse().map_err(|_| error())?;
+                    if recurrence.interval == 0 {
+                        return Err(error());
+                    }
+                }
+                "BYDAY" => {
+                    let days = value
+                        .split(',')
+                        .map(|name| {
+                            WEEKDAYS
+                                .iter()
+                                .find(|(_, other)| *other == name)
+                                .map(|(day, _)| *day)
+                                .ok_or_else(error)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    recurrence = recurrence.on(&days);
+                }
+                "BYMONTHDAY" => {
+                    let day: u32 = value.parse().map_err(|_| error())?;
+                    if !(1..=31).contains(&day) {
+                        return Err(error());
+                    }
+                    recurrence.month_day = Some(day);
+                }
+                "FROM" => {
+                    recurrence.from = match value {
+                        "DUE" => RepeatFrom::Due,
+                        "COMPLETION" => RepeatFrom::Completion,
+                        _ => return Err(error()),
+                    };
+                }
+                _ => return Err(error()),
+            }
+        }
+        recurrence.frequency = frequency.ok_or_else(error)?;
+        Ok(recurrence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, FixedOffset, NaiveDate, Utc, Weekday};
+
+    use super::{Frequency, Recurrence, RepeatFrom};
+    use crate::Task;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    // The next due date of a task due on `due` and completed that day.
+    fn next(recurrence: &Recurrence, due: &str) -> String {
+        let completed = format!("{}T12:00:00Z", due)
+            .parse::<DateTime<Utc>>()
+            .unwrap();
+        recurrence
+            .next_due(Some(date(due)), &completed)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn daily_and_weekly() {
+        let daily = Recurrence::new(Frequency::Daily, 3);
+        assert_eq!(next(&daily, "2024-02-27"), "2024-03-01");
+        let weekdays = Recurrence::new(Frequency::Daily, 1).on(&[
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ]);
+        assert_eq!(next(&weekdays, "2024-07-05"), "2024-07-08");
+
+        let weekly = Recurrence::new(Frequency::Weekly, 1);
+        assert_eq!(next(&weekly, "2024-12-30"), "2025-01-06");
+        // Every other week on Monday and Thursday, from a Monday.
+        let twice = Recurrence::new(Frequency::Weekly, 2).on(&[Weekday::Thu, Weekday::Mon]);
+        assert_eq!(next(&twice, "2024-07-01"), "2024-07-04");
+        assert_eq!(next(&twice, "2024-07-04"), "2024-07-15");
+        // From a day not in the rule, the next day in the rule that week.
+        assert_eq!(next(&twice, "2024-07-02"), "2024-07-04");
+        assert_eq!(next(&twice, "2024-07-06"), "2024-07-15");
+    }
+
+    #[test]
+    fn month_ends() {
+        let monthly = Recurrence::new(Frequency::Monthly, 1);
+        assert_eq!(next(&monthly, "2024-01-31"), "2024-02-29");
+        assert_eq!(next(&monthly, "2024-02-29"), "2024-03-29");
+        assert_eq!(
+            next(&monthly.clone().on_day(31), "2024-02-29"),
+            "2024-03-31"
+        );
+        assert_eq!(next(&monthly, "2023-01-30"), "2023-02-28");
+        assert_eq!(next(&monthly, "2024-01-30"), "2024-02-29");
+        assert_eq!(next(&monthly, "2024-03-30"), "2024-04-30");
+        assert_eq!(next(&monthly, "2024-12-15"), "2025-01-15");
+        let quarterly = Recurrence::new(Frequency::Monthly, 3);
+        assert_eq!(next(&quarterly, "2024-11-30"), "2025-02-28");
+
+        let yearly = Recurrence::new(Frequency::Yearly, 1);
+        assert_eq!(next(&yearly, "2024-02-29"), "2025-02-28");
+        assert_eq!(next(&yearly, "2023-02-28"), "2024-02-28");
+        let leap = Recurrence::new(Frequency::Yearly, 4);
+        assert_eq!(next(&leap, "2024-02-29"), "2028-02-29");
+    }
+
+    // The due dates of `count` occurrences of a task due on `due` with
+    // `recurrence`, each completed on the day it is due.
+    fn series(recurrence: &Recurrence, due: &str, count: usize) -> Vec<String> {
+        let mut task = Task::new(Task::new_id(), String::new(), None, Some(date(due)), None);
+        task.set_recurrence(Some(recurrence.clone()));
+        let mut dues = Vec::new();
+        for _ in 0..count {
+            let due = task.due().unwrap();
+            let completed = due.and_hms_opt(12, 0, 0).unwrap().and_utc();
+            task = task.next_occurrence(&completed).unwrap();
+            dues.push(task.due().unwrap().to_string());
+        }
+        dues
+    }
+
+    #[test]
+    fn monthly_chores_keep_their_day() {
+        let monthly = Recurrence::new(Frequency::Monthly, 1);
+        assert_eq!(
+            series(&monthly, "2023-01-28", 4),
+            ["2023-02-28", "2023-03-28", "2023-04-28", "2023-05-28"]
+        );
+        assert_eq!(
+            series(&monthly, "2024-01-30", 4),
+            ["2024-02-29", "2024-03-30", "2024-04-30", "2024-05-30"]
+        );
+        assert_eq!(
+            series(&monthly, "2024-01-31", 4),
+            ["2024-02-29", "2024-03-31", "2024-04-30", "2024-05-31"]
+        );
+        let yearly = Recurrence::new(Frequency::Yearly, 1);
+        assert_eq!(
+            series(&yearly, "2024-02-29", 4),
+            ["2025-02-28", "2026-02-28", "2027-02-28", "2028-02-29"]
+        );
+
+        // The day is kept when skipping occurrences already past.
+        let late = at("2024-03-15T09:00:00+00:00");
+        assert_eq!(
+            monthly.next_due(Some(date("2024-01-31")), &late),
+            Some(date("2024-03-31"))
+        );
+
+        // Repeating from the completion follows the completion date.
+        let monthly = monthly.repeat_from(RepeatFrom::Completion);
+        assert_eq!(
+            series(&monthly, "2024-01-31", 2),
+            ["2024-02-29", "2024-03-29"]
+        );
+    }
+
+    #[test]
+    fn daylight_saving_changes() {
+        // Completed half an hour after local midnight on the day clocks in
+        // central Europe went forward: in UTC it is still the day before.
+        let daily = Recurrence::new(Frequency::Daily, 1).repeat_from(RepeatFrom::Completion);
+        let completed = at("2024-03-31T00:30:00+01:00");
+        assert_eq!(daily.next_due(None, &completed), Some(date("2024-04-01")));
+        assert_eq!(
+            daily.next_due(None, &completed.with_timezone(&Utc)),
+            Some(date("2024-03-31"))
+        );
+        // Late in the evening of the day clocks in the eastern US went back,
+        // it is already the next day in UTC.
+        let completed = at("2024-11-03T23:30:00-05:00");
+        assert_eq!(daily.next_due(None, &completed), Some(date("2024-11-04")));
+
+        // Weekly from the due date lands on the same weekday across a
+        // change, as dates carry no time of day.
+        let weekly = Recurrence::new(Frequency::Weekly, 1);
+        assert_eq!(next(&weekly, "2024-03-28"), "2024-04-04");
+        assert_eq!(next(&weekly, "2024-10-24"), "2024-10-31");
+    }
+
+    #[test]
+    fn from_due_or_completion() {
+        let weekly = Recurrence::new(Frequency::Weekly, 1);
+        let late = at("2024-07-20T09:00:00+00:00");
+        // Done two weeks late: the next occurrence is the first one after
+        // the completion, on the same weekday as before.
+        assert_eq!(
+            weekly.next_due(Some(date("2024-07-04")), &late),
+            Some(date("2024-07-25"))
+        );
+        let weekly = weekly.repeat_from(RepeatFrom::Completion);
+        assert_eq!(
+            weekly.next_due(Some(date("2024-07-04")), &late),
+            Some(date("2024-07-27"))
+        );
+        // Without a due date, a rule from the due date counts from the
+        // completion.
+        let monthly = Recurrence::new(Frequency::Monthly, 1);
+        assert_eq!(monthly.next_due(None, &late), Some(date("2024-08-20")));
+    }
+
+    #[test]
+    fn no_occurrence_past_the_last_date() {
+        let completed = NaiveDate::MAX.and_hms_opt(12, 0, 0).unwrap().and_utc();
+        for frequency in [
+            Frequency::Daily,
+            Frequency::Weekly,
+            Frequency::Monthly,
+            Frequency::Yearly,
+        ] {
+            let rule = Recurrence::new(frequency, 1);
+            assert_eq!(rule.next_due(Some(NaiveDate::MAX), &completed), None);
+        }
+
+        let mut task = Task::new(
+            Task::new_id(),
+            String::new(),
+            None,
+            Some(NaiveDate::MAX),
+            None,
+        );
+        task.set_recurrence(Some(Recurrence::new(Frequency::Daily, 1)));
+        assert!(task.next_occurrence(&completed).is_none());
+    }
+
+    #[test]
+    fn rules_round_trip() {
+        let rule = Recurrence::new(Frequency::Weekly, 2)
+            .on(&[Weekday::Thu, Weekday::Mon])
+            .repeat_from(RepeatFrom::Completion);
+        assert_eq!(
+            rule.to_string(),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;FROM=COMPLETION"
+        );
+        assert_eq!(rule.to_string().parse::<Recurrence>().unwrap(), rule);
+        assert_eq!(
+            "FREQ=MONTHLY".parse::<Recurrence>().unwrap(),
+            Recurrence::new(Frequency::Monthly, 1)
+        );
+        let rule = Recurrence::new(Frequency::Monthly, 1).on_day(30);
+        assert_eq!(rule.to_string(), "FREQ=MONTHLY;BYMONTHDAY=30");
+        assert_eq!(rule.to_string().parse::<Recurrence>().unwrap(), rule);
+        for invalid in [
+            "",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "INTERVAL=2",
+            "FREQ=DAILY;BYDAY=XX",
+            "FREQ=MONTHLY;BYMONTHDAY=0",
+            "FREQ=MONTHLY;BYMONTHDAY=32",
+        ] {
+            assert!(invalid.parse::<Recurrence>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Recovering what can be recovered from a task file that fails to load.
+//!
+//! Salvaging reads as much of the file's document as it can, ignoring CRC
+//! mismatches and a truncated end, and loads it with Automerge's partial
+//! loading, which keeps the changes that precede any damage.  If the file's
+//! own document is damaged, the newest readable backup is merged in.  The
+//! original file is never overwritten: it is moved aside to
+//! `<file>.corrupt-<timestamp>` before the salvaged document is saved in its
+//! place.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use automerge::{AutoCommit, LoadOptions, OnPartialLoad, VerificationMode};
+
+use crate::container::{Chunk, CHUNK_SIZE_LIMIT};
+use crate::document::{
+    backup_path, hydrate_tasks, load_document, read_error, save_document, with_suffix, Error,
+    SaveOptions, AUTOMERGE_CHUNK,
+};
+use crate::lock::FileLock;
+use crate::transform::{self, Encryption, Passphrase};
+
+/// How much of a file's own document was recovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovered {
+    /// The document was intact; the damage was elsewhere in the file.
+    All,
+    /// The document was read up to the first damaged change.
+    Partial,
+    /// The document could not be read at all.
+    Nothing,
+}
+
+/// What salvaging a file found, and what it did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Where the damaged file was moved to.
+    pub corrupt_path: PathBuf,
+    /// How much of the file's own document was recovered.
+    pub recovered: Recovered,
+    /// The backup merged into the salvaged document, if one was needed and
+    /// could be read.  Changes made after it was written may still be lost.
+    pub backup: Option<PathBuf>,
+    /// The number of changes in the salvaged document.
+    pub changes: usize,
+    /// The number of changes that salvaged changes depend on but that could
+    /// not be recovered.  The salvaged changes that depend on them are not
+    /// applied.
+    pub missing_changes: usize,
+}
+
+// The size of the magic bytes at the start of a file.
+const MAGIC_SIZE: usize = 9;
+
+// Find the payload of the first `AMRG` chunk in `bytes`, however damaged the
+// file is: CRCs are not checked, and a chunk cut short by the end of the file
+// yields whatever is left of it.
+fn find_document_chunk(bytes: &[u8]) -> Option<Chunk> {
+    let mut offset = MAGIC_SIZE;
+    while offset + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().ok()?);
+        if length > CHUNK_SIZE_LIMIT {
+            return None;
+        }
+        let chunk_type: [u8; 4] = bytes[offset + 4..offset + 8].try_into().ok()?;
+        let start = offset + 8;
+        let end = start + usize::try_from(length).expect("u32 must fit in a usize");
+        if transform::plain_type(chunk_type) == AUTOMERGE_CHUNK {
+            let data = bytes[start..end.min(bytes.len())].to_vec();
+            return Some(Chunk::new(chunk_type, data));
+        }
+        offset = end + 4;
+    }
+    None
+}
+
+// Recover what can be recovered of the document stored in `bytes`, returning
+// it along with whether all of it was.
+fn salvage_document(
+    bytes: &[u8],
+    encryption: Option<&mut Encryption>,
+) -> Result<Option<(AutoCommit, bool)>, Error> {
+    let Some(chunk) = find_document_chunk(bytes) else {
+        return Ok(None);
+    };
+    let chunk = match chunk.decode(encryption).map_err(read_error) {
+        Ok(chunk) => chunk,
+        // Without a passphrase there is no telling whether anything is
+        // recoverable; let the caller ask for one.
+        Err(Error::PassphraseRequired) => return Err(Error::PassphraseRequired),
+        Err(_) => return Ok(None),
+    };
+    // Automerge checks its own checksums, so a document that loads in full is
+    // intact even if the chunk's CRC does not match.
+    if let Ok(document) = AutoCommit::load(&chunk.data) {
+        return Ok(Some((document, true)));
+    }
+    let options = LoadOptions::new()
+        .on_partial_load(OnPartialLoad::Ignore)
+        .verification_mode(VerificationMode::DontCheck);
+    Ok(AutoCommit::load_with_options(&chunk.data, options)
+        .ok()
+        .map(|document| (document, false)))
+}
+
+// Return the newest backup of `filename` that can be read, and its path.
+fn newest_backup(
+    filename: &Path,
+    mut encryption: Option<&mut Encryption>,
+) -> Option<(AutoCommit, PathBuf)> {
+    (1..)
+        .map(|n| backup_path(filename, n))
+        .take_while(|path| path.exists())
+        .find_map(|path| {
+            let document = load_document(&path, encryption.as_deref_mut()).ok()?;
+            hydrate_tasks(&document).ok()?;
+            Some((document, path))
+        })
+}
+
+// Return a path next to `filename` to move it aside to, which no file has.
+fn corrupt_path(filename: &Path) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%dT%H%M%S");
+    let path = with_suffix(filename, &format!(".corrupt-{}", timestamp));
+    if !path.exists() {
+        return path;
+    }
+    (2..)
+        .map(|n| with_suffix(&path, &format!("-{}", n)))
+        .find(|path| !path.exists())
+        .expect("some name must be free")
+}
+
+/// Salvage what can be recovered from the damaged task file `filename`.
+///
+/// The file is moved aside to `<file>.corrupt-<timestamp>` and the salvaged
+/// document is saved in its place.  `passphrase` is needed if the file is
+/// encrypted; the salvaged file is encrypted with it too.
+///
+/// # Errors
+///
+/// Returns [`Error::NothingToSalvage`] if neither the file nor any of its
+/// backups could be read, in which case the file is left where it is.
+pub fn salvage_file(
+    filename: &Path,
+    passphrase: Option<Passphrase>,
+) -> Result<SalvageReport, Error> {
+    let _lock = FileLock::acquire(filename)?;
+    let bytes = fs::read(filename).map_err(|e| Error::OpenFile(e, filename.to_owned()))?;
+    let mut encryption = passphrase.map(Encryption::new);
+
+    // A recovered document that can not be read as tasks is no use.
+    let salvaged = salvage_document(&bytes, encryption.as_mut())?
+        .filter(|(document, _)| hydrate_tasks(document).is_ok());
+    let recovered = match &salvaged {
+        Some((_, true)) => Recovered::All,
+        Some((_, false)) => Recovered::Partial,
+        None => Recovered::Nothing,
+    };
+    let mut document = salvaged.map(|(document, _)| document);
+    let mut backup = None;
+    if recovered != Recovered::All {
+        if let Some((mut other, path)) = newest_backup(filename, encryption.as_mut()) {
+            match &mut document {
+                Some(document) => {
+                    document.merge(&mut other).map_err(Error::AutomergeMerge)?;
+                }
+                None => document = Some(other),
+            }
+            backup = Some(path);
+        }
+    }
+    let mut document = document.ok_or_else(|| Error::NothingToSalvage(filename.to_owned()))?;
+    hydrate_tasks(&document)?;
+
+    let corrupt_path = corrupt_path(filename);
+    fs::rename(filename, &corrupt_path).map_err(|e| Error::MoveAside(e, corrupt_path.clone()))?;
+    save_document(
+        filename,
+        &mut document,
+        &SaveOptions::default(),
+        encryption.as_mut(),
+    )?;
+
+    Ok(SalvageReport {
+        corrupt_path,
+        recovered,
+        backup,
+        changes: document.get_changes(&[]).len(),
+        missing_changes: document.get_missing_deps(&[]).len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::container::{write_chunk, write_header, FileHeader};
+    use crate::document::{load_tasks, new_document, save_tasks, TaskList, END_CHUNK};
+    use crate::serialization::SerializableTaskList;
+    use crate::{inspect_file, Task};
+
+    fn titled(title: &str) -> TaskList {
+        TaskList {
+            tasks: vec![Task::new(
+                Task::new_id(),
+                title.to_string(),
+                None,
+                None,
+                None,
+            )],
+        }
+    }
+
+    fn title_in(path: &Path) -> String {
+        load_tasks(path).unwrap().tasks[0].title().to_string()
+    }
+
+    fn write_file(path: &Path, document: Vec<u8>) {
+        let mut bytes = Vec::new();
+        write_header(&mut bytes).unwrap();
+        write_chunk(&FileHeader::current().to_chunk(), &mut bytes).unwrap();
+        write_chunk(&Chunk::new(AUTOMERGE_CHUNK, document), &mut bytes).unwrap();
+        write_chunk(&Chunk::new(END_CHUNK, vec![]), &mut bytes).unwrap();
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn recovers_a_file_damaged_outside_the_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        save_tasks(&path, &titled("one")).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        // Corrupt the CRC of the `SEND` chunk.
+        *bytes.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        assert!(load_tasks(&path).is_err());
+
+        let report = salvage_file(&path, None).unwrap();
+        assert_eq!(report.recovered, Recovered::All);
+        assert_eq!(report.backup, None);
+        assert_eq!(report.missing_changes, 0);
+        assert_eq!(title_in(&path), "one");
+        assert_eq!(fs::read(&report.corrupt_path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn keeps_the_changes_before_the_damage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let mut tasks = titled("one");
+        let mut doc = new_document(&tasks).unwrap();
+        let mut document = doc.save();
+        tasks.tasks[0].set_title("two".to_string());
+        autosurgeon::reconcile(&mut doc, SerializableTaskList::from(tasks)).unwrap();
+        document.extend(doc.save_incremental());
+        // Cut the change that renamed the task short.
+        document.truncate(document.len() - 3);
+        write_file(&path, document);
+        assert!(load_tasks(&path).is_err());
+
+        let report = salvage_file(&path, None).unwrap();
+        assert_eq!(report.recovered, Recovered::Partial);
+        assert_eq!(title_in(&path), "one");
+    }
+
+    #[test]
+    fn falls_back_to_the_newest_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        save_tasks(&path, &titled("one")).unwrap();
+        save_tasks(&path, &titled("two")).unwrap();
+        let chunk = inspect_file(&path, None).unwrap().chunks[1].clone();
+        let mut bytes = fs::read(&path).unwrap();
+        let offset = usize::try_from(chunk.offset).unwrap() + 8 + chunk.length / 2;
+        bytes[offset] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        let report = salvage_file(&path, None).unwrap();
+        assert_eq!(report.recovered, Recovered::Nothing);
+        assert_eq!(report.backup, Some(backup_path(&path, 1)));
+        assert_eq!(title_in(&path), "one");
+    }
+
+    #[test]
+    fn leaves_unsalvageable_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        save_tasks(&path, &titled("one")).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        // Keep the `SHDR` chunk and the start of the `AMRG` chunk.
+        let truncated = &bytes[..MAGIC_SIZE + 34 + 20];
+        fs::write(&path, truncated).unwrap();
+
+        let error = salvage_file(&path, None).unwrap_err();
+        assert!(matches!(error, Error::NothingToSalvage(_)));
+        assert_eq!(fs::read(&path).unwrap(), truncated);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -1,12 +1,18 @@
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 
-use autosurgeon::reconcile::NoKey;
-use autosurgeon::{Hydrate, HydrateError, MaybeMissing, Reconcile};
+use automerge::{ObjId, ReadDoc, ScalarValue, Value};
+use autosurgeon::reconcile::{LoadKey, MapReconciler, NoKey};
+use autosurgeon::{
+    Hydrate, HydrateError, MaybeMissing, Prop, Reconcile, ReconcileError, Reconciler, Text,
+};
 use chrono::NaiveDate;
 use uuid::Uuid;
 
-use super::Task;
+use super::{Task, TaskId};
 use crate::document::TaskList;
+use crate::list::{ListId, NamedList};
 
 pub fn to_option<T>(from: MaybeMissing<T>) -> Option<T> {
     match from {
@@ -35,7 +41,7 @@ pub(crate) struct SerializableNaiveDate(NaiveDate);
 impl Reconcile for SerializableNaiveDate {
     type Key<'a> = NoKey;
 
-    fn reconcile<R: autosurgeon::Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
+    fn reconcile<R: Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
         reconciler.str(self.0.format("%F").to_string())
     }
 }
@@ -55,7 +61,7 @@ pub(crate) struct SerializableDateTime(chrono::DateTime<chrono::Utc>);
 impl Reconcile for SerializableDateTime {
     type Key<'a> = NoKey;
 
-    fn reconcile<R: autosurgeon::Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
+    fn reconcile<R: Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
         reconciler.str(self.0.format("%FT%TZ").to_string())
     }
 }
@@ -69,22 +75,252 @@ impl Hydrate for SerializableDateTime {
     }
 }
 
+// Put `value` under `key` in the map, or delete `key` if `value` is missing.
+fn put_or_delete<M: MapReconciler, T: Reconcile>(
+    map: &mut M,
+    key: &str,
+    value: &MaybeMissing<T>,
+) -> Result<(), M::Error> {
+    match value {
+        MaybeMissing::Missing => map.delete(key),
+        MaybeMissing::Present(value) => map.put(key, value),
+    }
+}
+
+// SerializableNotes is the notes of a task, stored as an Automerge text
+// object so that concurrent edits to them merge character by character.
+//
+// Reconciling text only applies the edits made to it since it was hydrated,
+// and new text is spliced into whatever text is already stored.  Notes that
+// replace stored ones must therefore be made with `edit`, from the stored
+// notes; see `SerializableTaskList::edit_notes_in`.
+#[derive(Clone, Hydrate)]
+pub(crate) struct SerializableNotes(Text);
+
+impl SerializableNotes {
+    fn new(notes: &str) -> Self {
+        SerializableNotes(Text::with_value(notes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    // Return these notes edited to read `notes`, so that reconciling them
+    // into the document they came from splices in only the difference.
+    fn edit(&self, notes: &str) -> Self {
+        let mut text = self.0.clone();
+        text.update(notes);
+        SerializableNotes(text)
+    }
+}
+
+impl fmt::Debug for SerializableNotes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SerializableNotes")
+            .field(&self.as_str())
+            .finish()
+    }
+}
+
+impl PartialEq for SerializableNotes {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Reconcile for SerializableNotes {
+    type Key<'a> = NoKey;
+
+    fn reconcile<R: Reconciler>(&self, reconciler: R) -> Result<(), R::Error> {
+        self.0.reconcile(reconciler)
+    }
+}
+
 // SerializableTask is a Task that can be stored and retrieved from an
 // Automerge document.
-#[derive(Debug, Clone, PartialEq, Reconcile, Hydrate)]
+#[derive(Debug, Clone, PartialEq, Hydrate)]
 pub(crate) struct SerializableTask {
     pub title: String,
+    pub notes: autosurgeon::hydrate::MaybeMissing<SerializableNotes>,
+    pub tags: autosurgeon::hydrate::MaybeMissing<BTreeMap<String, bool>>,
     pub snoozed: autosurgeon::hydrate::MaybeMissing<SerializableNaiveDate>,
     pub due_date: autosurgeon::hydrate::MaybeMissing<SerializableNaiveDate>,
+    pub priority: autosurgeon::hydrate::MaybeMissing<String>,
+    pub recurrence: autosurgeon::hydrate::MaybeMissing<String>,
     pub completed: autosurgeon::hydrate::MaybeMissing<SerializableDateTime>,
+    pub parent: autosurgeon::hydrate::MaybeMissing<String>,
+    pub list: autosurgeon::hydrate::MaybeMissing<String>,
+    pub extensions: autosurgeon::hydrate::MaybeMissing<BTreeMap<String, String>>,
+}
+
+// Reconcile is implemented by hand so that optional fields that have been
+// cleared are deleted from a long lived document, rather than left behind
+// with their old value.
+impl Reconcile for SerializableTask {
+    type Key<'a> = NoKey;
+
+    fn reconcile<R: Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
+        let mut map = reconciler.map()?;
+        map.put("title", &self.title)?;
+        put_or_delete(&mut map, "notes", &self.notes)?;
+        put_or_delete(&mut map, "tags", &self.tags)?;
+        put_or_delete(&mut map, "snoozed", &self.snoozed)?;
+        put_or_delete(&mut map, "due_date", &self.due_date)?;
+        put_or_delete(&mut map, "priority", &self.priority)?;
+        put_or_delete(&mut map, "recurrence", &self.recurrence)?;
+        put_or_delete(&mut map, "completed", &self.completed)?;
+        put_or_delete(&mut map, "parent", &self.parent)?;
+        put_or_delete(&mut map, "list", &self.list)?;
+        put_or_delete(&mut map, "extensions", &self.extensions)?;
+        Ok(())
+    }
+}
+
+// OrderEntry is a task id stored in a task order list.  Entries are keyed by
+// the id they hold, so reconciling a reordered list deletes and re-inserts
+// the moved ids instead of overwriting every position in place.  Overwritten
+// positions do not merge: two concurrent moves could otherwise drop an id
+// from the list entirely.
+#[derive(Debug, Clone, PartialEq, Hydrate)]
+pub(crate) struct OrderEntry(pub String);
+
+impl Reconcile for OrderEntry {
+    type Key<'a> = Cow<'a, str>;
+
+    fn reconcile<R: Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
+        reconciler.str(&self.0)
+    }
+
+    fn hydrate_key<'a, D: ReadDoc>(
+        doc: &D,
+        obj: &ObjId,
+        prop: Prop<'_>,
+    ) -> Result<LoadKey<Self::Key<'a>>, ReconcileError> {
+        Ok(match doc.get(obj, &prop)? {
+            Some((Value::Scalar(value), _)) => match &*value {
+                ScalarValue::Str(id) => LoadKey::Found(Cow::Owned(id.to_string())),
+                _ => LoadKey::KeyNotFound,
+            },
+            _ => LoadKey::KeyNotFound,
+        })
+    }
+
+    fn key<'a>(&'a self) -> LoadKey<Self::Key<'a>> {
+        LoadKey::Found(Cow::Borrowed(&self.0))
+    }
+}
+
+// SerializableList is a NamedList without its id, which is the key it is
+// stored under.
+#[derive(Debug, Clone, PartialEq, autosurgeon::Reconcile, Hydrate)]
+pub(crate) struct SerializableList {
+    pub name: String,
 }
 
 // SerializableTaskList is a TaskList that can be stored and retrieved from
-// an Automerge document.
-#[derive(Debug, Clone, PartialEq, autosurgeon::Reconcile, autosurgeon::Hydrate)]
+// an Automerge document, along with the lists the tasks are in.  Documents
+// written before there were lists have no `list_map` or `list_order`.
+#[derive(Debug, Clone, PartialEq, Hydrate)]
 pub(crate) struct SerializableTaskList {
     pub task_map: BTreeMap<String, SerializableTask>,
-    pub task_order: Vec<String>,
+    pub task_order: Vec<OrderEntry>,
+    pub list_map: MaybeMissing<BTreeMap<String, SerializableList>>,
+    pub list_order: MaybeMissing<Vec<OrderEntry>>,
+}
+
+// As with SerializableTask, the lists are deleted from the document rather
+// than left behind when there are none to store.
+impl Reconcile for SerializableTaskList {
+    type Key<'a> = NoKey;
+
+    fn reconcile<R: Reconciler>(&self, mut reconciler: R) -> Result<(), R::Error> {
+        let mut map = reconciler.map()?;
+        map.put("task_map", &self.task_map)?;
+        map.put("task_order", &self.task_order)?;
+        put_or_delete(&mut map, "list_map", &self.list_map)?;
+        put_or_delete(&mut map, "list_order", &self.list_order)?;
+        Ok(())
+    }
+}
+
+// Return the lists stored in `list_map` and `list_order`, in order.  As with
+// tasks, lists deleted concurrently with a move are skipped, as are all but
+// the first place of a list moved concurrently.
+pub(crate) fn stored_lists(
+    list_map: &MaybeMissing<BTreeMap<String, SerializableList>>,
+    list_order: &MaybeMissing<Vec<OrderEntry>>,
+) -> Vec<NamedList> {
+    let (MaybeMissing::Present(list_map), MaybeMissing::Present(list_order)) =
+        (list_map, list_order)
+    else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::new();
+    list_order
+        .iter()
+        .filter_map(|OrderEntry(id)| {
+            let list = list_map.get(id)?;
+            let id: ListId = id.parse().ok()?;
+            seen.insert(id).then(|| NamedList {
+                id,
+                name: list.name.clone(),
+            })
+        })
+        .collect()
+}
+
+// Return the `list_map` and `list_order` that store `lists`.
+pub(crate) fn lists_to_store(
+    lists: &[NamedList],
+) -> (BTreeMap<String, SerializableList>, Vec<OrderEntry>) {
+    let list_map = lists
+        .iter()
+        .map(|list| {
+            let name = list.name.clone();
+            (list.id.to_string(), SerializableList { name })
+        })
+        .collect();
+    let list_order = lists
+        .iter()
+        .map(|list| OrderEntry(list.id.to_string()))
+        .collect();
+    (list_map, list_order)
+}
+
+impl SerializableTaskList {
+    // Store `lists` along with the tasks, or no lists if there are none.
+    pub fn with_lists(self, lists: &[NamedList]) -> Self {
+        if lists.is_empty() {
+            return Self {
+                list_map: MaybeMissing::Missing,
+                list_order: MaybeMissing::Missing,
+                ..self
+            };
+        }
+        let (list_map, list_order) = lists_to_store(lists);
+        Self {
+            list_map: MaybeMissing::Present(list_map),
+            list_order: MaybeMissing::Present(list_order),
+            ..self
+        }
+    }
+
+    pub fn lists(&self) -> Vec<NamedList> {
+        stored_lists(&self.list_map, &self.list_order)
+    }
+
+    // Make the notes of each task edits of those stored in `doc`, so that
+    // reconciling this list into `doc` changes only what differs.
+    pub fn edit_notes_in<D: ReadDoc>(&mut self, doc: &D) -> Result<(), HydrateError> {
+        let stored: MaybeMissing<BTreeMap<String, SerializableTask>> =
+            autosurgeon::hydrate_prop(doc, automerge::ROOT, "task_map")?;
+        let stored = to_option(stored).unwrap_or_default();
+        for (id, task) in &mut self.task_map {
+            task.edit_notes_of(stored.get(id));
+        }
+        Ok(())
+    }
 }
 
 // A SerializableTask can be created from a Task.
@@ -92,20 +328,98 @@ impl From<Task> for SerializableTask {
     fn from(value: Task) -> Self {
         Self {
             title: value.title().into(),
+            // Notes are left out when empty, as most tasks have none.
+            notes: to_maybe(
+                Some(value.notes())
+                    .filter(|notes| !notes.is_empty())
+                    .map(SerializableNotes::new),
+            ),
+            // Tags are the keys of a map, so that concurrently adding and
+            // removing different tags merges.
+            tags: to_maybe(
+                Some(value.tags())
+                    .filter(|tags| !tags.is_empty())
+                    .map(|tags| tags.iter().map(|tag| (tag.clone(), true)).collect()),
+            ),
             snoozed: to_maybe(value.snoozed().map(SerializableNaiveDate)),
             due_date: to_maybe(value.due().map(SerializableNaiveDate)),
+            priority: to_maybe(value.priority().map(|priority| priority.to_string())),
+            // The rule is one string, so concurrent edits of it keep one
+            // whole rule rather than mixing parts of two.
+            recurrence: to_maybe(value.recurrence().map(ToString::to_string)),
             completed: to_maybe(value.completed().map(SerializableDateTime)),
+            parent: to_maybe(value.parent().map(|parent| parent.to_string())),
+            // Tasks in the default list name no list, as they did before
+            // there were other lists.
+            list: to_maybe(
+                Some(value.list())
+                    .filter(|list| !list.is_default())
+                    .map(|list| list.to_string()),
+            ),
+            // Most tasks have no extensions; leave the map out rather than
+            // store an empty one for each.
+            extensions: to_maybe(Some(value.extensions().clone()).filter(|e| !e.is_empty())),
+        }
+    }
+}
+
+impl SerializableTask {
+    // Make the notes edits of the notes of `stored`, the task as it is in
+    // the document this will be reconciled into, or new text if it has none.
+    pub fn edit_notes_of(&mut self, stored: Option<&SerializableTask>) {
+        let MaybeMissing::Present(notes) = &self.notes else {
+            return;
+        };
+        let notes = match stored.map(|stored| &stored.notes) {
+            Some(MaybeMissing::Present(stored)) => stored.edit(notes.as_str()),
+            _ => SerializableNotes::new(notes.as_str()),
+        };
+        self.notes = MaybeMissing::Present(notes);
+    }
+
+    // Convert to a Task.  The id is not stored in the task itself; it is the
+    // key the task is stored under.
+    pub fn into_task(self, id: TaskId) -> Task {
+        let mut task = Task::new(
+            id,
+            self.title,
+            to_option(self.snoozed).map(|v| v.0),
+            to_option(self.due_date).map(|v| v.0),
+            to_option(self.completed).map(|v| v.0),
+        );
+        if let MaybeMissing::Present(notes) = &self.notes {
+            task.set_notes(notes.as_str().to_string());
         }
+        // A priority this version does not know, written by a later one, is
+        // read as none.
+        task.set_priority(to_option(self.priority).and_then(|priority| priority.parse().ok()));
+        task.set_recurrence(to_option(self.recurrence).and_then(|rule| rule.parse().ok()));
+        task.set_tags(
+            to_option(self.tags)
+                .unwrap_or_default()
+                .into_keys()
+                .collect(),
+        );
+        // A parent id that does not parse is treated like one whose task was
+        // deleted: the task is top-level.
+        task.set_parent(to_option(self.parent).and_then(|parent| parent.parse().ok()));
+        task.set_list(
+            to_option(self.list)
+                .and_then(|list| list.parse().ok())
+                .unwrap_or_default(),
+        );
+        *task.extensions_mut() = to_option(self.extensions).unwrap_or_default();
+        task
     }
 }
 
 // A SerializableTaskList can be created from a TaskList.
 impl From<TaskList> for SerializableTaskList {
     fn from(task_list: TaskList) -> Self {
-        let task_order: Vec<String> = task_list
+        let task_order: Vec<OrderEntry> = task_list
             .tasks
             .iter()
-            .map(|task| task.id().to_string())
+            .map(|task| OrderEntry(task.id().to_string()))
             .collect();
         let task_map: BTreeMap<String, SerializableTask> = task_list
             .tasks
@@ -115,6 +429,8 @@ impl From<TaskList> for SerializableTaskList {
         Self {
             task_map,
             task_order,
+            list_map: MaybeMissing::Missing,
+            list_order: MaybeMissing::Missing,
         }
     }
 }
@@ -126,8 +442,11 @@ impl From<SerializableTaskList> for TaskList {
         let tasks: Vec<Task> = value
             .task_order
             .iter()
-            .filter_map(|id| {
-                let task = value.task_map.get(id).unwrap();
+            .filter_map(|OrderEntry(id)| {
+                // Due to CRDT merges an id may remain in the order after a
+                // concurrent delete removed it from the map.  Such a task is
+                // deleted.
+                let task = value.task_map.get(id)?;
                 let id = Uuid::parse_str(id).unwrap();
                 if !seen.insert(id) {
                     // Due to CRDT merges an item may appear in multiple places
@@ -135,13 +454,7 @@ impl From<SerializableTaskList> for TaskList {
                     // the first.
                     return None;
                 }
-                Some(Task::new(
-                    id.into(),
-                    task.title.clone(),
-                    to_option(task.snoozed).map(|v| v.0),
-                    to_option(task.due_date).map(|v| v.0),
-                    to_option(task.completed).map(|v| v.0),
-                ))
+                Some(task.clone().into_task(id.into()))
             })
             .collect();
         TaskList { tasks }
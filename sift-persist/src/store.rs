@@ -1,15 +1,65 @@
+use std::path::Path;
+
+use anyhow::bail;
+use automerge::{sync, ChangeHash};
+
 use super::{Task, TaskId};
+use crate::file::Access;
+use crate::list::{ListId, NamedList};
+use crate::transform::Passphrase;
+use crate::tree::{task_tree, TaskNode};
 
 pub trait Transaction {
     fn get_task(&self, id: &TaskId) -> anyhow::Result<Task>;
 
+    /// Replace a task already in the store.  The task keeps its place in
+    /// the tree whatever [`Task::parent`] says; use
+    /// [`Transaction::move_task`] to move it.
     fn put_task(&mut self, task: &Task) -> anyhow::Result<()>;
 
+    /// Delete a task.  Its subtasks are not deleted; they become top-level.
     fn delete_task(&mut self, id: &TaskId) -> anyhow::Result<()>;
 
-    fn insert_task(&mut self, previous: Option<&TaskId>, task: &Task) -> anyhow::Result<()>;
+    /// Insert a task as a subtask of `parent`, or top-level if `None`, just
+    /// after its sibling `previous`, or first if `None`.
+    fn insert_task(
+        &mut self,
+        parent: Option<&TaskId>,
+        previous: Option<&TaskId>,
+        task: &Task,
+    ) -> anyhow::Result<()>;
+
+    /// Move a task, with its subtasks, as in [`Transaction::insert_task`].
+    ///
+    /// Returns an error if `parent` is the task or one of its subtasks.
+    fn move_task(
+        &mut self,
+        parent: Option<&TaskId>,
+        previous: Option<&TaskId>,
+        task: &TaskId,
+    ) -> anyhow::Result<()>;
+
+    /// Move a task to the top of `list`.  Its subtasks are not moved with
+    /// it; move them too, or they become top-level tasks of the old list.
+    fn move_task_to_list(&mut self, list: &ListId, id: &TaskId) -> anyhow::Result<()> {
+        let mut task = self.get_task(id)?;
+        task.set_list(*list);
+        self.put_task(&task)?;
+        self.move_task(None, None, id)
+    }
+
+    /// Add `list` just after the list `previous`, or first if `None`.
+    fn create_list(&mut self, previous: Option<&ListId>, list: &NamedList) -> anyhow::Result<()>;
+
+    fn rename_list(&mut self, id: &ListId, name: &str) -> anyhow::Result<()>;
 
-    fn move_task(&mut self, previous: Option<&TaskId>, task: &TaskId) -> anyhow::Result<()>;
+    /// Delete a list and the tasks in it.
+    ///
+    /// Returns an error for the default list, which cannot be deleted.
+    fn delete_list(&mut self, id: &ListId) -> anyhow::Result<()>;
+
+    /// Move a list just after the list `previous`, or first if `None`.
+    fn move_list(&mut self, previous: Option<&ListId>, id: &ListId) -> anyhow::Result<()>;
 
     // Commit and consume the transaction.
     //
@@ -19,10 +69,113 @@ pub trait Transaction {
 }
 
 pub trait Store {
+    /// Open a store from the file at `path`.  The store remembers `path` as
+    /// the destination for [`Store::flush`].
+    ///
+    /// With [`Access::ReadWrite`] the file is locked against other processes
+    /// until the store is dropped, and opening fails with
+    /// [`crate::Error::Locked`] if another process holds the lock.
+    ///
+    /// Opening an encrypted file fails with
+    /// [`crate::Error::PassphraseRequired`]; use
+    /// [`Store::open_with_passphrase`] instead.
+    fn open(path: &Path, access: Access) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::open_with_passphrase(path, access, None)
+    }
+
+    /// Like [`Store::open`], but with the passphrase for an encrypted file.
+    /// A store given a passphrase encrypts the file when it saves it, even if
+    /// the file was not encrypted before.
+    fn open_with_passphrase(
+        path: &Path,
+        access: Access,
+        passphrase: Option<Passphrase>,
+    ) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+
+    /// Open a store from the file at `path` for reading and writing.
+    fn load(path: &Path) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Self::open(path, Access::ReadWrite)
+    }
+
+    /// Whether the store was opened with [`Access::ReadOnly`], in which case
+    /// saving fails.
+    fn is_read_only(&self) -> bool;
+
+    /// Save the store to the file at `path`.  The store remembers `path` as
+    /// the destination for [`Store::flush`].
+    fn save(&mut self, path: &Path) -> anyhow::Result<()>;
+
+    /// Save the store to the file it was last loaded from or saved to.
+    ///
+    /// Returns an error if the store has never been associated with a file.
+    fn flush(&mut self) -> anyhow::Result<()>;
+
+    /// Merge changes made by other processes to the file the store was last
+    /// loaded from or saved to, returning whether the store changed.
+    ///
+    /// Undo and redo history is discarded when the store changes, since
+    /// undoing past a reload would revert the other processes' changes too.
+    fn reload(&mut self) -> anyhow::Result<bool>;
+
+    /// Return the current point in the store's history, to look back at or
+    /// apply changes as of later with [`Store::list_tasks_at`] and
+    /// [`Store::merge_at`].  The heads are kept when the store is saved and
+    /// loaded.
+    fn heads(&mut self) -> Vec<ChangeHash>;
+
     fn get_task(&self, id: &TaskId) -> anyhow::Result<Task>;
 
     fn list_tasks(&self) -> anyhow::Result<Vec<Task>>;
 
+    /// List the lists of tasks in order, the default list included.
+    fn lists(&self) -> anyhow::Result<Vec<NamedList>>;
+
+    /// List the tasks as a tree of subtasks.  See [`crate::task_tree`] for
+    /// how tasks with missing or looping parents are placed.
+    fn list_task_tree(&self) -> anyhow::Result<Vec<TaskNode>> {
+        Ok(task_tree(self.list_tasks()?))
+    }
+
+    /// List the tasks as they were when the store was at `heads`.
+    ///
+    /// Returns an error if the store's history does not hold `heads`.
+    fn list_tasks_at(&mut self, heads: &[ChangeHash]) -> anyhow::Result<Vec<Task>>;
+
+    /// Make the changes `callback` makes as if they were made when the store
+    /// was at `heads`, then merge them with everything done since, as one
+    /// undoable change.  Sync tools use this to apply changes made elsewhere
+    /// since the last sync without overwriting concurrent local changes;
+    /// conflicting edits of one field are resolved as in any Automerge merge.
+    ///
+    /// With empty `heads`, the changes are made to the store as it is, since
+    /// changes made to an empty history could not be merged.
+    fn merge_at<F>(&mut self, heads: &[ChangeHash], callback: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&mut dyn Transaction) -> anyhow::Result<()>;
+
+    /// Return the next message of an Automerge sync session with a peer,
+    /// or `None` if the peer has everything this store can send for now.
+    /// [`crate::sync_with_peer`] runs a whole session.
+    fn generate_sync_message(&mut self, state: &mut sync::State) -> Option<sync::Message>;
+
+    /// Apply a message from a peer in an Automerge sync session.
+    ///
+    /// As with [`Store::reload`], undo and redo history is discarded if the
+    /// message changes the store.
+    fn receive_sync_message(
+        &mut self,
+        state: &mut sync::State,
+        message: sync::Message,
+    ) -> anyhow::Result<()>;
+
     fn undo(&mut self) -> anyhow::Result<()>;
 
     fn redo(&mut self) -> anyhow::Result<()>;
@@ -40,19 +193,76 @@ pub trait Store {
     }
 }
 
+// Return the index in the flat task order `order` at which a task goes to
+// come just after `previous` among the children of `parent`.  Without a
+// `previous`, the task goes first among its siblings: just after the parent,
+// or before that if a sibling already is.
+fn insertion_index<T: PartialEq>(
+    order: &[T],
+    parent_of: impl Fn(&T) -> Option<T>,
+    parent: Option<&T>,
+    previous: Option<&T>,
+) -> usize {
+    if let Some(index) = previous.and_then(|previous| order.iter().position(|id| id == previous)) {
+        return index + 1;
+    }
+    let after_parent = parent
+        .and_then(|parent| order.iter().position(|id| id == parent))
+        .map_or(0, |index| index + 1);
+    order
+        .iter()
+        .position(|id| parent_of(id).as_ref() == parent)
+        .map_or(after_parent, |index| index.min(after_parent))
+}
+
+// Fail if moving `id` under `parent` would make it its own ancestor.
+fn check_not_within<T: PartialEq>(
+    parent: Option<&T>,
+    id: &T,
+    parent_of: impl Fn(&T) -> Option<T>,
+) -> anyhow::Result<()> {
+    let Some(parent) = parent else {
+        return Ok(());
+    };
+    if parent == id {
+        bail!("cannot move a task under itself");
+    }
+    let mut ancestor = parent_of(parent);
+    // Bound the walk in case a merge has left the parents looping.
+    for _ in 0..10_000 {
+        match ancestor {
+            Some(ref other) if other == id => bail!("cannot move a task under its own subtask"),
+            Some(ref other) => ancestor = parent_of(other),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
 pub(crate) mod memory {
     use std::fmt;
     use std::path::Path;
 
     use anyhow::bail;
+    use automerge::sync::{self, SyncDoc};
+    use automerge::transaction::CommitOptions;
+    use automerge::{AutoCommit, ChangeHash};
 
-    use super::{Store, Transaction};
-    use crate::{load_tasks, save_tasks, Task, TaskId, TaskList};
+    use super::{check_not_within, insertion_index, Store, Transaction};
+    use crate::document::{hydrate_tasks, Error, SaveOptions};
+    use crate::file::{Access, StoreFile};
+    use crate::list::{self, with_default, ListId, NamedList};
+    use crate::serialization::SerializableTaskList;
+    use crate::transform::Passphrase;
+    use crate::{Task, TaskId, TaskList};
 
     #[derive(Default, Clone)]
     struct Record {
         tasks: im::HashMap<TaskId, Task>,
         order: im::Vector<TaskId>,
+        // The lists as stored in the document, which leaves out the default
+        // list until it is renamed or moved.
+        lists: Vec<NamedList>,
     }
 
     impl Record {
@@ -69,28 +279,37 @@ pub(crate) mod memory {
                 "MemoryStore::put called with task not in the order list"
             );
             match self.tasks.entry(task.id()) {
-                Occupied(mut entry) => *entry.get_mut() = task.clone(),
+                Occupied(mut entry) => {
+                    let mut task = task.clone();
+                    task.set_parent(entry.get().parent());
+                    *entry.get_mut() = task;
+                }
                 Vacant(_) => {
                     panic!("MemoryStore::put called with task not in the tasks map")
                 }
             }
         }
 
-        fn insert_task(&mut self, previous: Option<&TaskId>, task: &Task) {
-            let index = if let Some(previous) = previous {
-                self.order
-                    .index_of(previous)
-                    .map_or(0, |index| index.saturating_add(1))
-            } else {
-                0
-            };
+        fn parent_of(&self, id: &TaskId) -> Option<TaskId> {
+            self.tasks.get(id).and_then(Task::parent)
+        }
+
+        fn index_for(&self, parent: Option<&TaskId>, previous: Option<&TaskId>) -> usize {
+            let order: Vec<TaskId> = self.order.iter().copied().collect();
+            insertion_index(&order, |id| self.parent_of(id), parent, previous)
+        }
+
+        fn insert_task(&mut self, parent: Option<&TaskId>, previous: Option<&TaskId>, task: &Task) {
+            let index = self.index_for(parent, previous);
 
             debug_assert!(
                 !self.order.contains(&task.id()),
                 "MemoryStore::insert called with task.id already in the order list"
             );
             self.order.insert(index, task.id());
-            self.tasks.entry(task.id()).or_insert(task.clone());
+            let mut task = task.clone();
+            task.set_parent(parent.copied());
+            self.tasks.entry(task.id()).or_insert(task);
         }
 
         fn delete_task(&mut self, id: &TaskId) {
@@ -98,17 +317,20 @@ pub(crate) mod memory {
             self.tasks.retain(|key, _| key != id);
         }
 
-        fn move_task(&mut self, previous: Option<&TaskId>, id: &TaskId) {
+        fn move_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            id: &TaskId,
+        ) -> anyhow::Result<()> {
+            check_not_within(parent, id, |id| self.parent_of(id))?;
             self.order.retain(|other| other != id);
-
-            let index = if let Some(previous_id) = previous {
-                self.order.index_of(previous_id).map(|index| index + 1)
-            } else {
-                None
-            }
-            .unwrap_or(0);
-
+            let index = self.index_for(parent, previous);
             self.order.insert(index, *id);
+            if let Some(task) = self.tasks.get_mut(id) {
+                task.set_parent(parent.copied());
+            }
+            Ok(())
         }
 
         fn list_tasks(&self) -> Vec<Task> {
@@ -122,6 +344,49 @@ pub(crate) mod memory {
                 })
                 .collect()
         }
+
+        fn from_document(document: &AutoCommit) -> anyhow::Result<Self> {
+            let stored: SerializableTaskList =
+                autosurgeon::hydrate(document).map_err(Error::Hydrate)?;
+            let lists = stored.lists();
+            let tasks = TaskList::from(stored).tasks;
+            let order: im::Vector<TaskId> = tasks.iter().map(Task::id).collect();
+            let tasks: im::HashMap<TaskId, Task> =
+                tasks.into_iter().map(|task| (task.id(), task)).collect();
+            Ok(Record {
+                tasks,
+                order,
+                lists,
+            })
+        }
+
+        fn lists(&self) -> Vec<NamedList> {
+            with_default(self.lists.clone())
+        }
+
+        fn edit_lists(
+            &mut self,
+            edit: impl FnOnce(&mut Vec<NamedList>) -> anyhow::Result<()>,
+        ) -> anyhow::Result<()> {
+            let mut lists = self.lists();
+            edit(&mut lists)?;
+            self.lists = lists;
+            Ok(())
+        }
+
+        fn delete_list(&mut self, id: &ListId) -> anyhow::Result<()> {
+            self.edit_lists(|lists| list::remove(lists, id))?;
+            let tasks: Vec<TaskId> = self
+                .order
+                .iter()
+                .filter(|task| self.tasks.get(*task).is_some_and(|task| task.list() == *id))
+                .copied()
+                .collect();
+            for task in &tasks {
+                self.delete_task(task);
+            }
+            Ok(())
+        }
     }
 
     #[derive(Default)]
@@ -129,6 +394,11 @@ pub(crate) mod memory {
         current: Record,
         undo_stack: Vec<Record>,
         redo_stack: Vec<Record>,
+        // The Automerge document `current` was loaded from.  Every change to
+        // `current` is reconciled into it as a new Automerge change, so the
+        // full history is written out on save.
+        document: AutoCommit,
+        file: StoreFile,
     }
 
     impl fmt::Debug for MemoryStore {
@@ -137,6 +407,8 @@ pub(crate) mod memory {
                 .field("current", &"Record")
                 .field("undo_stack", &format_args!("Vec<Record>"))
                 .field("redo_stack", &format_args!("Vec<Record>"))
+                .field("document", &format_args!("AutoCommit"))
+                .field("file", &self.file)
                 .finish()
         }
     }
@@ -168,19 +440,55 @@ pub(crate) mod memory {
             Ok(())
         }
 
-        fn insert_task(&mut self, previous: Option<&TaskId>, task: &Task) -> anyhow::Result<()> {
-            self.store.insert_task(previous, task);
+        fn insert_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            task: &Task,
+        ) -> anyhow::Result<()> {
+            self.store.insert_task(parent, previous, task);
             Ok(())
         }
 
-        fn move_task(&mut self, previous: Option<&TaskId>, task: &TaskId) -> anyhow::Result<()> {
-            self.store.move_task(previous, task);
-            Ok(())
+        fn move_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            task: &TaskId,
+        ) -> anyhow::Result<()> {
+            self.store.move_task(parent, previous, task)
+        }
+
+        fn create_list(
+            &mut self,
+            previous: Option<&ListId>,
+            list: &NamedList,
+        ) -> anyhow::Result<()> {
+            self.store
+                .current
+                .edit_lists(|lists| list::create(lists, previous, list))
+        }
+
+        fn rename_list(&mut self, id: &ListId, name: &str) -> anyhow::Result<()> {
+            self.store
+                .current
+                .edit_lists(|lists| list::rename(lists, id, name))
+        }
+
+        fn delete_list(&mut self, id: &ListId) -> anyhow::Result<()> {
+            self.store.current.delete_list(id)
+        }
+
+        fn move_list(&mut self, previous: Option<&ListId>, id: &ListId) -> anyhow::Result<()> {
+            self.store
+                .current
+                .edit_lists(|lists| list::reorder(lists, previous, id))
         }
 
         fn commit(self: Box<Self>) -> anyhow::Result<()> {
             self.store.undo_stack.push(self.start);
-            Ok(())
+            self.store.redo_stack.clear();
+            self.store.reconcile_document("Edit tasks")
         }
     }
 
@@ -190,34 +498,31 @@ pub(crate) mod memory {
             Self::default()
         }
 
-        pub fn load(path: &Path) -> Result<MemoryStore, anyhow::Error> {
-            let tasks = load_tasks(path)?;
-
-            let order: im::Vector<TaskId> = tasks.tasks.iter().map(Task::id).collect();
-            let tasks: im::HashMap<TaskId, Task> = tasks
-                .tasks
-                .into_iter()
-                .map(|task| (task.id(), task))
-                .collect();
+        pub fn set_save_options(&mut self, save_options: SaveOptions) {
+            self.file.set_save_options(save_options);
+        }
 
-            Ok(MemoryStore {
-                current: Record { tasks, order },
-                undo_stack: Vec::new(),
-                redo_stack: Vec::new(),
-            })
+        /// Encrypt the file with `passphrase` from the next save on, or with
+        /// `None`, save it unencrypted.
+        pub fn set_passphrase(&mut self, passphrase: Option<Passphrase>) {
+            self.file.set_passphrase(passphrase);
         }
 
-        pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
-            let tasks = TaskList {
-                tasks: self
-                    .current
-                    .order
-                    .iter()
-                    .map(|id| self.current.tasks.get(id).unwrap())
-                    .cloned()
-                    .collect(),
-            };
-            save_tasks(path, &tasks)?;
+        // Record the current state of the store as a new change in the
+        // Automerge document.  Reconciliation only writes the differences,
+        // so unchanged tasks do not grow the history.
+        fn reconcile_document(&mut self, message: &str) -> anyhow::Result<()> {
+            let mut tasks = SerializableTaskList::from(TaskList {
+                tasks: self.current.list_tasks(),
+            })
+            .with_lists(&self.current.lists);
+            tasks.edit_notes_in(&self.document)?;
+            autosurgeon::reconcile(&mut self.document, &tasks)?;
+            self.document.commit_with(
+                CommitOptions::default()
+                    .with_message(message)
+                    .with_time(chrono::Utc::now().timestamp()),
+            );
             Ok(())
         }
 
@@ -225,16 +530,17 @@ pub(crate) mod memory {
             self.current.delete_task(id);
         }
 
-        fn insert_task(&mut self, previous: Option<&TaskId>, task: &Task) {
-            let saved = self.current.clone();
-            self.current.insert_task(previous, task);
-            self.undo_stack.push(saved);
+        fn insert_task(&mut self, parent: Option<&TaskId>, previous: Option<&TaskId>, task: &Task) {
+            self.current.insert_task(parent, previous, task);
         }
 
-        fn move_task(&mut self, previous: Option<&TaskId>, task: &TaskId) {
-            let saved = self.current.clone();
-            self.current.move_task(previous, task);
-            self.undo_stack.push(saved);
+        fn move_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            task: &TaskId,
+        ) -> anyhow::Result<()> {
+            self.current.move_task(parent, previous, task)
         }
 
         fn get_task_impl(&self, id: &TaskId) -> anyhow::Result<Task> {
@@ -247,6 +553,49 @@ pub(crate) mod memory {
     }
 
     impl Store for MemoryStore {
+        fn open_with_passphrase(
+            path: &Path,
+            access: Access,
+            passphrase: Option<Passphrase>,
+        ) -> Result<MemoryStore, anyhow::Error> {
+            let (file, document) = StoreFile::open(path, access, passphrase)?;
+
+            Ok(MemoryStore {
+                current: Record::from_document(&document)?,
+                undo_stack: Vec::new(),
+                redo_stack: Vec::new(),
+                document,
+                file,
+            })
+        }
+
+        fn is_read_only(&self) -> bool {
+            self.file.is_read_only()
+        }
+
+        fn save(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+            self.file.save(path, &mut self.document)?;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> anyhow::Result<()> {
+            self.file.flush(&mut self.document)
+        }
+
+        fn reload(&mut self) -> anyhow::Result<bool> {
+            if !self.file.reload(&mut self.document)? {
+                return Ok(false);
+            }
+            self.current = Record::from_document(&self.document)?;
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+            Ok(true)
+        }
+
+        fn heads(&mut self) -> Vec<ChangeHash> {
+            self.document.get_heads()
+        }
+
         fn get_task(&self, id: &TaskId) -> anyhow::Result<Task> {
             self.get_task_impl(id)
         }
@@ -256,11 +605,69 @@ pub(crate) mod memory {
             Ok(tasks)
         }
 
+        fn lists(&self) -> anyhow::Result<Vec<NamedList>> {
+            Ok(self.current.lists())
+        }
+
+        fn list_tasks_at(&mut self, heads: &[ChangeHash]) -> anyhow::Result<Vec<Task>> {
+            if heads.is_empty() {
+                return Ok(Vec::new());
+            }
+            let past = self.document.fork_at(heads)?;
+            Ok(hydrate_tasks(&past)?.tasks)
+        }
+
+        fn merge_at<F>(&mut self, heads: &[ChangeHash], callback: F) -> anyhow::Result<()>
+        where
+            F: FnOnce(&mut dyn Transaction) -> anyhow::Result<()>,
+        {
+            if heads.is_empty() {
+                return self.with_transaction(callback);
+            }
+            // Make the changes to a store holding the document as it was,
+            // which reconciles them into a fork of it.
+            let document = self.document.fork_at(heads)?;
+            let mut past = MemoryStore {
+                current: Record::from_document(&document)?,
+                document,
+                ..MemoryStore::default()
+            };
+            past.with_transaction(callback)?;
+            self.document.merge(&mut past.document)?;
+            let saved =
+                std::mem::replace(&mut self.current, Record::from_document(&self.document)?);
+            self.undo_stack.push(saved);
+            self.redo_stack.clear();
+            Ok(())
+        }
+
+        fn generate_sync_message(&mut self, state: &mut sync::State) -> Option<sync::Message> {
+            self.document.sync().generate_sync_message(state)
+        }
+
+        fn receive_sync_message(
+            &mut self,
+            state: &mut sync::State,
+            message: sync::Message,
+        ) -> anyhow::Result<()> {
+            let start = self.document.get_heads();
+            self.document
+                .sync()
+                .receive_sync_message(state, message)
+                .map_err(Error::SyncApply)?;
+            if self.document.get_heads() != start {
+                self.current = Record::from_document(&self.document)?;
+                self.undo_stack.clear();
+                self.redo_stack.clear();
+            }
+            Ok(())
+        }
+
         fn undo(&mut self) -> anyhow::Result<()> {
             if let Some(record) = self.undo_stack.pop() {
-                self.redo_stack.push(record.clone());
+                self.redo_stack.push(self.current.clone());
                 self.current = record;
-                Ok(())
+                self.reconcile_document("Undo")
             } else {
                 bail!("undo is not available")
             }
@@ -268,8 +675,9 @@ pub(crate) mod memory {
 
         fn redo(&mut self) -> anyhow::Result<()> {
             if let Some(record) = self.redo_stack.pop() {
+                self.undo_stack.push(self.current.clone());
                 self.current = record;
-                Ok(())
+                self.reconcile_document("Redo")
             } else {
                 bail!("redo is not available")
             }
@@ -280,4 +688,991 @@ pub(crate) mod memory {
             Box::new(transaction)
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use chrono::NaiveDate;
+
+        use super::MemoryStore;
+        use crate::document::{hydrate_tasks, save_document, SaveOptions};
+        use crate::{Access, NamedList, Store, Task};
+
+        #[test]
+        fn save_preserves_history() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tasks.sift");
+
+            let task = Task::new(Task::new_id(), "first".to_string(), None, None, None);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            store.save(&path).unwrap();
+            let first_heads = store.document.get_heads();
+            drop(store);
+
+            let mut store = MemoryStore::load(&path).unwrap();
+            let mut edited = task.clone();
+            edited.set_title("second".to_string());
+            store.with_transaction(|txn| txn.put_task(&edited)).unwrap();
+            store.save(&path).unwrap();
+            drop(store);
+
+            let mut store = MemoryStore::load(&path).unwrap();
+            for hash in &first_heads {
+                assert!(store.document.get_change_by_hash(hash).is_some());
+            }
+            assert_eq!(store.list_tasks().unwrap(), vec![edited]);
+        }
+
+        #[test]
+        fn undo_and_redo() {
+            let task = Task::new(Task::new_id(), "before".to_string(), None, None, None);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            let mut edited = task.clone();
+            edited.set_title("after".to_string());
+            store.with_transaction(|txn| txn.put_task(&edited)).unwrap();
+
+            store.undo().unwrap();
+            assert_eq!(store.list_tasks().unwrap(), [task.clone()]);
+            store.undo().unwrap();
+            assert!(store.list_tasks().unwrap().is_empty());
+            assert!(store.undo().is_err());
+
+            store.redo().unwrap();
+            assert_eq!(store.list_tasks().unwrap(), [task.clone()]);
+            store.redo().unwrap();
+            assert_eq!(store.list_tasks().unwrap(), [edited]);
+            assert!(store.redo().is_err());
+
+            // A new change cannot be followed by a redo.
+            store.undo().unwrap();
+            store.with_transaction(|txn| txn.put_task(&task)).unwrap();
+            assert!(store.redo().is_err());
+        }
+
+        #[test]
+        fn reload_merges_changes_from_disk() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tasks.sift");
+            let first = Task::new(Task::new_id(), "first".to_string(), None, None, None);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &first))
+                .unwrap();
+            store.save(&path).unwrap();
+            assert!(!store.reload().unwrap());
+
+            // Another writer, such as a sync tool, adds a task to the file
+            // while the local store edits the first one.
+            let mut other = MemoryStore::open(&path, Access::ReadOnly).unwrap();
+            let second = Task::new(Task::new_id(), "second".to_string(), None, None, None);
+            other
+                .with_transaction(|txn| txn.insert_task(None, Some(&first.id()), &second))
+                .unwrap();
+            save_document(&path, &mut other.document, &SaveOptions::default(), None).unwrap();
+
+            let mut edited = first.clone();
+            edited.set_title("edited".to_string());
+            store.with_transaction(|txn| txn.put_task(&edited)).unwrap();
+
+            assert!(store.reload().unwrap());
+            assert_eq!(store.list_tasks().unwrap(), vec![edited, second]);
+            // Undoing now would also revert the other writer's task.
+            assert!(store.undo_stack.is_empty());
+        }
+
+        #[test]
+        fn cleared_fields_are_removed_from_document() {
+            let snoozed = NaiveDate::from_ymd_opt(2024, 7, 3);
+            let task = Task::new(Task::new_id(), "title".to_string(), snoozed, None, None);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+
+            let mut unsnoozed = task.clone();
+            unsnoozed.set_snoozed(None);
+            store
+                .with_transaction(|txn| txn.put_task(&unsnoozed))
+                .unwrap();
+
+            let tasks = hydrate_tasks(&store.document).unwrap();
+            assert_eq!(tasks.tasks, vec![unsnoozed]);
+        }
+
+        #[test]
+        fn merge_at_keeps_concurrent_changes() {
+            let first = Task::new(Task::new_id(), "first".to_string(), None, None, None);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &first))
+                .unwrap();
+            let heads = store.heads();
+
+            let mut local = first.clone();
+            local.set_title("edited".to_string());
+            store.with_transaction(|txn| txn.put_task(&local)).unwrap();
+
+            let second = Task::new(Task::new_id(), "second".to_string(), None, None, None);
+            let mut remote = first.clone();
+            remote.set_due(NaiveDate::from_ymd_opt(2024, 7, 5));
+            store
+                .merge_at(&heads, |txn| {
+                    txn.put_task(&remote)?;
+                    txn.insert_task(None, Some(&first.id()), &second)
+                })
+                .unwrap();
+
+            let mut both = local.clone();
+            both.set_due(remote.due());
+            assert_eq!(store.list_tasks().unwrap(), vec![both, second]);
+            assert_eq!(store.list_tasks_at(&heads).unwrap(), vec![first]);
+            store.undo().unwrap();
+            assert_eq!(store.list_tasks().unwrap(), vec![local]);
+        }
+
+        #[test]
+        fn lists_are_saved() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tasks.sift");
+            let work = NamedList::new("Work");
+            let mut task = Task::new(Task::new_id(), "report".to_string(), None, None, None);
+            task.set_list(work.id);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| {
+                    txn.create_list(None, &work)?;
+                    txn.insert_task(None, None, &task)
+                })
+                .unwrap();
+            store.save(&path).unwrap();
+            drop(store);
+
+            let mut store = MemoryStore::load(&path).unwrap();
+            let names = |store: &MemoryStore| -> Vec<String> {
+                let lists = store.lists().unwrap();
+                lists.into_iter().map(|list| list.name).collect()
+            };
+            assert_eq!(names(&store), ["Work", "Tasks"]);
+            assert_eq!(store.get_task(&task.id()).unwrap().list(), work.id);
+
+            store
+                .with_transaction(|txn| txn.delete_list(&work.id))
+                .unwrap();
+            assert_eq!(names(&store), ["Tasks"]);
+            assert!(store.list_tasks().unwrap().is_empty());
+            store.undo().unwrap();
+            assert_eq!(names(&store), ["Work", "Tasks"]);
+            assert_eq!(store.list_tasks().unwrap(), vec![task]);
+        }
+
+        #[test]
+        fn put_task_keeps_parent() {
+            let parent = Task::new(Task::new_id(), "parent".to_string(), None, None, None);
+            let child = Task::new(Task::new_id(), "child".to_string(), None, None, None);
+            let mut store = MemoryStore::new();
+            store
+                .with_transaction(|txn| {
+                    txn.insert_task(None, None, &parent)?;
+                    txn.insert_task(Some(&parent.id()), None, &child)
+                })
+                .unwrap();
+
+            // `child` was built without a parent; putting it must not move it.
+            let mut edited = child.clone();
+            edited.set_title("edited".to_string());
+            store.with_transaction(|txn| txn.put_task(&edited)).unwrap();
+            let stored = store.get_task(&child.id()).unwrap();
+            assert_eq!(stored.title(), "edited");
+            assert_eq!(stored.parent(), Some(parent.id()));
+            assert!(store
+                .with_transaction(|txn| txn.move_task(Some(&child.id()), None, &parent.id()))
+                .is_err());
+
+            let tree = store.list_task_tree().unwrap();
+            assert_eq!(tree.len(), 1);
+            assert_eq!(tree[0].children[0].task, stored);
+        }
+    }
+}
+
+pub(crate) mod autocommit {
+    use std::fmt;
+    use std::path::Path;
+
+    use anyhow::{anyhow, bail};
+    use automerge::sync::{self, SyncDoc};
+    use automerge::transaction::{CommitOptions, Transactable};
+    use automerge::{AutoCommit, ChangeHash, ObjId, ObjType, ReadDoc, ScalarValue, Value, ROOT};
+    use autosurgeon::MaybeMissing;
+
+    use super::{check_not_within, insertion_index, Store, Transaction};
+    use crate::document::{hydrate_lists, hydrate_tasks, new_document, Error, SaveOptions};
+    use crate::file::{Access, StoreFile};
+    use crate::list::{self, ListId, NamedList};
+    use crate::serialization::{lists_to_store, to_option, SerializableTask, SerializableTaskList};
+    use crate::transform::Passphrase;
+    use crate::{Task, TaskId, TaskList};
+
+    /// A store that keeps tasks directly in an Automerge document.
+    ///
+    /// Every committed transaction becomes one Automerge change.  Undo and
+    /// redo restore the task list as it was at an earlier point in the
+    /// change graph, recording the restoration as a new change.
+    pub struct AutomergeStore {
+        document: AutoCommit,
+        undo_stack: Vec<Vec<ChangeHash>>,
+        redo_stack: Vec<Vec<ChangeHash>>,
+        file: StoreFile,
+    }
+
+    impl fmt::Debug for AutomergeStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("AutomergeStore")
+                .field("document", &format_args!("AutoCommit"))
+                .field("undo_stack", &self.undo_stack)
+                .field("redo_stack", &self.redo_stack)
+                .field("file", &self.file)
+                .finish()
+        }
+    }
+
+    impl Default for AutomergeStore {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    fn commit_options(message: &str) -> CommitOptions {
+        CommitOptions::default()
+            .with_message(message)
+            .with_time(chrono::Utc::now().timestamp())
+    }
+
+    impl AutomergeStore {
+        #[must_use]
+        pub fn new() -> Self {
+            let mut document = new_document(&TaskList::default())
+                .expect("reconciling an empty task list must succeed");
+            document.commit_with(commit_options("Create task list"));
+            Self::from_document(document, StoreFile::default())
+        }
+
+        fn from_document(document: AutoCommit, file: StoreFile) -> Self {
+            Self {
+                document,
+                undo_stack: Vec::new(),
+                redo_stack: Vec::new(),
+                file,
+            }
+        }
+
+        pub fn set_save_options(&mut self, save_options: SaveOptions) {
+            self.file.set_save_options(save_options);
+        }
+
+        /// Encrypt the file with `passphrase` from the next save on, or with
+        /// `None`, save it unencrypted.
+        pub fn set_passphrase(&mut self, passphrase: Option<Passphrase>) {
+            self.file.set_passphrase(passphrase);
+        }
+
+        fn object(&self, key: &str, expected: ObjType) -> anyhow::Result<ObjId> {
+            match self.document.get(ROOT, key)? {
+                Some((Value::Object(obj_type), id)) if obj_type == expected => Ok(id),
+                _ => bail!("document has no {} {:?}", key, expected),
+            }
+        }
+
+        fn task_map(&self) -> anyhow::Result<ObjId> {
+            self.object("task_map", ObjType::Map)
+        }
+
+        fn task_order(&self) -> anyhow::Result<ObjId> {
+            self.object("task_order", ObjType::List)
+        }
+
+        // Return the ids in the task order list, in order.  An id may appear
+        // more than once after a merge.
+        fn order(&self) -> anyhow::Result<Vec<String>> {
+            let order = self.task_order()?;
+            let mut ids = Vec::new();
+            for index in 0..self.document.length(&order) {
+                match self.document.get(&order, index)? {
+                    Some((Value::Scalar(value), _)) => match &*value {
+                        ScalarValue::Str(id) => ids.push(id.to_string()),
+                        other => bail!("unexpected task order entry {:?}", other),
+                    },
+                    other => bail!("unexpected task order entry {:?}", other),
+                }
+            }
+            Ok(ids)
+        }
+
+        // Return the parent id stored in the task `id`, if any.
+        fn parent_of(&self, id: &str) -> Option<String> {
+            let task_map = self.task_map().ok()?;
+            let (_, task) = self.document.get(&task_map, id).ok()??;
+            match self.document.get(&task, "parent").ok()?? {
+                (Value::Scalar(value), _) => match &*value {
+                    ScalarValue::Str(parent) => Some(parent.to_string()),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+
+        // Return the index in the task order list at which a task goes to
+        // come after `previous` among the children of `parent`.
+        fn index_for(
+            &self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+        ) -> anyhow::Result<usize> {
+            Ok(insertion_index(
+                &self.order()?,
+                |id| self.parent_of(id),
+                parent.map(ToString::to_string).as_ref(),
+                previous.map(ToString::to_string).as_ref(),
+            ))
+        }
+
+        fn remove_from_order(&mut self, id: &TaskId) -> anyhow::Result<()> {
+            let order = self.task_order()?;
+            let id = id.to_string();
+            // Delete from the back so earlier indexes stay valid.
+            for (index, _) in self
+                .order()?
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, other)| **other == id)
+            {
+                self.document.delete(&order, index)?;
+            }
+            Ok(())
+        }
+
+        fn get_task_impl(&self, id: &TaskId) -> anyhow::Result<Task> {
+            let task: MaybeMissing<SerializableTask> = autosurgeon::hydrate_prop(
+                &self.document,
+                self.task_map()?,
+                id.to_string().as_str(),
+            )?;
+            match task {
+                MaybeMissing::Present(task) => Ok(task.into_task(*id)),
+                MaybeMissing::Missing => bail!("task not found"),
+            }
+        }
+
+        fn put_task_impl(&mut self, task: &Task) -> anyhow::Result<()> {
+            let task_map = self.task_map()?;
+            let id = task.id().to_string();
+            let mut serializable = SerializableTask::from(task.clone());
+            let stored: MaybeMissing<SerializableTask> =
+                autosurgeon::hydrate_prop(&self.document, &task_map, id.as_str())?;
+            serializable.edit_notes_of(to_option(stored).as_ref());
+            autosurgeon::reconcile_prop(&mut self.document, task_map, id.as_str(), serializable)?;
+            Ok(())
+        }
+
+        fn put_task(&mut self, task: &Task) -> anyhow::Result<()> {
+            // Like MemoryStore, putting a task that was never inserted is an
+            // error.
+            let stored = self.get_task_impl(&task.id())?;
+            let mut task = task.clone();
+            task.set_parent(stored.parent());
+            self.put_task_impl(&task)
+        }
+
+        fn insert_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            task: &Task,
+        ) -> anyhow::Result<()> {
+            let index = self.index_for(parent, previous)?;
+            let order = self.task_order()?;
+            self.document.insert(&order, index, task.id().to_string())?;
+            let mut task = task.clone();
+            task.set_parent(parent.copied());
+            self.put_task_impl(&task)
+        }
+
+        fn delete_task(&mut self, id: &TaskId) -> anyhow::Result<()> {
+            self.remove_from_order(id)?;
+            let task_map = self.task_map()?;
+            self.document.delete(&task_map, id.to_string())?;
+            Ok(())
+        }
+
+        fn move_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            id: &TaskId,
+        ) -> anyhow::Result<()> {
+            check_not_within(
+                parent.map(ToString::to_string).as_ref(),
+                &id.to_string(),
+                |id| self.parent_of(id),
+            )?;
+            let mut task = self.get_task_impl(id)?;
+            self.remove_from_order(id)?;
+            let index = self.index_for(parent, previous)?;
+            let order = self.task_order()?;
+            self.document.insert(&order, index, id.to_string())?;
+            if task.parent().as_ref() != parent {
+                task.set_parent(parent.copied());
+                self.put_task_impl(&task)?;
+            }
+            Ok(())
+        }
+
+        // Apply `edit` to the lists and store the result.
+        fn edit_lists(
+            &mut self,
+            edit: impl FnOnce(&mut Vec<NamedList>) -> anyhow::Result<()>,
+        ) -> anyhow::Result<()> {
+            let mut lists = hydrate_lists(&self.document)?;
+            edit(&mut lists)?;
+            let (list_map, list_order) = lists_to_store(&lists);
+            autosurgeon::reconcile_prop(&mut self.document, ROOT, "list_map", list_map)?;
+            autosurgeon::reconcile_prop(&mut self.document, ROOT, "list_order", list_order)?;
+            Ok(())
+        }
+
+        fn delete_list(&mut self, id: &ListId) -> anyhow::Result<()> {
+            self.edit_lists(|lists| list::remove(lists, id))?;
+            for task in hydrate_tasks(&self.document)?.tasks {
+                if task.list() == *id {
+                    self.delete_task(&task.id())?;
+                }
+            }
+            Ok(())
+        }
+
+        // Make the task list look as it did at `heads`, recording the
+        // difference as a new change.
+        fn restore(&mut self, heads: &[ChangeHash], message: &str) -> anyhow::Result<()> {
+            let past = self.document.fork_at(heads)?;
+            let mut tasks: SerializableTaskList = autosurgeon::hydrate(&past)?;
+            tasks.edit_notes_in(&self.document)?;
+            autosurgeon::reconcile(&mut self.document, &tasks)?;
+            self.document.commit_with(commit_options(message));
+            Ok(())
+        }
+    }
+
+    struct AutomergeTransaction<'a> {
+        store: &'a mut AutomergeStore,
+        start: Vec<ChangeHash>,
+        messages: Vec<String>,
+        committed: bool,
+    }
+
+    impl<'a> AutomergeTransaction<'a> {
+        fn new(store: &'a mut AutomergeStore) -> Self {
+            let start = store.document.get_heads();
+            Self {
+                store,
+                start,
+                messages: Vec::new(),
+                committed: false,
+            }
+        }
+    }
+
+    impl Transaction for AutomergeTransaction<'_> {
+        fn get_task(&self, id: &TaskId) -> anyhow::Result<Task> {
+            self.store.get_task_impl(id)
+        }
+
+        fn put_task(&mut self, task: &Task) -> anyhow::Result<()> {
+            self.messages.push(format!("Edit task {}", task.id()));
+            self.store.put_task(task)
+        }
+
+        fn delete_task(&mut self, id: &TaskId) -> anyhow::Result<()> {
+            self.messages.push(format!("Delete task {}", id));
+            self.store.delete_task(id)
+        }
+
+        fn insert_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            task: &Task,
+        ) -> anyhow::Result<()> {
+            self.messages.push(format!("Insert task {}", task.id()));
+            self.store.insert_task(parent, previous, task)
+        }
+
+        fn move_task(
+            &mut self,
+            parent: Option<&TaskId>,
+            previous: Option<&TaskId>,
+            task: &TaskId,
+        ) -> anyhow::Result<()> {
+            self.messages.push(format!("Move task {}", task));
+            self.store.move_task(parent, previous, task)
+        }
+
+        fn create_list(
+            &mut self,
+            previous: Option<&ListId>,
+            list: &NamedList,
+        ) -> anyhow::Result<()> {
+            self.messages.push(format!("Create list {}", list.id));
+            self.store
+                .edit_lists(|lists| list::create(lists, previous, list))
+        }
+
+        fn rename_list(&mut self, id: &ListId, name: &str) -> anyhow::Result<()> {
+            self.messages.push(format!("Rename list {}", id));
+            self.store.edit_lists(|lists| list::rename(lists, id, name))
+        }
+
+        fn delete_list(&mut self, id: &ListId) -> anyhow::Result<()> {
+            self.messages.push(format!("Delete list {}", id));
+            self.store.delete_list(id)
+        }
+
+        fn move_list(&mut self, previous: Option<&ListId>, id: &ListId) -> anyhow::Result<()> {
+            self.messages.push(format!("Move list {}", id));
+            self.store
+                .edit_lists(|lists| list::reorder(lists, previous, id))
+        }
+
+        fn commit(mut self: Box<Self>) -> anyhow::Result<()> {
+            self.committed = true;
+            let message = self.messages.join("\n");
+            let start = std::mem::take(&mut self.start);
+            if self
+                .store
+                .document
+                .commit_with(commit_options(&message))
+                .is_some()
+            {
+                self.store.undo_stack.push(start);
+                self.store.redo_stack.clear();
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for AutomergeTransaction<'_> {
+        fn drop(&mut self) {
+            if !self.committed {
+                self.store.document.rollback();
+            }
+        }
+    }
+
+    impl Store for AutomergeStore {
+        fn open_with_passphrase(
+            path: &Path,
+            access: Access,
+            passphrase: Option<Passphrase>,
+        ) -> Result<AutomergeStore, anyhow::Error> {
+            let (file, document) = StoreFile::open(path, access, passphrase)?;
+            // Fail now, rather than on first use, if the document does not
+            // hold a task list.
+            hydrate_tasks(&document)?;
+            Ok(Self::from_document(document, file))
+        }
+
+        fn is_read_only(&self) -> bool {
+            self.file.is_read_only()
+        }
+
+        fn save(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+            self.file.save(path, &mut self.document)?;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> anyhow::Result<()> {
+            self.file.flush(&mut self.document)
+        }
+
+        fn reload(&mut self) -> anyhow::Result<bool> {
+            if !self.file.reload(&mut self.document)? {
+                return Ok(false);
+            }
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+            Ok(true)
+        }
+
+        fn heads(&mut self) -> Vec<ChangeHash> {
+            self.document.get_heads()
+        }
+
+        fn get_task(&self, id: &TaskId) -> anyhow::Result<Task> {
+            self.get_task_impl(id)
+        }
+
+        fn list_tasks(&self) -> anyhow::Result<Vec<Task>> {
+            Ok(hydrate_tasks(&self.document)?.tasks)
+        }
+
+        fn lists(&self) -> anyhow::Result<Vec<NamedList>> {
+            Ok(hydrate_lists(&self.document)?)
+        }
+
+        fn list_tasks_at(&mut self, heads: &[ChangeHash]) -> anyhow::Result<Vec<Task>> {
+            if heads.is_empty() {
+                return Ok(Vec::new());
+            }
+            let past = self.document.fork_at(heads)?;
+            Ok(hydrate_tasks(&past)?.tasks)
+        }
+
+        fn merge_at<F>(&mut self, heads: &[ChangeHash], callback: F) -> anyhow::Result<()>
+        where
+            F: FnOnce(&mut dyn Transaction) -> anyhow::Result<()>,
+        {
+            if heads.is_empty() {
+                return self.with_transaction(callback);
+            }
+            let mut past = Self::from_document(self.document.fork_at(heads)?, StoreFile::default());
+            past.with_transaction(callback)?;
+            let start = self.document.get_heads();
+            self.document.merge(&mut past.document)?;
+            if self.document.get_heads() != start {
+                self.undo_stack.push(start);
+                self.redo_stack.clear();
+            }
+            Ok(())
+        }
+
+        fn generate_sync_message(&mut self, state: &mut sync::State) -> Option<sync::Message> {
+            self.document.sync().generate_sync_message(state)
+        }
+
+        fn receive_sync_message(
+            &mut self,
+            state: &mut sync::State,
+            message: sync::Message,
+        ) -> anyhow::Result<()> {
+            let start = self.document.get_heads();
+            self.document
+                .sync()
+                .receive_sync_message(state, message)
+                .map_err(Error::SyncApply)?;
+            if self.document.get_heads() != start {
+                self.undo_stack.clear();
+                self.redo_stack.clear();
+            }
+            Ok(())
+        }
+
+        fn undo(&mut self) -> anyhow::Result<()> {
+            let heads = self
+                .undo_stack
+                .pop()
+                .ok_or_else(|| anyhow!("undo is not available"))?;
+            let current = self.document.get_heads();
+            self.restore(&heads, "Undo")?;
+            self.redo_stack.push(current);
+            Ok(())
+        }
+
+        fn redo(&mut self) -> anyhow::Result<()> {
+            let heads = self
+                .redo_stack
+                .pop()
+                .ok_or_else(|| anyhow!("redo is not available"))?;
+            let current = self.document.get_heads();
+            self.restore(&heads, "Redo")?;
+            self.undo_stack.push(current);
+            Ok(())
+        }
+
+        fn transaction<'a>(&'a mut self) -> Box<dyn Transaction + 'a> {
+            Box::new(AutomergeTransaction::new(self))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::AutomergeStore;
+        use crate::{Access, ListId, NamedList, Store, Task};
+
+        fn new_task(title: &str) -> Task {
+            Task::new(Task::new_id(), title.to_string(), None, None, None)
+        }
+
+        fn titles(store: &AutomergeStore) -> Vec<String> {
+            store
+                .list_tasks()
+                .unwrap()
+                .iter()
+                .map(|task| task.title().to_string())
+                .collect()
+        }
+
+        #[test]
+        fn insert_move_and_delete() {
+            let (one, two, three) = (new_task("one"), new_task("two"), new_task("three"));
+            let mut store = AutomergeStore::new();
+            store
+                .with_transaction(|txn| {
+                    txn.insert_task(None, None, &one)?;
+                    txn.insert_task(None, Some(&one.id()), &two)?;
+                    txn.insert_task(None, Some(&two.id()), &three)
+                })
+                .unwrap();
+            assert_eq!(titles(&store), ["one", "two", "three"]);
+
+            store
+                .with_transaction(|txn| txn.move_task(None, None, &three.id()))
+                .unwrap();
+            assert_eq!(titles(&store), ["three", "one", "two"]);
+
+            store
+                .with_transaction(|txn| txn.delete_task(&one.id()))
+                .unwrap();
+            assert_eq!(titles(&store), ["three", "two"]);
+            assert!(store.get_task(&one.id()).is_err());
+        }
+
+        #[test]
+        fn subtasks() {
+            let (one, two, three) = (new_task("one"), new_task("two"), new_task("three"));
+            let mut store = AutomergeStore::new();
+            store
+                .with_transaction(|txn| {
+                    txn.insert_task(None, None, &one)?;
+                    txn.insert_task(None, Some(&one.id()), &two)?;
+                    txn.insert_task(Some(&one.id()), None, &three)
+                })
+                .unwrap();
+            assert_eq!(titles(&store), ["one", "three", "two"]);
+
+            // Move `two` under `one`, after `three`.
+            store
+                .with_transaction(|txn| {
+                    txn.move_task(Some(&one.id()), Some(&three.id()), &two.id())
+                })
+                .unwrap();
+            let tree = store.list_task_tree().unwrap();
+            assert_eq!(tree.len(), 1);
+            let children: Vec<_> = tree[0]
+                .children
+                .iter()
+                .map(|node| node.task.title())
+                .collect();
+            assert_eq!(children, ["three", "two"]);
+            assert_eq!(store.get_task(&two.id()).unwrap().parent(), Some(one.id()));
+
+            assert!(store
+                .with_transaction(|txn| txn.move_task(Some(&two.id()), None, &one.id()))
+                .is_err());
+
+            // Back to the top level, first.
+            store
+                .with_transaction(|txn| txn.move_task(None, None, &two.id()))
+                .unwrap();
+            assert_eq!(titles(&store), ["two", "one", "three"]);
+            assert_eq!(store.get_task(&two.id()).unwrap().parent(), None);
+            store.undo().unwrap();
+            assert_eq!(store.get_task(&two.id()).unwrap().parent(), Some(one.id()));
+        }
+
+        #[test]
+        fn notes_are_edited_in_place() {
+            let mut store = AutomergeStore::new();
+            let task = new_task("shopping");
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            let set_notes = |store: &mut AutomergeStore, notes: &str| {
+                store
+                    .with_transaction(|txn| {
+                        let mut task = txn.get_task(&task.id())?;
+                        task.set_notes(notes.to_string());
+                        txn.put_task(&task)
+                    })
+                    .unwrap();
+            };
+            set_notes(&mut store, "milk");
+            set_notes(&mut store, "milk\neggs");
+            assert_eq!(store.get_task(&task.id()).unwrap().notes(), "milk\neggs");
+            store.undo().unwrap();
+            assert_eq!(store.get_task(&task.id()).unwrap().notes(), "milk");
+            store.redo().unwrap();
+            assert_eq!(store.get_task(&task.id()).unwrap().notes(), "milk\neggs");
+            set_notes(&mut store, "");
+            assert_eq!(store.get_task(&task.id()).unwrap().notes(), "");
+            store.undo().unwrap();
+            assert_eq!(store.get_task(&task.id()).unwrap().notes(), "milk\neggs");
+        }
+
+        #[test]
+        fn lists() {
+            let names = |store: &AutomergeStore| -> Vec<String> {
+                let lists = store.lists().unwrap();
+                lists.into_iter().map(|list| list.name).collect()
+            };
+            let mut store = AutomergeStore::new();
+            assert_eq!(names(&store), ["Tasks"]);
+
+            let (work, home) = (NamedList::new("Work"), NamedList::new("Home"));
+            store
+                .with_transaction(|txn| {
+                    txn.create_list(Some(&ListId::DEFAULT), &work)?;
+                    txn.create_list(None, &home)
+                })
+                .unwrap();
+            assert_eq!(names(&store), ["Home", "Tasks", "Work"]);
+            store
+                .with_transaction(|txn| {
+                    txn.rename_list(&ListId::DEFAULT, "Inbox")?;
+                    txn.move_list(None, &work.id)
+                })
+                .unwrap();
+            assert_eq!(names(&store), ["Work", "Home", "Inbox"]);
+
+            let mut report = new_task("report");
+            report.set_list(work.id);
+            let other = new_task("other");
+            store
+                .with_transaction(|txn| {
+                    txn.insert_task(None, None, &report)?;
+                    txn.insert_task(None, None, &other)?;
+                    txn.move_task_to_list(&home.id, &other.id())
+                })
+                .unwrap();
+            assert_eq!(store.get_task(&other.id()).unwrap().list(), home.id);
+
+            assert!(store
+                .with_transaction(|txn| txn.delete_list(&ListId::DEFAULT))
+                .is_err());
+            store
+                .with_transaction(|txn| txn.delete_list(&work.id))
+                .unwrap();
+            assert_eq!(names(&store), ["Home", "Inbox"]);
+            assert_eq!(titles(&store), ["other"]);
+            store.undo().unwrap();
+            assert_eq!(names(&store), ["Work", "Home", "Inbox"]);
+            assert_eq!(titles(&store), ["other", "report"]);
+        }
+
+        #[test]
+        fn undo_and_redo() {
+            let task = new_task("before");
+            let mut store = AutomergeStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            let mut edited = task.clone();
+            edited.set_title("after".to_string());
+            store.with_transaction(|txn| txn.put_task(&edited)).unwrap();
+
+            store.undo().unwrap();
+            assert_eq!(titles(&store), ["before"]);
+            store.undo().unwrap();
+            assert!(titles(&store).is_empty());
+            assert!(store.undo().is_err());
+
+            store.redo().unwrap();
+            assert_eq!(titles(&store), ["before"]);
+            store.redo().unwrap();
+            assert_eq!(titles(&store), ["after"]);
+            assert!(store.redo().is_err());
+        }
+
+        #[test]
+        fn failed_transaction_is_rolled_back() {
+            let task = new_task("title");
+            let mut store = AutomergeStore::new();
+            let result = store.with_transaction(|txn| {
+                txn.insert_task(None, None, &task)?;
+                anyhow::bail!("callback failed")
+            });
+            assert!(result.is_err());
+            assert!(titles(&store).is_empty());
+            assert!(store.undo().is_err());
+        }
+
+        #[test]
+        fn commits_are_described() {
+            let task = new_task("title");
+            let mut store = AutomergeStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            let change = store.document.get_last_local_change().unwrap();
+            assert_eq!(
+                change.message().map(String::as_str),
+                Some(format!("Insert task {}", task.id()).as_str())
+            );
+        }
+
+        #[test]
+        fn save_and_load() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tasks.sift");
+            let task = new_task("title");
+            let mut store = AutomergeStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            store.save(&path).unwrap();
+            drop(store);
+
+            let store = AutomergeStore::load(&path).unwrap();
+            assert_eq!(store.list_tasks().unwrap(), vec![task]);
+        }
+
+        #[test]
+        fn locked_file_opens_read_only() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tasks.sift");
+            let mut store = AutomergeStore::new();
+            store.save(&path).unwrap();
+
+            let error = AutomergeStore::load(&path).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<crate::Error>(),
+                Some(crate::Error::Locked { .. })
+            ));
+
+            let mut reader = AutomergeStore::open(&path, Access::ReadOnly).unwrap();
+            assert!(reader.is_read_only());
+            assert!(reader.flush().is_err());
+            // The writer still holds the lock and can keep saving.
+            store.flush().unwrap();
+        }
+
+        #[test]
+        fn merge_at_keeps_concurrent_changes() {
+            let task = new_task("one");
+            let mut store = AutomergeStore::new();
+            store
+                .with_transaction(|txn| txn.insert_task(None, None, &task))
+                .unwrap();
+            let heads = store.heads();
+
+            let mut local = task.clone();
+            local.set_due(chrono::NaiveDate::from_ymd_opt(2024, 7, 5));
+            store.with_transaction(|txn| txn.put_task(&local)).unwrap();
+
+            let mut remote = task.clone();
+            remote.set_title("renamed".to_string());
+            store.merge_at(&heads, |txn| txn.put_task(&remote)).unwrap();
+
+            let mut both = local.clone();
+            both.set_title("renamed".to_string());
+            assert_eq!(store.list_tasks().unwrap(), vec![both]);
+            assert_eq!(store.list_tasks_at(&heads).unwrap(), vec![task]);
+            store.undo().unwrap();
+            assert_eq!(store.list_tasks().unwrap(), vec![local]);
+        }
+    }
 }
@@ -0,0 +1,71 @@
+//! Tags on tasks, such as `work` or `errands`, for grouping tasks across
+//! lists.
+//!
+//! Tags can be typed into a title as words starting with `#`: "Call Bob
+//! #work" is the task "Call Bob" tagged `work`.  A `#` followed by digits
+//! alone, as in "Fix bug #12", is part of the title rather than a tag.
+
+use std::collections::BTreeSet;
+
+// Return the tag named by `word`, if it is one.
+fn tag_of(word: &str) -> Option<&str> {
+    let tag = word.strip_prefix('#')?;
+    let valid = !tag.is_empty()
+        && !tag.chars().all(|c| c.is_ascii_digit())
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '/'));
+    valid.then_some(tag)
+}
+
+/// Split the `#tag` words out of `text`, returning the rest as a title and
+/// the tags.  Runs of whitespace in the title become one space.
+#[must_use]
+pub fn split_tags(text: &str) -> (String, BTreeSet<String>) {
+    let mut title = Vec::new();
+    let mut tags = BTreeSet::new();
+    for word in text.split_whitespace() {
+        match tag_of(word) {
+            Some(tag) => {
+                tags.insert(tag.to_string());
+            }
+            None => title.push(word),
+        }
+    }
+    (title.join(" "), tags)
+}
+
+/// Return `title` followed by `tags` as `#tag` words, the inverse of
+/// [`split_tags`].
+#[must_use]
+pub fn join_tags(title: &str, tags: &BTreeSet<String>) -> String {
+    let mut text = title.to_string();
+    for tag in tags {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push('#');
+        text.push_str(tag);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join_tags, split_tags};
+
+    #[test]
+    fn splits_tags_from_titles() {
+        let (title, tags) = split_tags("Call  Bob #work about #12 #phone-calls #");
+        assert_eq!(title, "Call Bob about #12 #");
+        assert_eq!(
+            tags.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["phone-calls", "work"]
+        );
+        assert_eq!(
+            join_tags(&title, &tags),
+            "Call Bob about #12 # #phone-calls #work"
+        );
+        assert_eq!(split_tags(&join_tags(&title, &tags)), (title, tags));
+    }
+}
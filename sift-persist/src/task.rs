@@ -1,8 +1,12 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
+use std::str::FromStr;
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use uuid::Uuid;
 
+use crate::{tag, ListId, Recurrence};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TaskId(Uuid);
 
@@ -18,6 +22,65 @@ impl From<Uuid> for TaskId {
     }
 }
 
+impl FromStr for TaskId {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(TaskId)
+    }
+}
+
+impl From<TaskId> for Uuid {
+    fn from(value: TaskId) -> Self {
+        value.0
+    }
+}
+
+impl TaskId {
+    /// When the task was created, from the timestamp in its id.  Ids that
+    /// were derived from another app's, as for imported tasks, have none.
+    #[must_use]
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        let (seconds, nanoseconds) = self.0.get_timestamp()?.to_unix();
+        DateTime::from_timestamp(i64::try_from(seconds).ok()?, nanoseconds)
+    }
+}
+
+/// How important a task is, for tasks that have a priority at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown priority `{0}`")]
+pub struct ParsePriorityError(String);
+
+impl FromStr for Priority {
+    type Err = ParsePriorityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Priority::Low),
+            "medium" => Ok(Priority::Medium),
+            "high" => Ok(Priority::High),
+            _ => Err(ParsePriorityError(s.to_string())),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Task {
     /// Task identifier.
@@ -26,6 +89,12 @@ pub struct Task {
     /// Title of the task.
     title: String,
 
+    /// Free-form notes on the task, empty if there are none.
+    notes: String,
+
+    /// Tags on the task; see [`crate::split_tags`].
+    tags: BTreeSet<String>,
+
     /// Snooze date of the task.  Tasks with a snoozed date do not appear
     // by default if the date is before the current date.
     snoozed: Option<NaiveDate>,
@@ -33,9 +102,27 @@ pub struct Task {
     /// Due date of the task.
     due: Option<NaiveDate>,
 
+    /// Priority of the task, if it has one.
+    priority: Option<Priority>,
+
+    /// How the task repeats, if it does.
+    recurrence: Option<Recurrence>,
+
     /// Completion date and time of the task.  If `None`, the task is
     /// incomplete.
     completed: Option<DateTime<Utc>>,
+
+    /// The task this is a subtask of, if any.  Set by the store when the
+    /// task is inserted or moved; see [`crate::Transaction::move_task`].
+    parent: Option<TaskId>,
+
+    /// The list the task is in.
+    list: ListId,
+
+    /// Data from other formats that Sift has no field for, kept so that it
+    /// can be written back out.  Keys are prefixed with the format's name,
+    /// e.g. `todotxt.projects`.
+    extensions: BTreeMap<String, String>,
 }
 
 impl Task {
@@ -50,9 +137,16 @@ impl Task {
         Self {
             id,
             title,
+            notes: String::new(),
+            tags: BTreeSet::new(),
             snoozed,
             due,
+            priority: None,
+            recurrence: None,
             completed,
+            parent: None,
+            list: ListId::DEFAULT,
+            extensions: BTreeMap::new(),
         }
     }
 
@@ -73,6 +167,28 @@ impl Task {
         &self.title
     }
 
+    #[must_use]
+    pub fn notes(&self) -> &str {
+        &self.notes
+    }
+
+    #[must_use]
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    #[must_use]
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// The title followed by the tags as `#tag` words, for editing both at
+    /// once with [`Task::set_title_with_tags`].
+    #[must_use]
+    pub fn title_with_tags(&self) -> String {
+        tag::join_tags(&self.title, &self.tags)
+    }
+
     #[must_use]
     pub fn snoozed(&self) -> Option<NaiveDate> {
         self.snoozed
@@ -83,11 +199,36 @@ impl Task {
         self.due
     }
 
+    #[must_use]
+    pub fn priority(&self) -> Option<Priority> {
+        self.priority
+    }
+
+    #[must_use]
+    pub fn recurrence(&self) -> Option<&Recurrence> {
+        self.recurrence.as_ref()
+    }
+
     #[must_use]
     pub fn completed(&self) -> Option<DateTime<Utc>> {
         self.completed
     }
 
+    #[must_use]
+    pub fn parent(&self) -> Option<TaskId> {
+        self.parent
+    }
+
+    #[must_use]
+    pub fn list(&self) -> ListId {
+        self.list
+    }
+
+    #[must_use]
+    pub fn extensions(&self) -> &BTreeMap<String, String> {
+        &self.extensions
+    }
+
     #[must_use]
     pub fn is_completed(&self) -> bool {
         self.completed.is_some()
@@ -97,6 +238,21 @@ impl Task {
         self.title = title;
     }
 
+    pub fn set_tags(&mut self, tags: BTreeSet<String>) {
+        self.tags = tags;
+    }
+
+    /// Set the title and tags from `text`, in which tags are `#tag` words.
+    pub fn set_title_with_tags(&mut self, text: &str) {
+        let (title, tags) = tag::split_tags(text);
+        self.title = title;
+        self.tags = tags;
+    }
+
+    pub fn set_notes(&mut self, notes: String) {
+        self.notes = notes;
+    }
+
     pub fn set_snoozed(&mut self, snoozed: Option<NaiveDate>) {
         self.snoozed = snoozed;
     }
@@ -108,4 +264,54 @@ impl Task {
     pub fn set_due(&mut self, date: Option<NaiveDate>) {
         self.due = date;
     }
+
+    pub fn set_priority(&mut self, priority: Option<Priority>) {
+        self.priority = priority;
+    }
+
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) {
+        self.recurrence = recurrence;
+    }
+
+    /// The occurrence of a repeating task after this one, which is completed
+    /// at `completed`: a copy with a new id, due on the next date the rule
+    /// gives, and neither snoozed nor completed.  Returns `None` if the task
+    /// does not repeat, or its next date would be past the last there is.
+    #[must_use]
+    pub fn next_occurrence<Tz: TimeZone>(&self, completed: &DateTime<Tz>) -> Option<Task> {
+        let recurrence = self.recurrence.as_ref()?;
+        Some(Task {
+            id: Task::new_id(),
+            due: Some(recurrence.next_due(self.due, completed)?),
+            snoozed: None,
+            completed: None,
+            recurrence: Some(recurrence.anchored(self.due)),
+            ..self.clone()
+        })
+    }
+
+    pub(crate) fn set_parent(&mut self, parent: Option<TaskId>) {
+        self.parent = parent;
+    }
+
+    /// Put the task in `list`, keeping its place among its siblings.
+    /// [`crate::Transaction::move_task_to_list`] moves it to the top instead.
+    pub fn set_list(&mut self, list: ListId) {
+        self.list = list;
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.extensions
+    }
+
+    /// Copy the extensions of `other` that this task does not have.  Used
+    /// when a task read from a format without extensions replaces a stored
+    /// one, so that the stored task's extensions survive.
+    pub fn keep_extensions_of(&mut self, other: &Task) {
+        for (key, value) in &other.extensions {
+            self.extensions
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
 }
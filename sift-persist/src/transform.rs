@@ -0,0 +1,452 @@
+//! Compression and encryption of chunk payloads.
+//!
+//! A transformed chunk is flagged by a lowercase third letter in its type,
+//! the bit PNG reserves, so `AMRG` is stored as `AMrG`.  Builds that predate
+//! transforms see an unknown critical chunk and refuse the file rather than
+//! misreading it.  The payload of a transformed chunk starts with a header:
+//!
+//! ```text
+//! u8        compression: 0 none, 1 deflate, 2 zstd
+//! u8        encryption: 0 none, 1 XChaCha20-Poly1305 with an Argon2id key
+//! [16]u8    salt            }
+//! u32 x 3   Argon2id memory  } only when encrypted
+//!           cost, iterations }
+//!           and parallelism  }
+//! [24]u8    nonce           }
+//! ```
+//!
+//! followed by the data, compressed first and then encrypted.  The chunk type
+//! and everything in the header before the nonce are authenticated along with
+//! the data.  The chunk's CRC covers the stored bytes, so corruption is still
+//! caught before any decryption is attempted.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::container::Error;
+
+/// How chunk payloads are compressed when saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+    Zstd,
+}
+
+impl Compression {
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            2 => Ok(Compression::Zstd),
+            _ => Err(Error::UnknownCompression(byte)),
+        }
+    }
+}
+
+/// A passphrase from which the key encrypting a task file is derived.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Passphrase(String);
+
+impl From<String> for Passphrase {
+    fn from(passphrase: String) -> Self {
+        Self(passphrase)
+    }
+}
+
+// Keep passphrases out of logs.
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Passphrase(..)")
+    }
+}
+
+const ENCRYPTION_NONE: u8 = 0;
+const ENCRYPTION_XCHACHA20POLY1305_ARGON2ID: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+// The Argon2id cost parameters, stored with each encrypted chunk so they can
+// be raised later without breaking existing files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KdfParams {
+    memory_cost: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_cost: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    // The most any cost read from a file may be, as a multiple of the
+    // default, so that a corrupt file cannot demand gigabytes of memory or
+    // minutes of work before the passphrase is checked.
+    const MAX_FACTOR: u32 = 8;
+
+    fn within_limits(&self) -> bool {
+        let limit = Self::default();
+        self.memory_cost <= limit.memory_cost * Self::MAX_FACTOR
+            && self.iterations <= limit.iterations * Self::MAX_FACTOR
+            && self.parallelism <= limit.parallelism * Self::MAX_FACTOR
+    }
+}
+
+#[derive(Clone)]
+struct Key {
+    salt: [u8; SALT_LEN],
+    params: KdfParams,
+    cipher: XChaCha20Poly1305,
+}
+
+impl Key {
+    fn derive(
+        passphrase: &Passphrase,
+        salt: [u8; SALT_LEN],
+        params: KdfParams,
+    ) -> Result<Self, Error> {
+        let argon2_params = argon2::Params::new(
+            params.memory_cost,
+            params.iterations,
+            params.parallelism,
+            Some(32),
+        )
+        .map_err(Error::KeyDerivation)?;
+        let argon2 = argon2::Argon2::new(
+            argon2::Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            argon2_params,
+        );
+        let mut key = [0; 32];
+        argon2
+            .hash_password_into(passphrase.0.as_bytes(), &salt, &mut key)
+            .map_err(Error::KeyDerivation)?;
+        Ok(Self {
+            salt,
+            params,
+            cipher: XChaCha20Poly1305::new(&key.into()),
+        })
+    }
+}
+
+/// The passphrase for an encrypted file, along with the key last derived from
+/// it.  Deriving a key is deliberately slow, so the key read from a file is
+/// reused, with its salt, when the file is saved again.
+#[derive(Clone)]
+pub(crate) struct Encryption {
+    passphrase: Passphrase,
+    params: KdfParams,
+    key: Option<Key>,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    pub(crate) fn new(passphrase: Passphrase) -> Self {
+        Self {
+            passphrase,
+            params: KdfParams::default(),
+            key: None,
+        }
+    }
+
+    // Encryption with cheap key derivation, since the default parameters take
+    // a noticeable fraction of a second per key.
+    #[cfg(test)]
+    pub(crate) fn cheap(passphrase: &str) -> Self {
+        Self {
+            params: KdfParams {
+                memory_cost: 8,
+                iterations: 1,
+                parallelism: 1,
+            },
+            ..Self::new(Passphrase::from(passphrase.to_string()))
+        }
+    }
+
+    // The key for a chunk written with `salt` and `params`.
+    fn key_for(&mut self, salt: [u8; SALT_LEN], params: KdfParams) -> Result<&Key, Error> {
+        let cached = matches!(&self.key, Some(key) if key.salt == salt && key.params == params);
+        if !cached {
+            self.key = Some(Key::derive(&self.passphrase, salt, params)?);
+        }
+        Ok(self.key.as_ref().expect("key was just derived"))
+    }
+
+    // The key for a new chunk.
+    fn current_key(&mut self) -> Result<&Key, Error> {
+        if self.key.is_none() {
+            let mut salt = [0; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            self.key = Some(Key::derive(&self.passphrase, salt, self.params)?);
+        }
+        Ok(self.key.as_ref().expect("key was just derived"))
+    }
+}
+
+/// Return whether a stored chunk type flags a transformed payload.
+pub(crate) fn is_transformed(chunk_type: [u8; 4]) -> bool {
+    chunk_type[2].is_ascii_lowercase()
+}
+
+/// Return the type under which a chunk is stored when transformed.
+pub(crate) fn transformed_type(chunk_type: [u8; 4]) -> [u8; 4] {
+    let mut stored = chunk_type;
+    stored[2] = stored[2].to_ascii_lowercase();
+    stored
+}
+
+/// Return the type of a chunk stored as `stored`.
+pub(crate) fn plain_type(stored: [u8; 4]) -> [u8; 4] {
+    let mut chunk_type = stored;
+    chunk_type[2] = chunk_type[2].to_ascii_uppercase();
+    chunk_type
+}
+
+/// Transform the payload of a chunk stored as `stored_type`.
+pub(crate) fn encode(
+    stored_type: [u8; 4],
+    data: &[u8],
+    compression: Compression,
+    encryption: Option<&mut Encryption>,
+) -> Result<Vec<u8>, Error> {
+    let data = compress(data, compression)?;
+    let mut payload = vec![compression.to_byte()];
+    let Some(encryption) = encryption else {
+        payload.push(ENCRYPTION_NONE);
+        payload.extend_from_slice(&data);
+        return Ok(payload);
+    };
+
+    payload.push(ENCRYPTION_XCHACHA20POLY1305_ARGON2ID);
+    let key = encryption.current_key()?;
+    payload.extend_from_slice(&key.salt);
+    for value in [
+        key.params.memory_cost,
+        key.params.iterations,
+        key.params.parallelism,
+    ] {
+        payload.extend_from_slice(&value.to_be_bytes());
+    }
+    let aad = [&stored_type[..], &payload].concat();
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key
+        .cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &data,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| Error::Encrypt)?;
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&ciphertext);
+    Ok(payload)
+}
+
+/// Undo [`encode`] for the payload of a chunk stored as `stored_type`.
+pub(crate) fn decode(
+    stored_type: [u8; 4],
+    payload: &[u8],
+    encryption: Option<&mut Encryption>,
+) -> Result<Vec<u8>, Error> {
+    let mut reader = Reader(payload);
+    let compression = Compression::from_byte(reader.byte()?)?;
+    match reader.byte()? {
+        ENCRYPTION_NONE => decompress(reader.0, compression),
+        ENCRYPTION_XCHACHA20POLY1305_ARGON2ID => {
+            let encryption = encryption.ok_or(Error::PassphraseRequired)?;
+            let salt = reader.array()?;
+            let params = KdfParams {
+                memory_cost: u32::from_be_bytes(reader.array()?),
+                iterations: u32::from_be_bytes(reader.array()?),
+                parallelism: u32::from_be_bytes(reader.array()?),
+            };
+            if !params.within_limits() {
+                return Err(Error::InvalidTransformHeader);
+            }
+            let aad = [&stored_type[..], &payload[..payload.len() - reader.0.len()]].concat();
+            let nonce: [u8; NONCE_LEN] = reader.array()?;
+            let key = encryption.key_for(salt, params)?;
+            let data = key
+                .cipher
+                .decrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: reader.0,
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| Error::Decrypt)?;
+            decompress(&data, compression)
+        }
+        byte => Err(Error::UnknownEncryption(byte)),
+    }
+}
+
+// Reads fields from the front of a transform header.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if self.0.len() < N {
+            return Err(Error::InvalidTransformHeader);
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().expect("split at N bytes"))
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.array::<1>()?[0])
+    }
+}
+
+fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>, Error> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).map_err(Error::Compress)?;
+            encoder.finish().map_err(Error::Compress)
+        }
+        Compression::Zstd => zstd::encode_all(data, 0).map_err(Error::Compress),
+    }
+}
+
+fn decompress(data: &[u8], compression: Compression) -> Result<Vec<u8>, Error> {
+    let reader: Box<dyn Read + '_> = match compression {
+        Compression::None => return Ok(data.to_vec()),
+        Compression::Deflate => Box::new(flate2::read::DeflateDecoder::new(data)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(data).map_err(Error::Decompress)?),
+    };
+    // Hold decompressed chunks to the same limit as stored ones, so a small
+    // corrupt or hostile file can not exhaust memory.
+    let limit = crate::container::CHUNK_SIZE_LIMIT;
+    let mut decompressed = Vec::new();
+    reader
+        .take(u64::from(limit) + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(Error::Decompress)?;
+    if decompressed.len() > usize::try_from(limit).expect("u32 must fit in a usize") {
+        return Err(Error::DecompressedChunkTooLarge(limit));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPE: [u8; 4] = *b"AMrG";
+
+    #[test]
+    fn chunk_types_flag_transforms() {
+        assert_eq!(transformed_type(*b"AMRG"), TYPE);
+        assert!(is_transformed(TYPE));
+        assert!(!is_transformed(*b"AMRG"));
+        assert_eq!(plain_type(TYPE), *b"AMRG");
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        let data = b"buy milk ".repeat(100);
+        for compression in [Compression::None, Compression::Deflate, Compression::Zstd] {
+            let payload = encode(TYPE, &data, compression, None).unwrap();
+            assert_eq!(decode(TYPE, &payload, None).unwrap(), data);
+            if compression != Compression::None {
+                assert!(payload.len() < data.len());
+            }
+        }
+    }
+
+    #[test]
+    fn encryption_round_trips() {
+        let data = b"call the customer";
+        let mut writer = Encryption::cheap("correct horse");
+        let payload = encode(TYPE, data, Compression::Zstd, Some(&mut writer)).unwrap();
+        assert!(!payload.windows(data.len()).any(|window| window == data));
+
+        let mut reader = Encryption::cheap("correct horse");
+        assert_eq!(decode(TYPE, &payload, Some(&mut reader)).unwrap(), data);
+    }
+
+    #[test]
+    fn encrypted_payloads_need_the_passphrase() {
+        let mut writer = Encryption::cheap("correct horse");
+        let payload = encode(TYPE, b"secret", Compression::None, Some(&mut writer)).unwrap();
+
+        assert!(matches!(
+            decode(TYPE, &payload, None),
+            Err(Error::PassphraseRequired)
+        ));
+        assert!(matches!(
+            decode(
+                TYPE,
+                &payload,
+                Some(&mut Encryption::cheap("battery staple"))
+            ),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn headers_and_types_are_authenticated() {
+        let mut writer = Encryption::cheap("correct horse");
+        let mut payload = encode(TYPE, b"secret", Compression::None, Some(&mut writer)).unwrap();
+        assert!(matches!(
+            decode(*b"XXrX", &payload, Some(&mut writer)),
+            Err(Error::Decrypt)
+        ));
+
+        // Claim the data is deflated.
+        payload[0] = Compression::Deflate.to_byte();
+        assert!(matches!(
+            decode(TYPE, &payload, Some(&mut writer)),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn excessive_key_derivation_costs_are_rejected() {
+        let mut writer = Encryption::cheap("correct horse");
+        let mut payload = encode(TYPE, b"secret", Compression::None, Some(&mut writer)).unwrap();
+
+        // Ask for 4 TiB of memory, after the compression and encryption
+        // bytes and the salt.
+        let memory_cost = 2 + SALT_LEN;
+        payload[memory_cost..memory_cost + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            decode(TYPE, &payload, Some(&mut writer)),
+            Err(Error::InvalidTransformHeader)
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! Tasks arranged as a tree of subtasks.
+//!
+//! A store keeps one flat order of tasks and, in each task, the id of its
+//! parent.  The children of a task are the tasks naming it as their parent,
+//! in the order they appear in the flat order.  Keeping the links in the
+//! tasks rather than nesting lists in the document means a concurrent move
+//! and edit of a task merge like any other change to it.
+
+use std::collections::HashMap;
+
+use crate::{Task, TaskId};
+
+/// A task and its subtasks, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskNode {
+    pub task: Task,
+    pub children: Vec<TaskNode>,
+}
+
+impl TaskNode {
+    /// Call `f` with this task and each of its descendants, parents before
+    /// their children.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a TaskNode)) {
+        f(self);
+        for child in &self.children {
+            child.walk(f);
+        }
+    }
+
+    /// Return the node for `id` among `nodes` and their descendants.
+    #[must_use]
+    pub fn find<'a>(nodes: &'a [TaskNode], id: &TaskId) -> Option<&'a TaskNode> {
+        nodes.iter().find_map(|node| {
+            if node.task.id() == *id {
+                Some(node)
+            } else {
+                Self::find(&node.children, id)
+            }
+        })
+    }
+}
+
+/// Arrange `tasks`, given in stored order, as a tree.
+///
+/// A task is top-level if its parent is not among `tasks`, as after a
+/// concurrent delete of the parent, or if following its parents leads back
+/// to it, as after concurrent moves of two tasks under each other.
+#[must_use]
+pub fn task_tree(tasks: Vec<Task>) -> Vec<TaskNode> {
+    let index: HashMap<TaskId, usize> = tasks
+        .iter()
+        .enumerate()
+        .map(|(position, task)| (task.id(), position))
+        .collect();
+    let parent_of = |position: usize| {
+        tasks[position]
+            .parent()
+            .and_then(|parent| index.get(&parent).copied())
+    };
+    let on_cycle = |position: usize| {
+        let mut ancestor = parent_of(position);
+        // A chain longer than the number of tasks must repeat a task.
+        for _ in 0..tasks.len() {
+            match ancestor {
+                Some(other) if other == position => return true,
+                Some(other) => ancestor = parent_of(other),
+                None => return false,
+            }
+        }
+        false
+    };
+
+    let mut roots = Vec::new();
+    let mut children = vec![Vec::new(); tasks.len()];
+    for position in 0..tasks.len() {
+        match parent_of(position) {
+            Some(parent) if !on_cycle(position) => children[parent].push(position),
+            _ => roots.push(position),
+        }
+    }
+
+    let mut tasks: Vec<Option<Task>> = tasks.into_iter().map(Some).collect();
+    roots
+        .into_iter()
+        .map(|position| build(position, &mut tasks, &children))
+        .collect()
+}
+
+fn build(position: usize, tasks: &mut [Option<Task>], children: &[Vec<usize>]) -> TaskNode {
+    TaskNode {
+        task: tasks[position]
+            .take()
+            .expect("each task is in the tree once"),
+        children: children[position]
+            .iter()
+            .map(|&child| build(child, tasks, children))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{task_tree, TaskNode};
+    use crate::{Task, TaskId};
+
+    fn task(title: &str, parent: Option<TaskId>) -> Task {
+        let mut task = Task::new(Task::new_id(), title.to_string(), None, None, None);
+        task.set_parent(parent);
+        task
+    }
+
+    fn outline(nodes: &[TaskNode]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for node in nodes {
+            lines.push(node.task.title().to_string());
+            lines.extend(
+                outline(&node.children)
+                    .into_iter()
+                    .map(|line| format!("  {}", line)),
+            );
+        }
+        lines
+    }
+
+    #[test]
+    fn children_follow_the_flat_order() {
+        let parent = task("parent", None);
+        let second = task("second", Some(parent.id()));
+        let first = task("first", Some(parent.id()));
+        let grandchild = task("grandchild", Some(second.id()));
+        let other = task("other", None);
+        let tree = task_tree(vec![
+            first,
+            parent.clone(),
+            other,
+            grandchild,
+            second.clone(),
+        ]);
+        assert_eq!(
+            outline(&tree),
+            ["parent", "  first", "  second", "    grandchild", "other"]
+        );
+        assert_eq!(
+            TaskNode::find(&tree, &second.id()).unwrap().children.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn orphans_and_cycles_are_top_level() {
+        let missing = Task::new_id();
+        let orphan = task("orphan", Some(missing));
+        let mut one = task("one", None);
+        let two = task("two", Some(one.id()));
+        one.set_parent(Some(two.id()));
+        let below = task("below", Some(two.id()));
+        let tree = task_tree(vec![orphan, one, two, below]);
+        assert_eq!(outline(&tree), ["orphan", "one", "two", "  below"]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Detecting changes made to task files by other processes.
+//!
+//! A [`FileWatch`] is polled to learn whether a file changed since it was
+//! last polled.  A [`Watcher`] polls one on a background thread and calls back
+//! when the file changes.  Either way, a change is followed by
+//! [`crate::Store::reload`] to merge the file into the store.
+//!
+//! Polling the file's metadata keeps this portable and free of platform
+//! notification APIs, at the cost of noticing changes up to one interval
+//! late.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+// What is compared to decide whether a file changed.  Saves replace the file
+// by renaming a new one over it, so the modification time alone would do on
+// most filesystems; the length catches changes within a coarse timestamp's
+// resolution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Stamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Polls a file for changes.
+#[derive(Debug)]
+pub struct FileWatch {
+    path: PathBuf,
+    stamp: Option<Stamp>,
+}
+
+impl FileWatch {
+    /// Start watching the file at `path`, which need not exist yet.
+    #[must_use]
+    pub fn new(path: &Path) -> FileWatch {
+        FileWatch {
+            path: path.to_path_buf(),
+            stamp: Stamp::of(path),
+        }
+    }
+
+    /// Return whether the file changed since the watch was created or last
+    /// polled.  A file that is created counts as changed; one that is removed
+    /// does not, since there is nothing to reload.
+    pub fn changed(&mut self) -> bool {
+        let stamp = Stamp::of(&self.path);
+        if stamp == self.stamp {
+            return false;
+        }
+        let changed = stamp.is_some();
+        self.stamp = stamp;
+        changed
+    }
+}
+
+/// Watches a file on a background thread, calling back when it changes.  The
+/// thread stops when this is dropped.
+#[derive(Debug)]
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Poll the file at `path` every `interval`, calling `on_change` on the
+    /// watcher's thread each time it changes.
+    pub fn new<F>(path: &Path, interval: Duration, mut on_change: F) -> Watcher
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut watch = FileWatch::new(path);
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || loop {
+                thread::park_timeout(interval);
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if watch.changed() {
+                    on_change();
+                }
+            })
+        };
+        Watcher {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn watch_reports_each_change_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let mut watch = FileWatch::new(&path);
+        assert!(!watch.changed());
+
+        fs::write(&path, "one").unwrap();
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        fs::write(&path, "three").unwrap();
+        assert!(watch.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watch.changed());
+    }
+
+    #[test]
+    fn watcher_calls_back_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let (sender, receiver) = mpsc::channel();
+        let watcher = Watcher::new(&path, Duration::from_millis(10), move || {
+            let _ = sender.send(());
+        });
+
+        fs::write(&path, "changed").unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        drop(watcher);
+        // The callback, and with it the sender, is gone once the thread stops,
+        // so this ends.
+        for () in receiver.iter() {}
+    }
+}
@@ -0,0 +1,50 @@
+/*!
+A relay server that syncs Sift task files between devices.
+
+The relay keeps one Automerge document per user, saved as `<user>.sift` in
+its data directory, and syncs it with each device that connects over the
+Automerge sync protocol on WebSocket.  Devices sync with
+`sift-sync relay ws://host:port/<user>`, giving the token the relay was
+started with.
+*/
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::uninlined_format_args)]
+#![deny(unused_crate_dependencies)]
+#![deny(unused_extern_crates)]
+
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use crate::relay::Relay;
+
+mod relay;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// The address to listen on.
+    #[arg(default_value = "127.0.0.1:7384")]
+    address: String,
+
+    /// The directory users' task files are kept in.
+    #[arg(long)]
+    data: PathBuf,
+
+    /// The token devices must give to sync.
+    #[arg(long, env = "SIFT_RELAY_TOKEN", hide_env_values = true)]
+    token: String,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    std::fs::create_dir_all(&args.data)
+        .with_context(|| format!("Cannot create `{}`", args.data.display()))?;
+    let listener = TcpListener::bind(&args.address)?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    Arc::new(Relay::new(args.data, args.token)).serve(&listener);
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Serving sync sessions to devices, one thread per connection.
+
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use sift_persist::{sync_over, Access, MemoryStore, PeerReport, Side, Store};
+use sift_sync::relay::WebSocketChannel;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::header::AUTHORIZATION;
+use tungstenite::http::StatusCode;
+
+// How long a device may stay silent, or leave its messages unread, before
+// its session is dropped.  A stalled session would otherwise hold its
+// thread, and its user's turn with their document, forever.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub(crate) struct Relay {
+    data: PathBuf,
+    token: String,
+    // Sessions of one user take turns with their document.
+    users: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+// Compare without stopping at the first difference, so that how long a
+// comparison takes does not tell how much of a guessed token was right.
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// User names become file names, so only plain ones are allowed.
+fn valid_user(user: &str) -> bool {
+    (1..=64).contains(&user.len())
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn refuse(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+impl Relay {
+    pub(crate) fn new(data: PathBuf, token: String) -> Self {
+        Relay {
+            data,
+            token,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Serve each device that connects to `listener` on a thread of its own.
+    pub(crate) fn serve(self: Arc<Self>, listener: &TcpListener) {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Cannot accept connection: {}", e);
+                    continue;
+                }
+            };
+            let relay = Arc::clone(&self);
+            thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+                match relay.session(stream) {
+                    Ok((user, report)) => eprintln!(
+                        "{} ({}): received {} and sent {} sync messages",
+                        user, peer, report.received, report.sent
+                    ),
+                    Err(e) => eprintln!("{}: {:#}", peer, e),
+                }
+            });
+        }
+    }
+
+    fn lock(&self, user: &str) -> Arc<Mutex<()>> {
+        let mut users = self.users.lock().expect("user locks are not poisoned");
+        Arc::clone(users.entry(user.to_string()).or_default())
+    }
+
+    // Authorize a device, then sync its document with the user's.
+    fn session(&self, stream: TcpStream) -> Result<(String, PeerReport)> {
+        let mut user = String::new();
+        let callback = |request: &Request, response: Response| {
+            let authorization = request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if !authorization.is_some_and(|token| same_token(token, &self.token)) {
+                return Err(refuse(StatusCode::UNAUTHORIZED, "invalid token"));
+            }
+            let path = request.uri().path().trim_start_matches('/');
+            if !valid_user(path) {
+                return Err(refuse(StatusCode::NOT_FOUND, "no such user"));
+            }
+            path.clone_into(&mut user);
+            Ok(response)
+        };
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        stream.set_write_timeout(Some(IDLE_TIMEOUT))?;
+        let socket = tungstenite::accept_hdr(stream, callback).map_err(|e| match e {
+            HandshakeError::Failure(e) => anyhow::Error::from(e),
+            // A read that times out reports that it would block, which
+            // interrupts the handshake.
+            HandshakeError::Interrupted(_) => anyhow!("the handshake timed out"),
+        })?;
+
+        let lock = self.lock(&user);
+        let _turn = lock.lock().expect("user locks are not poisoned");
+        let path = self.data.join(format!("{}.sift", user));
+        let exists = path.exists();
+        let mut store = if exists {
+            MemoryStore::open(&path, Access::ReadWrite)?
+        } else {
+            MemoryStore::default()
+        };
+        let mut channel = WebSocketChannel::new(socket);
+        let report = sync_over(&mut store, &mut channel, Side::Listening)?;
+        // Save before closing, so that a device whose session ends cleanly
+        // knows its changes are kept.
+        if report.changed {
+            if exists {
+                store.flush()?;
+            } else {
+                store.save(&path)?;
+            }
+        }
+        channel.close()?;
+        Ok((user, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sift_persist::Task;
+    use sift_sync::relay::{sync_with_relay, Error};
+
+    use super::*;
+
+    const TOKEN: &str = "shared secret";
+
+    // Start a relay on a free local port, returning its address.  The relay
+    // runs until the test process exits.
+    fn start(data: PathBuf) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let relay = Arc::new(Relay::new(data, TOKEN.to_string()));
+        thread::spawn(move || relay.serve(&listener));
+        format!("ws://{}", address)
+    }
+
+    fn titles(store: &MemoryStore) -> Vec<String> {
+        let tasks = store.list_tasks().unwrap();
+        tasks.iter().map(|task| task.title().to_string()).collect()
+    }
+
+    #[test]
+    fn devices_sync_through_the_relay() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("{}/alice", start(dir.path().to_path_buf()));
+
+        let one = Task::new(Task::new_id(), "one".to_string(), None, None, None);
+        let mut laptop = MemoryStore::default();
+        laptop
+            .with_transaction(|txn| txn.insert_task(None, None, &one))
+            .unwrap();
+        sync_with_relay(&mut laptop, &url, TOKEN).unwrap();
+
+        let mut desktop = MemoryStore::default();
+        let report = sync_with_relay(&mut desktop, &url, TOKEN).unwrap();
+        assert!(report.changed);
+        assert_eq!(titles(&desktop), ["one"]);
+
+        let mut renamed = one.clone();
+        renamed.set_title("renamed on the desktop".to_string());
+        desktop
+            .with_transaction(|txn| txn.put_task(&renamed))
+            .unwrap();
+        sync_with_relay(&mut desktop, &url, TOKEN).unwrap();
+        sync_with_relay(&mut laptop, &url, TOKEN).unwrap();
+        assert_eq!(titles(&laptop), ["renamed on the desktop"]);
+
+        // The relay's copy is saved in the container format.
+        let path = dir.path().join("alice.sift");
+        let saved = MemoryStore::open(&path, Access::ReadOnly).unwrap();
+        assert_eq!(titles(&saved), ["renamed on the desktop"]);
+    }
+
+    #[test]
+    fn users_have_separate_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let relay = start(dir.path().to_path_buf());
+
+        let one = Task::new(Task::new_id(), "one".to_string(), None, None, None);
+        let mut alice = MemoryStore::default();
+        alice
+            .with_transaction(|txn| txn.insert_task(None, None, &one))
+            .unwrap();
+        sync_with_relay(&mut alice, &format!("{}/alice", relay), TOKEN).unwrap();
+
+        let mut bob = MemoryStore::default();
+        let report = sync_with_relay(&mut bob, &format!("{}/bob", relay), TOKEN).unwrap();
+        assert!(!report.changed);
+        assert!(titles(&bob).is_empty());
+    }
+
+    #[test]
+    fn refuses_wrong_tokens_and_bad_user_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let relay = start(dir.path().to_path_buf());
+        let mut store = MemoryStore::default();
+
+        let error = sync_with_relay(&mut store, &format!("{}/alice", relay), "guess").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Unauthorized(_))
+        ));
+        let error = sync_with_relay(&mut store, &format!("{}/..", relay), TOKEN).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Connect(..))
+        ));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(same_token(TOKEN, "shared secret"));
+        assert!(!same_token(TOKEN, "shared secreT"));
+        assert!(!same_token(TOKEN, "shared"));
+    }
+}
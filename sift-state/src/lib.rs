@@ -1,3 +1,5 @@
+mod snooze;
 mod state;
 
-pub use state::State;
+pub use snooze::{parse_date, SNOOZE_PRESETS};
+pub use state::{open_store, today, DisplayedTask, SortMode, State};
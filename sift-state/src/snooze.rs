@@ -0,0 +1,185 @@
+/*!
+Dates to snooze tasks until, as typed by people.
+
+[`parse_date`] understands phrases such as "tomorrow", "this weekend",
+"next monday", "in 3 days", "2 weeks", "jul 5" and "2024-07-05".  Relative
+phrases are counted from the day given as today, so that the result does not
+depend on the clock.
+*/
+
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+
+/// Phrases offered as ready-made choices wherever a snooze date is picked.
+pub const SNOOZE_PRESETS: [&str; 4] = ["tomorrow", "this weekend", "next monday", "in 7 days"];
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+// Return the weekday named by `word`, in full or by its first three letters.
+fn weekday(word: &str) -> Option<Weekday> {
+    let full = [
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ];
+    full.iter()
+        .find(|(name, _)| *name == word || (word.len() == 3 && name.starts_with(word)))
+        .map(|(_, day)| *day)
+}
+
+// Return the month numbered from 1 named by `word`, in full or by its first
+// three letters.
+fn month(word: &str) -> Option<u32> {
+    let index = MONTHS
+        .iter()
+        .position(|name| *name == word || (word.len() == 3 && name.starts_with(word)))?;
+    u32::try_from(index + 1).ok()
+}
+
+// The first `day` after `today`.
+fn next_weekday(today: NaiveDate, day: Weekday) -> NaiveDate {
+    let ahead = (7 + day.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    let ahead = if ahead == 0 { 7 } else { ahead };
+    today + Days::new(ahead.into())
+}
+
+// Parse an amount of time such as "3 days", "a week" or "2w" into a date
+// that long after `today`.
+fn after(today: NaiveDate, words: &[&str]) -> Option<NaiveDate> {
+    let (count, unit) = match words {
+        [count, unit] => (*count, *unit),
+        [word] => {
+            let split = word.find(|c: char| !c.is_ascii_digit())?;
+            word.split_at(split)
+        }
+        _ => return None,
+    };
+    let count: u32 = match count {
+        "a" | "an" | "one" => 1,
+        count => count.parse().ok()?,
+    };
+    match unit {
+        "d" | "day" | "days" => today.checked_add_days(Days::new(count.into())),
+        "w" | "week" | "weeks" => today.checked_add_days(Days::new(u64::from(count) * 7)),
+        "m" | "month" | "months" => today.checked_add_months(Months::new(count)),
+        _ => None,
+    }
+}
+
+// Parse a day of a month such as "jul 5" or "5 july", in the year of
+// `today` or, if that day is past, the next year.
+fn day_of_month(today: NaiveDate, words: &[&str]) -> Option<NaiveDate> {
+    let (month, day) = match words {
+        [first, second] => match (month(first), month(second)) {
+            (Some(month), None) => (month, second.parse().ok()?),
+            (None, Some(month)) => (month, first.parse().ok()?),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+    if date > today {
+        Some(date)
+    } else {
+        NaiveDate::from_ymd_opt(today.year() + 1, month, day)
+    }
+}
+
+/// Parse `text` as a date, counting relative dates from `today`.  Returns
+/// `None` if `text` is not a date this understands.
+///
+/// Weekdays, with or without "next" or "this", are the first such day after
+/// today, and "this weekend" is the first Saturday after today.
+#[must_use]
+pub fn parse_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let text = text.trim().to_lowercase();
+    if let Ok(date) = text.parse::<NaiveDate>() {
+        return Some(date);
+    }
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    if matches!(words.first(), Some(&("in" | "on" | "until"))) {
+        words.remove(0);
+    }
+    match words.as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["this" | "next", "weekend"] | ["weekend"] => Some(next_weekday(today, Weekday::Sat)),
+        ["next", "week"] => Some(next_weekday(today, Weekday::Mon)),
+        ["next", "month"] => today.checked_add_months(Months::new(1)),
+        ["this" | "next", day] | [day] if weekday(day).is_some() => {
+            Some(next_weekday(today, weekday(day)?))
+        }
+        words => after(today, words).or_else(|| day_of_month(today, words)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{parse_date, SNOOZE_PRESETS};
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    // Parse `text` on Wednesday, July 3rd 2024.
+    fn parse(text: &str) -> Option<String> {
+        parse_date(text, date("2024-07-03")).map(|date| date.to_string())
+    }
+
+    #[test]
+    fn relative_dates() {
+        assert_eq!(parse("today").as_deref(), Some("2024-07-03"));
+        assert_eq!(parse(" Tomorrow ").as_deref(), Some("2024-07-04"));
+        assert_eq!(parse("this weekend").as_deref(), Some("2024-07-06"));
+        assert_eq!(parse("next week").as_deref(), Some("2024-07-08"));
+        assert_eq!(parse("next month").as_deref(), Some("2024-08-03"));
+        assert_eq!(parse("next Monday").as_deref(), Some("2024-07-08"));
+        assert_eq!(parse("on fri").as_deref(), Some("2024-07-05"));
+        // A weekday is never today.
+        assert_eq!(parse("wednesday").as_deref(), Some("2024-07-10"));
+        assert_eq!(parse("in 3 days").as_deref(), Some("2024-07-06"));
+        assert_eq!(parse("2 weeks").as_deref(), Some("2024-07-17"));
+        assert_eq!(parse("in a week").as_deref(), Some("2024-07-10"));
+        assert_eq!(parse("10d").as_deref(), Some("2024-07-13"));
+
+        for preset in SNOOZE_PRESETS {
+            assert!(parse(preset).is_some(), "{}", preset);
+        }
+    }
+
+    #[test]
+    fn explicit_dates() {
+        assert_eq!(parse("2024-12-25").as_deref(), Some("2024-12-25"));
+        assert_eq!(parse("jul 5").as_deref(), Some("2024-07-05"));
+        assert_eq!(parse("5 July").as_deref(), Some("2024-07-05"));
+        // Days already past this year are next year's.
+        assert_eq!(parse("july 3").as_deref(), Some("2025-07-03"));
+        assert_eq!(parse("feb 29").as_deref(), None);
+        assert_eq!(parse("feb 28").as_deref(), Some("2025-02-28"));
+    }
+
+    #[test]
+    fn rejects_other_text() {
+        for text in ["", "soon", "next", "3", "in 3 fortnights", "jul", "13/07"] {
+            assert_eq!(parse(text), None, "{}", text);
+        }
+    }
+}
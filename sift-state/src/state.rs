@@ -5,13 +5,21 @@ The `State` struct contains the application's state.  It is the
 central data structure for the application.
 */
 
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display};
+use std::path::Path;
 
 use chrono::Datelike;
 use itertools::Itertools;
-use sift_persist::{MemoryStore, Store, Task, TaskId};
+use sift_persist::{
+    task_tree, Access, Frequency, ListId, MemoryStore, NamedList, Priority, Recurrence, Store,
+    Task, TaskId, TaskNode,
+};
 
-fn today() -> chrono::NaiveDate {
+/// The current day in the local time zone.
+#[must_use]
+pub fn today() -> chrono::NaiveDate {
     let now = chrono::Local::now();
     chrono::NaiveDate::from_ymd_opt(now.year(), now.month(), now.day()).unwrap()
 }
@@ -20,22 +28,377 @@ fn next_week() -> chrono::NaiveDate {
     today() + chrono::TimeDelta::try_weeks(1).unwrap()
 }
 
+/// Open the task file at `path`.  If another process has it locked, open it
+/// read-only instead, as [`Store::is_read_only`] then tells.
+///
+/// # Errors
+///
+/// Returns any error from reading the file.
+pub fn open_store(path: &Path) -> anyhow::Result<MemoryStore> {
+    match MemoryStore::load(path) {
+        Err(error)
+            if matches!(
+                error.downcast_ref::<sift_persist::Error>(),
+                Some(sift_persist::Error::Locked { .. })
+            ) =>
+        {
+            MemoryStore::open(path, Access::ReadOnly)
+        }
+        result => result,
+    }
+}
+
+fn is_snoozed(task: &Task, today: chrono::NaiveDate) -> bool {
+    matches!(task.snoozed(), Some(date) if date > today)
+}
+
+// Whether `node` is displayed among its siblings: it is not snoozed, and it
+// or one of its subtasks has the tag filtered on, if any.
+fn is_shown(node: &TaskNode, today: chrono::NaiveDate, tag_filter: Option<&str>) -> bool {
+    if is_snoozed(&node.task, today) {
+        return false;
+    }
+    match tag_filter {
+        Some(tag) => tagged_within(node, tag),
+        None => true,
+    }
+}
+
+// Return the parent of the task `id` and the displayed tasks sharing that
+// parent, `id` included, in order.
+fn siblings(
+    nodes: &[TaskNode],
+    parent: Option<TaskId>,
+    id: &TaskId,
+    today: chrono::NaiveDate,
+    tag_filter: Option<&str>,
+) -> Option<(Option<TaskId>, Vec<TaskId>)> {
+    if nodes.iter().any(|node| node.task.id() == *id) {
+        let ids = nodes
+            .iter()
+            .filter(|node| is_shown(node, today, tag_filter))
+            .map(|node| node.task.id())
+            .collect();
+        return Some((parent, ids));
+    }
+    nodes
+        .iter()
+        .find_map(|node| siblings(&node.children, Some(node.task.id()), id, today, tag_filter))
+}
+
+// Return the ids of the subtasks of `node`, their subtasks, and so on.
+fn descendants(node: &TaskNode) -> Vec<TaskId> {
+    let mut ids = Vec::new();
+    node.walk(&mut |other| ids.push(other.task.id()));
+    ids.remove(0);
+    ids
+}
+
+// Whether `node` or any of its descendants is tagged `tag`.
+fn tagged_within(node: &TaskNode, tag: &str) -> bool {
+    node.task.has_tag(tag) || node.children.iter().any(|child| tagged_within(child, tag))
+}
+
+// Whether `node` and all its descendants are completed.
+fn all_completed(node: &TaskNode) -> bool {
+    node.task.is_completed() && node.children.iter().all(all_completed)
+}
+
+/// The order tasks are displayed in.  Orders other than
+/// [`SortMode::Manual`] only change the view, not the order tasks are stored
+/// in, and sort subtasks among their siblings.  Tasks that sort the same stay
+/// in their stored order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortMode {
+    /// The order tasks were put in.
+    #[default]
+    Manual,
+    /// Earliest due date first, then tasks with none.
+    Due,
+    /// Highest priority first, then tasks with none.
+    Priority,
+    /// Oldest first, by the time in each task's id.
+    Created,
+    /// Incomplete tasks first, then the most recently completed.
+    Completed,
+}
+
+impl SortMode {
+    /// The mode after this one, wrapping around to manual order.
+    #[must_use]
+    pub fn next(self) -> Self {
+        match self {
+            SortMode::Manual => SortMode::Due,
+            SortMode::Due => SortMode::Priority,
+            SortMode::Priority => SortMode::Created,
+            SortMode::Created => SortMode::Completed,
+            SortMode::Completed => SortMode::Manual,
+        }
+    }
+
+    fn sort(self, nodes: &mut [TaskNode]) {
+        match self {
+            SortMode::Manual => return,
+            SortMode::Due => nodes.sort_by_key(|node| {
+                let due = node.task.due();
+                (due.is_none(), due)
+            }),
+            // No priority sorts below any priority, so last once reversed.
+            SortMode::Priority => nodes.sort_by_key(|node| Reverse(node.task.priority())),
+            SortMode::Created => nodes.sort_by_key(|node| {
+                let created = node.task.id().created();
+                (created.is_none(), created)
+            }),
+            SortMode::Completed => nodes.sort_by_key(|node| {
+                let completed = node.task.completed();
+                (completed.is_some(), Reverse(completed))
+            }),
+        }
+        for node in nodes {
+            self.sort(&mut node.children);
+        }
+    }
+}
+
+impl Display for SortMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortMode::Manual => "manual",
+            SortMode::Due => "due date",
+            SortMode::Priority => "priority",
+            SortMode::Created => "created",
+            SortMode::Completed => "completed",
+        })
+    }
+}
+
+/// A task as displayed: how deep it is among subtasks, and whether its own
+/// subtasks are shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayedTask {
+    pub task: Task,
+    pub depth: usize,
+    pub has_subtasks: bool,
+    pub collapsed: bool,
+}
+
+/// The application state, generic over the [`Store`] holding the tasks.
 #[derive(Default)]
-pub struct State {
-    pub store: MemoryStore,
+pub struct State<S = MemoryStore> {
+    pub store: S,
     pub selected: Option<TaskId>,
+    /// The list being shown.
+    pub list: ListId,
+    /// Whether completing a task also completes its subtasks.
+    pub cascade_completion: bool,
+    /// The order tasks are displayed in.
+    pub sort_mode: SortMode,
+    // Tasks whose subtasks are hidden.  This is part of the view, not the
+    // tasks, so it is not saved.
+    collapsed: HashSet<TaskId>,
+    // Show only tasks with this tag, and the tasks they are subtasks of.
+    tag_filter: Option<String>,
 }
 
-impl State {
-    pub fn new(store: MemoryStore) -> Self {
+impl<S: Store> State<S> {
+    pub fn new(store: S) -> Self {
         let mut state = State {
             store,
             selected: None,
+            list: ListId::DEFAULT,
+            cascade_completion: false,
+            sort_mode: SortMode::Manual,
+            collapsed: HashSet::new(),
+            tag_filter: None,
         };
         state.selected = state.first_id();
         state
     }
 
+    // Return the tree of tasks in the list being shown.
+    fn task_tree(&self) -> Vec<TaskNode> {
+        let mut tasks = self.store.list_tasks().expect("XXX: handle error");
+        tasks.retain(|task| task.list() == self.list);
+        task_tree(tasks)
+    }
+
+    pub fn lists(&self) -> Vec<NamedList> {
+        self.store.lists().expect("XXX: handle error")
+    }
+
+    /// Return the list being shown.
+    pub fn current_list(&self) -> NamedList {
+        let lists = self.lists();
+        lists
+            .iter()
+            .find(|list| list.id == self.list)
+            .unwrap_or(&lists[0])
+            .clone()
+    }
+
+    /// Show the list `id`, selecting its first task.
+    pub fn select_list(&mut self, id: ListId) {
+        self.list = id;
+        self.selected = self.first_id();
+    }
+
+    // Return the list after the current one, wrapping around.
+    fn next_list_id(&self) -> ListId {
+        let ids: Vec<ListId> = self.lists().iter().map(|list| list.id).collect();
+        match ids.iter().position(|id| *id == self.list) {
+            Some(index) => ids[(index + 1) % ids.len()],
+            None => ids[0],
+        }
+    }
+
+    /// Show the list after the current one, or the first after the last.
+    pub fn next_list(&mut self) {
+        self.select_list(self.next_list_id());
+    }
+
+    /// Add a list named `name` after the current one and show it.
+    pub fn create_list(&mut self, name: &str) -> ListId {
+        let list = NamedList::new(name);
+        let current = self.list;
+        self.store
+            .with_transaction(|txn| txn.create_list(Some(&current), &list))
+            .expect("FIXME: propagate errors");
+        self.select_list(list.id);
+        list.id
+    }
+
+    /// Rename the list being shown.
+    pub fn rename_list(&mut self, name: &str) {
+        let current = self.list;
+        self.store
+            .with_transaction(|txn| txn.rename_list(&current, name))
+            .expect("FIXME: propagate errors");
+    }
+
+    /// Delete the list being shown, and its tasks, then show the list
+    /// before it.  The default list is not deleted.
+    pub fn delete_list(&mut self) {
+        let current = self.list;
+        if current.is_default() {
+            return;
+        }
+        let lists = self.lists();
+        let index = lists.iter().position(|list| list.id == current);
+        let previous = index
+            .and_then(|index| index.checked_sub(1))
+            .map_or(ListId::DEFAULT, |index| lists[index].id);
+        self.store
+            .with_transaction(|txn| txn.delete_list(&current))
+            .expect("FIXME: propagate errors");
+        self.select_list(previous);
+    }
+
+    /// Move the selected task, with its subtasks, to the top of `list`.
+    pub fn move_to_list(&mut self, list: ListId) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        if list == self.list {
+            return;
+        }
+        let subtasks =
+            TaskNode::find(&self.task_tree(), &selected).map_or_else(Vec::new, descendants);
+        let mut moved: HashSet<&TaskId> = subtasks.iter().collect();
+        moved.insert(&selected);
+        self.selected = self.selection_after_removing(&moved);
+        self.store
+            .with_transaction(|txn| {
+                txn.move_task_to_list(&list, &selected)?;
+                for id in &subtasks {
+                    let mut subtask = txn.get_task(id)?;
+                    subtask.set_list(list);
+                    txn.put_task(&subtask)?;
+                }
+                Ok(())
+            })
+            .expect("FIXME: propagate errors");
+    }
+
+    /// Move the selected task to the list after the current one.
+    pub fn move_to_next_list(&mut self) {
+        self.move_to_list(self.next_list_id());
+    }
+
+    // Show the default list if the list being shown is gone, as after an
+    // undo or a change from elsewhere.
+    fn keep_list(&mut self) {
+        if !self.lists().iter().any(|list| list.id == self.list) {
+            self.select_list(ListId::DEFAULT);
+        }
+    }
+
+    /// Replace the notes of the task with `id`, returning whether they
+    /// changed.  Nothing is recorded if they are unchanged, so that closing an
+    /// editor does not add an undo step.
+    pub fn set_notes(&mut self, id: &TaskId, notes: &str) -> bool {
+        let Some(mut task) = self.get_task(id) else {
+            return false;
+        };
+        if task.notes() == notes {
+            return false;
+        }
+        task.set_notes(notes.to_string());
+        self.store
+            .with_transaction(|txn| txn.put_task(&task))
+            .expect("FIXME: propagate errors");
+        true
+    }
+
+    /// Move any `#tag` words in the title of the task with `id` into its
+    /// tags, for frontends that edit the title alone.
+    pub fn take_tags_from_title(&mut self, id: &TaskId) {
+        let Some(stored) = self.get_task(id) else {
+            return;
+        };
+        let mut task = stored.clone();
+        task.set_title_with_tags(&stored.title_with_tags());
+        if task != stored {
+            self.store
+                .with_transaction(|txn| txn.put_task(&task))
+                .expect("FIXME: propagate errors");
+        }
+    }
+
+    /// The tags on the tasks in the list being shown.
+    pub fn tags(&self) -> BTreeSet<String> {
+        let mut tags = BTreeSet::new();
+        for node in &self.task_tree() {
+            node.walk(&mut |node| tags.extend(node.task.tags().iter().cloned()));
+        }
+        tags
+    }
+
+    /// The tag tasks are being filtered on, if any.
+    pub fn tag_filter(&self) -> Option<&str> {
+        self.tag_filter.as_deref()
+    }
+
+    /// Show only the tasks tagged `tag`, or all tasks.  The selection moves
+    /// to the first task shown if it is hidden.
+    pub fn set_tag_filter(&mut self, tag: Option<String>) {
+        self.tag_filter = tag;
+        if self.index_of_id(self.selected).is_none() {
+            self.selected = self.first_id();
+        }
+    }
+
+    /// Filter on the tag after the one being filtered on, in order, or on
+    /// the first tag if there is no filter.  After the last tag, the filter
+    /// is removed.
+    pub fn next_tag_filter(&mut self) {
+        let tags = self.tags();
+        let next = match &self.tag_filter {
+            None => tags.into_iter().next(),
+            Some(current) => tags.into_iter().find(|tag| tag > current),
+        };
+        self.set_tag_filter(next);
+    }
+
     pub fn get_task(&self, id: &TaskId) -> Option<Task> {
         match self.store.get_task(id) {
             Ok(task) => Some(task),
@@ -59,26 +422,90 @@ impl State {
     }
 
     pub fn list_tasks_for_display(&self) -> Vec<Task> {
-        let today = today();
-        let mut tasks = self.store.list_tasks().expect("XXX: handle error");
-        tasks.retain(|task| {
-            let snoozed = matches!(task.snoozed(), Some(date) if date > today);
-            !snoozed
-        });
-        tasks
+        self.list_displayed_tasks()
+            .into_iter()
+            .map(|displayed| displayed.task)
+            .collect()
     }
 
+    /// List the tasks to display in the sort mode's order, each followed by
+    /// its subtasks unless they are collapsed.  Snoozed tasks are left out
+    /// along with their subtasks, and so are tasks without the tag being
+    /// filtered on, unless one of their subtasks has it.
+    pub fn list_displayed_tasks(&self) -> Vec<DisplayedTask> {
+        fn push<S>(
+            state: &State<S>,
+            nodes: &[TaskNode],
+            depth: usize,
+            today: chrono::NaiveDate,
+            displayed: &mut Vec<DisplayedTask>,
+        ) {
+            for node in nodes {
+                if !is_shown(node, today, state.tag_filter.as_deref()) {
+                    continue;
+                }
+                let collapsed = state.collapsed.contains(&node.task.id());
+                displayed.push(DisplayedTask {
+                    task: node.task.clone(),
+                    depth,
+                    has_subtasks: !node.children.is_empty(),
+                    collapsed,
+                });
+                if !collapsed {
+                    push(state, &node.children, depth + 1, today, displayed);
+                }
+            }
+        }
+
+        let mut tree = self.task_tree();
+        self.sort_mode.sort(&mut tree);
+        let mut displayed = Vec::new();
+        push(self, &tree, 0, today(), &mut displayed);
+        displayed
+    }
+
+    /// Complete the task with `id`, or reopen it if it is completed.
+    ///
+    /// Completing a repeating task adds its next occurrence just after it,
+    /// and the completed task stops repeating, so that reopening it does not
+    /// add another.  The subtasks of a repeating task are not repeated.
     pub fn toggle_id(&mut self, id: &TaskId) {
+        let subtasks = if self.cascade_completion {
+            TaskNode::find(&self.task_tree(), id).map_or_else(Vec::new, descendants)
+        } else {
+            Vec::new()
+        };
         self.store
             .with_transaction(|txn| {
                 let mut task = txn.get_task(id)?;
+                let now = chrono::Local::now();
                 let completed = if task.completed().is_some() {
                     None
                 } else {
-                    Some(chrono::Utc::now())
+                    Some(now.to_utc())
                 };
                 task.set_completed(completed);
+                // The completion date, and so the next due date, is the
+                // user's local one.
+                let next = completed.and_then(|_| task.next_occurrence(&now));
+                if next.is_some() {
+                    task.set_recurrence(None);
+                }
                 txn.put_task(&task)?;
+                if let Some(next) = &next {
+                    txn.insert_task(task.parent().as_ref(), Some(id), next)?;
+                }
+                // Only completion cascades: reopening a task leaves its
+                // subtasks as they are.
+                if completed.is_some() {
+                    for id in &subtasks {
+                        let mut subtask = txn.get_task(id)?;
+                        if !subtask.is_completed() {
+                            subtask.set_completed(completed);
+                            txn.put_task(&subtask)?;
+                        }
+                    }
+                }
                 Ok(())
             })
             .expect("FIXME: propagate errors");
@@ -90,30 +517,83 @@ impl State {
         }
     }
 
+    /// Step the priority of the selected task from none to high, medium,
+    /// low and back to none.
+    pub fn cycle_priority(&mut self) {
+        let Some(id) = self.selected else {
+            return;
+        };
+        self.store
+            .with_transaction(|txn| {
+                let mut task = txn.get_task(&id)?;
+                task.set_priority(match task.priority() {
+                    None => Some(Priority::High),
+                    Some(Priority::High) => Some(Priority::Medium),
+                    Some(Priority::Medium) => Some(Priority::Low),
+                    Some(Priority::Low) => None,
+                });
+                txn.put_task(&task)
+            })
+            .expect("FIXME: propagate errors");
+    }
+
+    /// Step the selected task from not repeating to repeating daily,
+    /// weekly, monthly, yearly and back to not repeating.
+    pub fn cycle_recurrence(&mut self) {
+        let Some(id) = self.selected else {
+            return;
+        };
+        self.store
+            .with_transaction(|txn| {
+                let mut task = txn.get_task(&id)?;
+                let frequency = match task.recurrence().map(|rule| rule.frequency) {
+                    None => Some(Frequency::Daily),
+                    Some(Frequency::Daily) => Some(Frequency::Weekly),
+                    Some(Frequency::Weekly) => Some(Frequency::Monthly),
+                    Some(Frequency::Monthly) => Some(Frequency::Yearly),
+                    Some(Frequency::Yearly) => None,
+                };
+                task.set_recurrence(frequency.map(|frequency| Recurrence::new(frequency, 1)));
+                txn.put_task(&task)
+            })
+            .expect("FIXME: propagate errors");
+    }
+
+    /// Toggle the selected task between not snoozed and snoozed for a week.
     pub fn snooze(&mut self) {
-        if let Some(id) = self.selected {
-            self.store
-                .with_transaction(|txn| {
-                    let mut task = txn.get_task(&id)?;
-                    let snoozed = if task.snoozed().is_some() {
-                        None
-                    } else {
-                        let next_week = next_week();
-                        Some(next_week)
-                    };
-                    task.set_snoozed(snoozed);
-                    txn.put_task(&task)
-                })
-                .expect("FIXME: propagate errors");
+        if let Some(task) = self.selected.and_then(|id| self.get_task(&id)) {
+            let snoozed = match task.snoozed() {
+                Some(_) => None,
+                None => Some(next_week()),
+            };
+            self.snooze_until(&task.id(), snoozed);
         }
-        // Order snoozed items after non-snoozed items.  Keep the current
-        // selection.
-        //
-        // Note: this is a stable sort.
-        // Note: false sorts before true.
-        // self.tasks
-        //     .tasks
-        //     .sort_by_key(|task| task.snoozed().is_some());
+    }
+
+    /// Snooze the task `id`, hiding it and its subtasks until `date`, or
+    /// stop snoozing it if `date` is `None`.  If the selected task is hidden,
+    /// the one before it is selected instead.
+    pub fn snooze_until(&mut self, id: &TaskId, date: Option<chrono::NaiveDate>) {
+        if date.is_some_and(|date| date > today()) {
+            let tree = self.task_tree();
+            if let Some(node) = TaskNode::find(&tree, id) {
+                let mut hidden = descendants(node);
+                hidden.push(*id);
+                if self
+                    .selected
+                    .is_some_and(|selected| hidden.contains(&selected))
+                {
+                    self.selected = self.selection_after_removing(&hidden.iter().collect());
+                }
+            }
+        }
+        self.store
+            .with_transaction(|txn| {
+                let mut task = txn.get_task(id)?;
+                task.set_snoozed(date);
+                txn.put_task(&task)
+            })
+            .expect("FIXME: propagate errors");
     }
 
     fn first_id(&mut self) -> Option<TaskId> {
@@ -163,13 +643,24 @@ impl State {
         self.selected = self.previous_id();
     }
 
+    /// Whether tasks can be moved up and down.  Only in manual order does
+    /// that change what is displayed, so in other orders moves are ignored.
+    pub fn can_move(&self) -> bool {
+        self.sort_mode == SortMode::Manual
+    }
+
     pub fn move_up(&mut self) {
+        if !self.can_move() {
+            return;
+        }
         if let Some(selected) = self.selected {
-            let ids = self.task_ids_for_move();
+            let (parent, ids) = self.task_ids_for_move(&selected);
             for (prev_prev_id, _, id) in ids.iter().circular_tuple_windows() {
                 if *id == Some(selected) {
                     self.store
-                        .with_transaction(|txn| txn.move_task(prev_prev_id.as_ref(), &selected))
+                        .with_transaction(|txn| {
+                            txn.move_task(parent.as_ref(), prev_prev_id.as_ref(), &selected)
+                        })
                         .expect("FIXME: handle this error");
                     break;
                 }
@@ -177,30 +668,41 @@ impl State {
         }
     }
 
-    // Return the list of displayed task IDs in a format useful for moving. The
-    // first id is always None, and subsequent ones are valid IDs in the usual
-    // order. This is useful for determining the previous task ID, which is None
-    // for the first real task.
+    // The free function `siblings`, with what is displayed now.
+    fn siblings(&self, tree: &[TaskNode], id: &TaskId) -> Option<(Option<TaskId>, Vec<TaskId>)> {
+        siblings(tree, None, id, today(), self.tag_filter.as_deref())
+    }
+
+    // Return the parent of `selected` and the list of displayed IDs of its
+    // siblings in a format useful for moving. The first id is always None,
+    // and subsequent ones are valid IDs in the usual order. This is useful
+    // for determining the previous task ID, which is None for the first real
+    // task.
     //
     // Not sure this is the best API. Consider returning Vec<TaskId> and making
     // callers deal with wrapping, etc.
-    fn task_ids_for_move(&mut self) -> Vec<Option<TaskId>> {
-        std::iter::once(None)
-            .chain(
-                self.list_tasks_for_display()
-                    .iter()
-                    .map(|task| Some(task.id())),
-            )
-            .collect()
+    fn task_ids_for_move(&mut self, selected: &TaskId) -> (Option<TaskId>, Vec<Option<TaskId>>) {
+        let (parent, siblings) = self
+            .siblings(&self.task_tree(), selected)
+            .unwrap_or_default();
+        let ids = std::iter::once(None)
+            .chain(siblings.into_iter().map(Some))
+            .collect();
+        (parent, ids)
     }
 
     pub fn move_down(&mut self) {
+        if !self.can_move() {
+            return;
+        }
         if let Some(selected) = self.selected {
-            let ids = self.task_ids_for_move();
+            let (parent, ids) = self.task_ids_for_move(&selected);
             for (id, successor_id) in ids.iter().circular_tuple_windows() {
                 if *id == Some(selected) {
                     self.store
-                        .with_transaction(|txn| txn.move_task(successor_id.as_ref(), &selected))
+                        .with_transaction(|txn| {
+                            txn.move_task(parent.as_ref(), successor_id.as_ref(), &selected)
+                        })
                         .expect("FIXME: handle this error");
                     break;
                 }
@@ -208,13 +710,117 @@ impl State {
         }
     }
 
+    /// Make the selected task the last subtask of the task before it among
+    /// its siblings, if there is one.
+    pub fn indent(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        let tree = self.task_tree();
+        let Some((_, siblings)) = self.siblings(&tree, &selected) else {
+            return;
+        };
+        let Some(index) = siblings.iter().position(|id| *id == selected) else {
+            return;
+        };
+        let Some(parent) = index.checked_sub(1).map(|index| siblings[index]) else {
+            return;
+        };
+        let last = TaskNode::find(&tree, &parent)
+            .and_then(|node| node.children.last())
+            .map(|node| node.task.id());
+        self.store
+            .with_transaction(|txn| txn.move_task(Some(&parent), last.as_ref(), &selected))
+            .expect("FIXME: handle this error");
+        // Keep the selected task in view.
+        self.collapsed.remove(&parent);
+    }
+
+    /// Make the selected subtask a sibling of its parent, just after it.
+    pub fn outdent(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        let tree = self.task_tree();
+        let Some((Some(parent), _)) = self.siblings(&tree, &selected) else {
+            return;
+        };
+        let grandparent = self.siblings(&tree, &parent).and_then(|(id, _)| id);
+        self.store
+            .with_transaction(|txn| txn.move_task(grandparent.as_ref(), Some(&parent), &selected))
+            .expect("FIXME: handle this error");
+    }
+
+    /// Hide the subtasks of the selected task.  If they are already hidden,
+    /// or it has none, select its parent instead.
+    pub fn collapse(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        let tree = self.task_tree();
+        let has_subtasks =
+            TaskNode::find(&tree, &selected).is_some_and(|node| !node.children.is_empty());
+        if has_subtasks && self.collapsed.insert(selected) {
+            return;
+        }
+        if let Some((Some(parent), _)) = self.siblings(&tree, &selected) {
+            self.selected = Some(parent);
+        }
+    }
+
+    /// Show the subtasks of the selected task.  If they are already shown,
+    /// select the first of them instead.
+    pub fn expand(&mut self) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+        if self.collapsed.remove(&selected) {
+            return;
+        }
+        let today = today();
+        let tag_filter = self.tag_filter.as_deref();
+        let first = TaskNode::find(&self.task_tree(), &selected).and_then(|node| {
+            node.children
+                .iter()
+                .find(|child| is_shown(child, today, tag_filter))
+                .map(|child| child.task.id())
+        });
+        if first.is_some() {
+            self.selected = first;
+        }
+    }
+
+    // Delete the tasks with `ids_to_delete` and their subtasks.
     fn delete_tasks(&mut self, ids_to_delete: &[TaskId]) {
+        let tree = self.task_tree();
+        let mut ids_to_delete = ids_to_delete.to_vec();
+        for id in ids_to_delete.clone() {
+            if let Some(node) = TaskNode::find(&tree, &id) {
+                ids_to_delete.extend(descendants(node));
+            }
+        }
+        let ids_to_delete: Vec<_> = ids_to_delete.into_iter().unique().collect();
         let id_set: HashSet<_> = ids_to_delete.iter().collect();
+        self.selected = self.selection_after_removing(&id_set);
+
+        self.store
+            .with_transaction(|txn| {
+                for id in &ids_to_delete {
+                    txn.delete_task(id).expect("FIXME: handle error here");
+                }
+                Ok(())
+            })
+            .expect("TODO: handle errors here");
+    }
+
+    // Return the task to select once the tasks in `removed` are no longer
+    // displayed: the last remaining one up to the selected task.
+    fn selection_after_removing(&self, removed: &HashSet<&TaskId>) -> Option<TaskId> {
         let mut new_selected = None;
         let mut saw_selected = false;
 
         for task in self.list_tasks_for_display() {
-            if !id_set.contains(&task.id()) && !saw_selected {
+            if !removed.contains(&task.id()) && !saw_selected {
                 new_selected = Some(task.id());
             }
             if let Some(selected) = self.selected {
@@ -223,24 +829,19 @@ impl State {
                 }
             }
         }
-        self.selected = new_selected;
-
-        self.store
-            .with_transaction(|txn| {
-                for id in ids_to_delete {
-                    txn.delete_task(id).expect("FIXME: handle error here");
-                }
-                Ok(())
-            })
-            .expect("TODO: handle errors here");
+        new_selected
     }
 
+    /// Delete the displayed completed tasks.  A completed task with
+    /// subtasks still to do is kept, so that they are not lost with it.
     pub fn delete_completed(&mut self) {
+        let tree = self.task_tree();
         let deletions: Vec<_> = self
             .list_tasks_for_display()
             .into_iter()
             .filter_map(|task| {
-                if task.is_completed() {
+                let done = TaskNode::find(&tree, &task.id()).is_some_and(all_completed);
+                if done {
                     Some(task.id())
                 } else {
                     None
@@ -254,11 +855,295 @@ impl State {
         self.delete_tasks(&[*id]);
     }
 
+    /// Save the tasks to the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the underlying store.
+    pub fn save(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.store.save(path)
+    }
+
+    /// Save the tasks to the file they were loaded from.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the underlying store.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.store.flush()
+    }
+
+    /// Merge changes made to the task file by other processes, keeping the
+    /// selection if the selected task still exists.  Returns whether the
+    /// tasks changed, in which case the caller should redraw.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the underlying store.
+    pub fn reload(&mut self) -> anyhow::Result<bool> {
+        if !self.store.reload()? {
+            return Ok(false);
+        }
+        self.keep_list();
+        if self.index_of_id(self.selected).is_none() {
+            self.selected = self.first_id();
+        }
+        Ok(true)
+    }
+
     pub fn undo(&mut self) {
         let _ignored_result = self.store.undo();
+        self.keep_list();
     }
 
     pub fn redo(&mut self) {
         let _ignored = self.store.redo();
+        self.keep_list();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sift_persist::{
+        Frequency, ListId, MemoryStore, Priority, Recurrence, RepeatFrom, Store, Task, TaskId,
+    };
+
+    use super::{today, SortMode, State};
+
+    fn state_with(titles: &[&str]) -> (State, Vec<TaskId>) {
+        let mut store = MemoryStore::default();
+        let tasks: Vec<_> = titles
+            .iter()
+            .map(|title| {
+                let mut task = Task::new(Task::new_id(), String::new(), None, None, None);
+                task.set_title_with_tags(title);
+                task
+            })
+            .collect();
+        store
+            .with_transaction(|txn| {
+                let mut previous = None;
+                for task in &tasks {
+                    txn.insert_task(None, previous.as_ref(), task)?;
+                    previous = Some(task.id());
+                }
+                Ok(())
+            })
+            .unwrap();
+        (State::new(store), tasks.iter().map(Task::id).collect())
+    }
+
+    fn outline(state: &State) -> Vec<String> {
+        state
+            .list_displayed_tasks()
+            .iter()
+            .map(|displayed| format!("{}{}", "  ".repeat(displayed.depth), displayed.task.title()))
+            .collect()
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let (mut state, ids) = state_with(&["a", "b", "c"]);
+        state.indent();
+        assert_eq!(outline(&state), ["a", "b", "c"], "first task stays put");
+
+        state.selected = Some(ids[1]);
+        state.indent();
+        state.selected = Some(ids[2]);
+        state.indent();
+        assert_eq!(outline(&state), ["a", "  b", "  c"]);
+
+        state.move_up();
+        assert_eq!(outline(&state), ["a", "  c", "  b"]);
+
+        state.outdent();
+        assert_eq!(outline(&state), ["a", "  b", "c"]);
+        state.outdent();
+        assert_eq!(outline(&state), ["a", "  b", "c"]);
+    }
+
+    #[test]
+    fn collapse_and_expand() {
+        let (mut state, ids) = state_with(&["a", "b", "c"]);
+        state.selected = Some(ids[1]);
+        state.indent();
+
+        state.collapse();
+        assert_eq!(state.selected, Some(ids[0]));
+        state.collapse();
+        assert_eq!(outline(&state), ["a", "c"]);
+        assert!(state.list_displayed_tasks()[0].collapsed);
+
+        state.expand();
+        assert_eq!(outline(&state), ["a", "  b", "c"]);
+        state.expand();
+        assert_eq!(state.selected, Some(ids[1]));
+    }
+
+    #[test]
+    fn completion_cascades_when_asked() {
+        let (mut state, ids) = state_with(&["a", "b", "c"]);
+        state.selected = Some(ids[1]);
+        state.indent();
+
+        state.toggle_id(&ids[0]);
+        assert!(!state.get_task(&ids[1]).unwrap().is_completed());
+        state.delete_completed();
+        assert_eq!(outline(&state), ["a", "  b", "c"], "open subtask keeps a");
+
+        state.toggle_id(&ids[0]);
+        state.cascade_completion = true;
+        state.toggle_id(&ids[0]);
+        assert!(state.get_task(&ids[1]).unwrap().is_completed());
+        state.delete_completed();
+        assert_eq!(outline(&state), ["c"]);
+    }
+
+    #[test]
+    fn lists() {
+        let (mut state, ids) = state_with(&["a", "b", "c"]);
+        state.selected = Some(ids[1]);
+        state.indent();
+
+        let work = state.create_list("Work");
+        assert_eq!(state.current_list().name, "Work");
+        assert!(outline(&state).is_empty());
+        state.next_list();
+        assert_eq!(state.list, ListId::DEFAULT);
+
+        state.selected = Some(ids[0]);
+        state.move_to_next_list();
+        assert_eq!(outline(&state), ["c"]);
+        state.next_list();
+        assert_eq!(state.list, work);
+        assert_eq!(outline(&state), ["a", "  b"]);
+
+        state.delete_list();
+        assert_eq!(state.list, ListId::DEFAULT);
+        assert_eq!(outline(&state), ["c"]);
+        state.undo();
+        assert_eq!(state.lists().len(), 2);
+    }
+
+    #[test]
+    fn tag_filter() {
+        let (mut state, ids) = state_with(&["a #work", "b #home", "c", "d #work"]);
+        state.selected = Some(ids[1]);
+        state.indent();
+        state.selected = Some(ids[2]);
+        assert_eq!(
+            state.tags().into_iter().collect::<Vec<_>>(),
+            ["home", "work"]
+        );
+
+        state.next_tag_filter();
+        assert_eq!(state.tag_filter(), Some("home"));
+        // "a" is shown as the task "b" is a subtask of.
+        assert_eq!(outline(&state), ["a", "  b"]);
+        assert_eq!(state.selected, Some(ids[0]));
+        state.next_tag_filter();
+        assert_eq!(outline(&state), ["a", "d"]);
+        state.selected = Some(ids[3]);
+        state.move_up();
+        assert_eq!(outline(&state), ["d", "a"], "moves past the hidden c");
+        state.next_tag_filter();
+        assert_eq!(state.tag_filter(), None);
+        assert_eq!(outline(&state), ["d", "a", "  b", "c"]);
+    }
+
+    #[test]
+    fn sort_modes_keep_the_stored_order() {
+        let (mut state, ids) = state_with(&["a", "b", "c"]);
+        state
+            .store
+            .with_transaction(|txn| {
+                for (id, due, priority) in [
+                    (ids[0], None, Some(Priority::Low)),
+                    (ids[1], "2024-07-05".parse().ok(), None),
+                    (ids[2], "2024-07-01".parse().ok(), Some(Priority::High)),
+                ] {
+                    let mut task = txn.get_task(&id)?;
+                    task.set_due(due);
+                    task.set_priority(priority);
+                    txn.put_task(&task)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        state.toggle_id(&ids[2]);
+
+        state.sort_mode = SortMode::Due;
+        assert_eq!(outline(&state), ["c", "b", "a"]);
+        state.sort_mode = SortMode::Priority;
+        assert_eq!(outline(&state), ["c", "a", "b"]);
+        state.sort_mode = SortMode::Created;
+        assert_eq!(outline(&state), ["a", "b", "c"]);
+        state.sort_mode = SortMode::Completed;
+        assert_eq!(outline(&state), ["a", "b", "c"]);
+
+        // Moves are ignored outside manual order.
+        state.sort_mode = SortMode::Due;
+        state.selected = Some(ids[1]);
+        state.move_up();
+        state.sort_mode = state.sort_mode.next().next().next().next();
+        assert_eq!(state.sort_mode, SortMode::Manual);
+        assert_eq!(outline(&state), ["a", "b", "c"]);
+        state.move_up();
+        assert_eq!(outline(&state), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn completing_a_repeating_task_adds_the_next() {
+        let (mut state, ids) = state_with(&["chore", "other"]);
+        let daily = Recurrence::new(Frequency::Daily, 1).repeat_from(RepeatFrom::Completion);
+        state
+            .store
+            .with_transaction(|txn| {
+                let mut task = txn.get_task(&ids[0])?;
+                task.set_recurrence(Some(daily.clone()));
+                txn.put_task(&task)
+            })
+            .unwrap();
+
+        state.toggle_id(&ids[0]);
+        let tasks = state.list_tasks_for_display();
+        assert_eq!(outline(&state), ["chore", "chore", "other"]);
+        assert!(tasks[0].is_completed());
+        assert_eq!(tasks[0].recurrence(), None);
+        assert!(!tasks[1].is_completed());
+        assert_eq!(tasks[1].due(), today().succ_opt());
+        assert_eq!(tasks[1].recurrence(), Some(&daily));
+
+        // Reopening the completed task does not add another.
+        state.toggle_id(&ids[0]);
+        assert_eq!(state.list_tasks_for_display().len(), 3);
+        state.undo();
+        state.undo();
+        assert_eq!(outline(&state), ["chore", "other"]);
+    }
+
+    #[test]
+    fn snooze_until_hides_the_task_and_can_be_undone() {
+        let (mut state, ids) = state_with(&["a", "b", "c"]);
+        state.selected = Some(ids[1]);
+        let tomorrow = today().succ_opt();
+
+        state.snooze_until(&ids[1], tomorrow);
+        assert_eq!(outline(&state), ["a", "c"]);
+        assert_eq!(state.selected, Some(ids[0]));
+        assert_eq!(state.get_task(&ids[1]).unwrap().snoozed(), tomorrow);
+
+        // Snoozing until today leaves the task shown.
+        state.snooze_until(&ids[2], Some(today()));
+        assert_eq!(outline(&state), ["a", "c"]);
+
+        state.undo();
+        state.undo();
+        assert_eq!(outline(&state), ["a", "b", "c"]);
+        assert_eq!(state.get_task(&ids[1]).unwrap().snoozed(), None);
+        state.redo();
+        assert_eq!(outline(&state), ["a", "c"]);
+        state.snooze_until(&ids[1], None);
+        assert_eq!(outline(&state), ["a", "b", "c"]);
     }
 }
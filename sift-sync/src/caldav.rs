@@ -0,0 +1,617 @@
+//! Sync with a CalDAV task collection, such as one on a Radicale server.
+//!
+//! Each task is a `VTODO` in its own `<id>.ics` resource.  The [`SyncState`]
+//! records the document's heads at the last sync and the ETag of each task's
+//! resource then, so that a sync fetches only resources whose ETag changed
+//! and writes only tasks that changed on either side.  Writes are
+//! conditional on the ETag: a resource changed on the server in the meantime
+//! is left alone and counted as a conflict, to be merged on the next sync.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sift_persist::{export_tasks, import_tasks, ChangeHash, Format, Store, Task, TaskId, TaskList};
+use url::Url;
+
+#[cfg(test)]
+mod mock;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid collection URL `{0}`")]
+    InvalidUrl(String, #[source] url::ParseError),
+    #[error("Request to `{0}` failed")]
+    Request(String, #[source] Box<ureq::Error>),
+    #[error("Cannot read the response from `{0}`")]
+    Response(String, #[source] io::Error),
+    #[error("Invalid WebDAV response from `{0}`")]
+    Xml(String, #[source] roxmltree::Error),
+    #[error("`{0}` changed on the server")]
+    Conflict(String),
+    #[error("Invalid task at `{0}`")]
+    Task(String, #[source] sift_persist::Error),
+    #[error("No task found at `{0}`")]
+    NoTask(String),
+    #[error("Cannot read sync state `{1}`")]
+    ReadState(#[source] io::Error, PathBuf),
+    #[error("Cannot write sync state `{1}`")]
+    WriteState(#[source] io::Error, PathBuf),
+    #[error("Invalid sync state `{1}`")]
+    InvalidState(#[source] serde_json::Error, PathBuf),
+}
+
+const PROPFIND_ETAGS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><getetag/></prop></propfind>"#;
+
+/// A CalDAV collection of tasks.
+pub struct Collection {
+    url: Url,
+    agent: ureq::Agent,
+    authorization: Option<String>,
+}
+
+impl Collection {
+    /// Use the collection at `url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `url` is not a valid URL.
+    pub fn new(url: &str) -> Result<Collection, Error> {
+        let mut parsed = Url::parse(url).map_err(|e| Error::InvalidUrl(url.to_string(), e))?;
+        // Resources are resolved relative to the collection, which only
+        // works if its path ends in a slash.
+        if !parsed.path().ends_with('/') {
+            let path = format!("{}/", parsed.path());
+            parsed.set_path(&path);
+        }
+        Ok(Collection {
+            url: parsed,
+            agent: ureq::AgentBuilder::new().build(),
+            authorization: None,
+        })
+    }
+
+    /// Authenticate with HTTP basic authentication.
+    #[must_use]
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials = format!("{}:{}", username, password);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        self.authorization = Some(format!("Basic {}", encoded));
+        self
+    }
+
+    /// The URL of the collection.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    // The URL a task new to the server is written to.
+    fn task_url(&self, id: &TaskId) -> String {
+        self.url
+            .join(&format!("{}.ics", id))
+            .expect("a task id is a valid path segment")
+            .into()
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self.agent.request(method, url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    fn response(
+        url: &str,
+        result: Result<ureq::Response, ureq::Error>,
+    ) -> Result<ureq::Response, Error> {
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(412, _)) => Err(Error::Conflict(url.to_string())),
+            Err(e) => Err(Error::Request(url.to_string(), Box::new(e))),
+        }
+    }
+
+    /// List the URL and ETag of each resource in the collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response is not a valid
+    /// WebDAV multistatus.
+    pub fn list(&self) -> Result<Vec<(String, String)>, Error> {
+        let url = self.url.as_str();
+        let result = self
+            .request("PROPFIND", url)
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND_ETAGS);
+        let body = Self::response(url, result)?
+            .into_string()
+            .map_err(|e| Error::Response(url.to_string(), e))?;
+        let xml = roxmltree::Document::parse(&body).map_err(|e| Error::Xml(url.to_string(), e))?;
+        let dav = |node: &roxmltree::Node, name: &str| {
+            node.tag_name().namespace() == Some("DAV:") && node.tag_name().name() == name
+        };
+        let mut resources = Vec::new();
+        for response in xml.descendants().filter(|node| dav(node, "response")) {
+            let text = |name: &str| {
+                response
+                    .descendants()
+                    .find(|node| dav(node, name))
+                    .and_then(|node| node.text())
+                    .map(str::trim)
+                    .filter(|text| !text.is_empty())
+            };
+            let (Some(href), Some(etag)) = (text("href"), text("getetag")) else {
+                continue;
+            };
+            let Ok(resource) = self.url.join(href) else {
+                continue;
+            };
+            if resource != self.url {
+                resources.push((resource.into(), etag.to_string()));
+            }
+        }
+        Ok(resources)
+    }
+
+    /// Fetch the resource at `url`, returning its body and ETag.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub fn get(&self, url: &str) -> Result<(String, Option<String>), Error> {
+        let response = Self::response(url, self.request("GET", url).call())?;
+        let etag = response.header("ETag").map(str::to_string);
+        let body = response
+            .into_string()
+            .map_err(|e| Error::Response(url.to_string(), e))?;
+        Ok((body, etag))
+    }
+
+    /// Write `body` to the resource at `url`, returning its new ETag if the
+    /// server sent one.  With an `etag`, the resource is only replaced if it
+    /// still has that ETag; without, it is only written if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Conflict`] if the resource does not match, or an
+    /// error if the request fails.
+    pub fn put(&self, url: &str, body: &str, etag: Option<&str>) -> Result<Option<String>, Error> {
+        let request = self
+            .request("PUT", url)
+            .set("Content-Type", "text/calendar; charset=utf-8");
+        let request = match etag {
+            Some(etag) => request.set("If-Match", etag),
+            None => request.set("If-None-Match", "*"),
+        };
+        let response = Self::response(url, request.send_string(body))?;
+        Ok(response.header("ETag").map(str::to_string))
+    }
+
+    /// Delete the resource at `url` if it still has `etag`.  A resource that
+    /// is already gone is not an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Conflict`] if the resource changed, or an error if
+    /// the request fails.
+    pub fn delete(&self, url: &str, etag: Option<&str>) -> Result<(), Error> {
+        let request = self.request("DELETE", url);
+        let request = match etag {
+            Some(etag) => request.set("If-Match", etag),
+            None => request,
+        };
+        match Self::response(url, request.call()) {
+            Err(Error::Request(_, e)) if matches!(*e, ureq::Error::Status(404, _)) => Ok(()),
+            result => result.map(drop),
+        }
+    }
+}
+
+// Where a task is on the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Resource {
+    url: String,
+    etag: Option<String>,
+}
+
+/// What is known about a collection as of the last sync with it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    collection: String,
+    heads: Vec<String>,
+    resources: BTreeMap<String, Resource>,
+}
+
+impl SyncState {
+    /// Read the sync state saved at `path`, or start afresh if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a sync state.
+    pub fn load(path: &Path) -> Result<SyncState, Error> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Error::InvalidState(e, path.to_path_buf())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(e) => Err(Error::ReadState(e, path.to_path_buf())),
+        }
+    }
+
+    /// Save the sync state to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(self).expect("sync state serializes");
+        fs::write(path, json).map_err(|e| Error::WriteState(e, path.to_path_buf()))
+    }
+}
+
+/// Return where the CalDAV sync state of the task file at `path` is kept.
+#[must_use]
+pub fn state_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".caldav.json");
+    PathBuf::from(name)
+}
+
+// Whether the server would store `a` and `b` the same, given that it keeps
+// completion times to the second and no extensions.
+fn same_on_server(a: &Task, b: &Task) -> bool {
+    a.id() == b.id()
+        && a.title() == b.title()
+        && a.snoozed() == b.snoozed()
+        && a.due() == b.due()
+        && a.completed().map(|time| time.timestamp()) == b.completed().map(|time| time.timestamp())
+}
+
+fn parse_task(url: &str, body: &str) -> Result<Task, Error> {
+    import_tasks(body.as_bytes(), Format::ICalendar)
+        .map_err(|e| Error::Task(url.to_string(), e))?
+        .tasks
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::NoTask(url.to_string()))
+}
+
+fn format_task(task: &Task) -> String {
+    let tasks = TaskList {
+        tasks: vec![task.clone()],
+    };
+    let mut body = Vec::new();
+    export_tasks(&tasks, Format::ICalendar, &mut body).expect("writing to memory succeeds");
+    String::from_utf8(body).expect("iCalendar is UTF-8")
+}
+
+/// Sync `store` with `collection`, updating `state`.  Changes from the server
+/// are one undoable change to the store, which the caller saves before
+/// saving `state`.
+///
+/// # Errors
+///
+/// Returns any error from the server or the store.  Writes the server
+/// refuses because of a conflict are counted in the report instead.
+pub fn sync<S: Store>(
+    store: &mut S,
+    collection: &Collection,
+    state: &mut SyncState,
+) -> anyhow::Result<Report> {
+    if state.collection != collection.url() {
+        *state = SyncState {
+            collection: collection.url().to_string(),
+            ..SyncState::default()
+        };
+    }
+    let mut report = Report::default();
+
+    let base = base_heads(store, &state.heads);
+    let base_tasks = store.list_tasks_at(&base)?;
+
+    // Fetch what changed on the server.
+    let ids: HashMap<&str, &str> = state
+        .resources
+        .iter()
+        .map(|(id, resource)| (resource.url.as_str(), id.as_str()))
+        .collect();
+    let listing = collection.list()?;
+    let mut pulled = Vec::new();
+    let mut fetched = Vec::new();
+    for (url, etag) in &listing {
+        let unchanged = ids
+            .get(url.as_str())
+            .is_some_and(|id| state.resources[*id].etag.as_deref() == Some(etag.as_str()));
+        if !unchanged {
+            let (body, _) = collection.get(url)?;
+            let task = parse_task(url, &body)?;
+            fetched.push((task.id(), url.clone(), etag.clone()));
+            pulled.push(task);
+        }
+    }
+    let listed: HashSet<&str> = listing.iter().map(|(url, _)| url.as_str()).collect();
+    let removed: Vec<TaskId> = state
+        .resources
+        .iter()
+        .filter(|(_, resource)| !listed.contains(resource.url.as_str()))
+        .filter_map(|(id, _)| id.parse().ok())
+        .collect();
+    for (id, url, etag) in fetched {
+        let etag = Some(etag);
+        state
+            .resources
+            .insert(id.to_string(), Resource { url, etag });
+    }
+
+    // Apply them as of the last sync, to merge with local changes since.
+    if !pulled.is_empty() || !removed.is_empty() {
+        let mut previous = base_tasks.last().map(Task::id);
+        store.merge_at(&base, |txn| {
+            for task in &pulled {
+                if let Ok(existing) = txn.get_task(&task.id()) {
+                    let mut task = task.clone();
+                    task.set_notes(existing.notes().to_string());
+                    task.set_priority(existing.priority());
+                    task.set_recurrence(existing.recurrence().cloned());
+                    task.set_list(existing.list());
+                    task.set_tags(existing.tags().clone());
+                    task.keep_extensions_of(&existing);
+                    txn.put_task(&task)?;
+                } else {
+                    txn.insert_task(None, previous.as_ref(), task)?;
+                    previous = Some(task.id());
+                }
+            }
+            for id in &removed {
+                if txn.get_task(id).is_ok() {
+                    txn.delete_task(id)?;
+                }
+            }
+            Ok(())
+        })?;
+        report.pulled = pulled.len();
+        report.removed = removed.len();
+    }
+    for id in &removed {
+        state.resources.remove(&id.to_string());
+    }
+
+    // Write back what differs from the server's copy.
+    let pulled: HashMap<TaskId, &Task> = pulled.iter().map(|task| (task.id(), task)).collect();
+    let base_tasks: HashMap<TaskId, &Task> =
+        base_tasks.iter().map(|task| (task.id(), task)).collect();
+    let tasks = store.list_tasks()?;
+    for task in &tasks {
+        let id = task.id();
+        let resource = state.resources.get(&id.to_string());
+        // The server's copy is unknown if it was neither fetched now nor
+        // synced last time, so the task is written anyway.
+        let server = pulled.get(&id).or_else(|| base_tasks.get(&id));
+        if resource.is_some() && server.is_some_and(|server| same_on_server(task, server)) {
+            continue;
+        }
+        let url =
+            resource.map_or_else(|| collection.task_url(&id), |resource| resource.url.clone());
+        let etag = resource.and_then(|resource| resource.etag.clone());
+        match collection.put(&url, &format_task(task), etag.as_deref()) {
+            Ok(etag) => {
+                state
+                    .resources
+                    .insert(id.to_string(), Resource { url, etag });
+                report.pushed += 1;
+            }
+            Err(Error::Conflict(_)) => report.conflicts += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // Delete from the server what was deleted locally.
+    let local: HashSet<String> = tasks.iter().map(|task| task.id().to_string()).collect();
+    let deleted: Vec<String> = state
+        .resources
+        .keys()
+        .filter(|id| !local.contains(*id))
+        .cloned()
+        .collect();
+    for id in deleted {
+        let resource = &state.resources[&id];
+        match collection.delete(&resource.url, resource.etag.as_deref()) {
+            Ok(()) => {
+                state.resources.remove(&id);
+                report.deleted += 1;
+            }
+            Err(Error::Conflict(_)) => report.conflicts += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // A refused write is merged on the next sync.  Until then the last sync
+    // stays the base, or the local change it carried would be taken as
+    // already on the server and lost in the merge.
+    if report.conflicts == 0 {
+        state.heads = format_heads(store);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use sift_persist::MemoryStore;
+
+    use super::mock::MockServer;
+    use super::*;
+
+    fn task(title: &str) -> Task {
+        Task::new(Task::new_id(), title.to_string(), None, None, None)
+    }
+
+    fn store_with(tasks: &[&Task]) -> MemoryStore {
+        let mut store = MemoryStore::default();
+        store
+            .with_transaction(|txn| {
+                let mut previous = None;
+                for task in tasks {
+                    txn.insert_task(None, previous.as_ref(), task)?;
+                    previous = Some(task.id());
+                }
+                Ok(())
+            })
+            .unwrap();
+        store
+    }
+
+    fn name(task: &Task) -> String {
+        format!("{}.ics", task.id())
+    }
+
+    fn server_task(server: &MockServer, task: &Task) -> Task {
+        parse_task("", &server.get(&name(task)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pushes_local_tasks_and_pulls_remote_ones() {
+        let server = MockServer::start();
+        let collection = Collection::new(server.url()).unwrap();
+        let mut state = SyncState::default();
+        let a = task("a");
+        let mut store = store_with(&[&a]);
+
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report.pushed, 1);
+        assert_eq!(server.names(), [name(&a)]);
+        assert_eq!(server_task(&server, &a), a);
+
+        let b = task("b");
+        server.put(&name(&b), format_task(&b));
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(report.pushed, 0);
+        assert_eq!(store.list_tasks().unwrap(), [a, b]);
+
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report, Report::default());
+    }
+
+    #[test]
+    fn concurrent_edits_are_merged() {
+        let server = MockServer::start();
+        let collection = Collection::new(server.url()).unwrap();
+        let mut state = SyncState::default();
+        let a = task("a");
+        let mut store = store_with(&[&a]);
+        sync(&mut store, &collection, &mut state).unwrap();
+
+        let mut remote = a.clone();
+        remote.set_title("edited on the server".to_string());
+        server.put(&name(&a), format_task(&remote));
+        let mut local = a.clone();
+        local.set_due("2024-07-05".parse().ok());
+        store.with_transaction(|txn| txn.put_task(&local)).unwrap();
+
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(report.pushed, 1);
+        let mut merged = remote.clone();
+        merged.set_due(local.due());
+        assert_eq!(store.list_tasks().unwrap(), [merged.clone()]);
+        assert_eq!(server_task(&server, &a), merged);
+
+        // The merge is one undoable change.
+        store.undo().unwrap();
+        assert_eq!(store.list_tasks().unwrap(), [local]);
+    }
+
+    #[test]
+    fn refused_writes_are_merged_on_the_next_sync() {
+        let server = MockServer::start();
+        let collection = Collection::new(server.url()).unwrap();
+        let mut state = SyncState::default();
+        let a = task("a");
+        let mut store = store_with(&[&a]);
+        sync(&mut store, &collection, &mut state).unwrap();
+
+        // The server's copy changes between listing and writing.
+        let mut remote = a.clone();
+        remote.set_title("edited on the server".to_string());
+        server.put_after_listing(&name(&a), format_task(&remote));
+        let mut local = a.clone();
+        local.set_due("2024-07-05".parse().ok());
+        store.with_transaction(|txn| txn.put_task(&local)).unwrap();
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(store.list_tasks().unwrap(), [local.clone()]);
+
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(report.pushed, 1);
+        assert_eq!(report.conflicts, 0);
+        let mut merged = remote.clone();
+        merged.set_due(local.due());
+        assert_eq!(store.list_tasks().unwrap(), [merged.clone()]);
+        assert_eq!(server_task(&server, &a), merged);
+    }
+
+    #[test]
+    fn deletions_are_synced_both_ways() {
+        let server = MockServer::start();
+        let collection = Collection::new(server.url()).unwrap();
+        let mut state = SyncState::default();
+        let (a, b) = (task("a"), task("b"));
+        let mut store = store_with(&[&a, &b]);
+        sync(&mut store, &collection, &mut state).unwrap();
+
+        server.delete(&name(&a));
+        store
+            .with_transaction(|txn| txn.delete_task(&b.id()))
+            .unwrap();
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.deleted, 1);
+        assert_eq!(store.list_tasks().unwrap(), []);
+        assert!(server.names().is_empty());
+    }
+
+    #[test]
+    fn writes_to_resources_changed_meanwhile_are_conflicts() {
+        let server = MockServer::start();
+        let collection = Collection::new(server.url()).unwrap();
+        let a = task("a");
+        let etag = collection
+            .put(&collection.task_url(&a.id()), &format_task(&a), None)
+            .unwrap();
+        assert!(etag.is_some());
+        assert!(matches!(
+            collection.put(&collection.task_url(&a.id()), &format_task(&a), None),
+            Err(Error::Conflict(_))
+        ));
+        server.put(&name(&a), format_task(&a));
+        assert!(matches!(
+            collection.delete(&collection.task_url(&a.id()), etag.as_deref()),
+            Err(Error::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn state_survives_saving() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.sift");
+        let server = MockServer::start();
+        let collection = Collection::new(server.url()).unwrap();
+        let mut state = SyncState::default();
+        let mut store = store_with(&[&task("a"), &task("b")]);
+        sync(&mut store, &collection, &mut state).unwrap();
+        store.save(&path).unwrap();
+        state.save(&state_path(&path)).unwrap();
+        drop(store);
+
+        let mut store = MemoryStore::load(&path).unwrap();
+        let mut state = SyncState::load(&state_path(&path)).unwrap();
+        let report = sync(&mut store, &collection, &mut state).unwrap();
+        assert_eq!(report, Report::default());
+    }
+}
@@ -0,0 +1,181 @@
+//! An in-process CalDAV server for tests, speaking just enough WebDAV for
+//! [`super::sync`]: `PROPFIND` of ETags, and `GET`, `PUT` and `DELETE` with
+//! `If-Match` and `If-None-Match`.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const COLLECTION: &str = "/tasks/";
+
+// Resources by name, with their body and ETag.
+#[derive(Default)]
+struct Resources {
+    resources: BTreeMap<String, (String, String)>,
+    version: u64,
+    // A write by another client to make once the collection is next listed.
+    after_listing: Option<(String, String)>,
+}
+
+impl Resources {
+    fn store(&mut self, name: &str, body: String) -> String {
+        self.version += 1;
+        let etag = format!("\"{}\"", self.version);
+        self.resources
+            .insert(name.to_string(), (body, etag.clone()));
+        etag
+    }
+}
+
+pub(super) struct MockServer {
+    url: String,
+    server: Arc<Server>,
+    resources: Arc<Mutex<Resources>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn request_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn propfind(resources: &Resources) -> Response<io::Cursor<Vec<u8>>> {
+    let mut body = format!(
+        "<?xml version=\"1.0\"?>\n<d:multistatus xmlns:d=\"DAV:\">\
+         <d:response><d:href>{}</d:href><d:propstat><d:prop/>\
+         <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        COLLECTION
+    );
+    for (name, (_, etag)) in &resources.resources {
+        body.push_str(&format!(
+            "<d:response><d:href>{}{}</d:href><d:propstat><d:prop>\
+             <d:getetag>{}</d:getetag></d:prop>\
+             <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+            COLLECTION,
+            name,
+            etag.replace('"', "&quot;")
+        ));
+    }
+    body.push_str("</d:multistatus>");
+    Response::from_string(body).with_status_code(207)
+}
+
+fn handle(mut request: Request, resources: &Mutex<Resources>) {
+    let mut resources = resources.lock().unwrap();
+    let name = request
+        .url()
+        .strip_prefix(COLLECTION)
+        .unwrap_or_default()
+        .to_string();
+    let current = resources.resources.get(&name).map(|(_, etag)| etag.clone());
+    let if_match = request_header(&request, "If-Match").map(str::to_string);
+    let if_none_match = request_header(&request, "If-None-Match").is_some();
+    let precondition_failed = match (&if_match, &current) {
+        (Some(expected), Some(current)) => expected != current,
+        (Some(_), None) => true,
+        (None, current) => if_none_match && current.is_some(),
+    };
+
+    let response = match request.method() {
+        Method::NonStandard(method) if method.as_str() == "PROPFIND" => {
+            let response = propfind(&resources);
+            if let Some((name, body)) = resources.after_listing.take() {
+                resources.store(&name, body);
+            }
+            response
+        }
+        _ if precondition_failed => Response::from_string("").with_status_code(412),
+        Method::Get => match resources.resources.get(&name) {
+            Some((body, etag)) => {
+                Response::from_string(body.clone()).with_header(header("ETag", etag))
+            }
+            None => Response::from_string("").with_status_code(404),
+        },
+        Method::Put => {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            let etag = resources.store(&name, body);
+            Response::from_string("")
+                .with_status_code(if current.is_some() { 204 } else { 201 })
+                .with_header(header("ETag", &etag))
+        }
+        Method::Delete => match resources.resources.remove(&name) {
+            Some(_) => Response::from_string("").with_status_code(204),
+            None => Response::from_string("").with_status_code(404),
+        },
+        _ => Response::from_string("").with_status_code(405),
+    };
+    request.respond(response).unwrap();
+}
+
+impl MockServer {
+    pub(super) fn start() -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let port = server.server_addr().to_ip().unwrap().port();
+        let resources = Arc::new(Mutex::new(Resources::default()));
+        let thread = std::thread::spawn({
+            let server = Arc::clone(&server);
+            let resources = Arc::clone(&resources);
+            move || {
+                for request in server.incoming_requests() {
+                    handle(request, &resources);
+                }
+            }
+        });
+        MockServer {
+            url: format!("http://127.0.0.1:{}{}", port, COLLECTION),
+            server,
+            resources,
+            thread: Some(thread),
+        }
+    }
+
+    pub(super) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Write a resource, as another client would.
+    pub(super) fn put(&self, name: &str, body: String) {
+        self.resources.lock().unwrap().store(name, body);
+    }
+
+    /// Write a resource, as another client would, just after the collection
+    /// is next listed.
+    pub(super) fn put_after_listing(&self, name: &str, body: String) {
+        self.resources.lock().unwrap().after_listing = Some((name.to_string(), body));
+    }
+
+    /// Delete a resource, as another client would.
+    pub(super) fn delete(&self, name: &str) {
+        self.resources.lock().unwrap().resources.remove(name);
+    }
+
+    pub(super) fn get(&self, name: &str) -> Option<String> {
+        let resources = self.resources.lock().unwrap();
+        resources.resources.get(name).map(|(body, _)| body.clone())
+    }
+
+    pub(super) fn names(&self) -> Vec<String> {
+        let resources = self.resources.lock().unwrap();
+        resources.resources.keys().cloned().collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
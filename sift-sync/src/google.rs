@@ -0,0 +1,751 @@
+//! Sync with a task list in Google Tasks.
+//!
+//! Sift's data model is loosely based on the Google Tasks API, so tasks map
+//! across directly: `title`, `notes`, `due`, `status` and `completed` are the
+//! task's fields, and its place in the list is its `position`, which the API
+//! changes by naming the `previous` task just as [`Transaction::insert_task`]
+//! and [`Transaction::move_task`] do.  Google Tasks has no snooze date,
+//! priority, recurrence or tags, so those are left as they are locally.
+//! Subtasks are synced as top-level tasks, in the order they are stored, and
+//! keep their parent locally when moved on the server.
+//!
+//! Google assigns its own ids to tasks, which the [`SyncState`] maps task ids
+//! to.  It also records when tasks last changed on the server, so that a sync
+//! lists only the tasks updated since, with `updatedMin`.
+//!
+//! The API is reached through the [`TasksApi`] trait, which [`RestClient`]
+//! implements over REST.
+//!
+//! [`Transaction::insert_task`]: sift_persist::Transaction::insert_task
+//! [`Transaction::move_task`]: sift_persist::Transaction::move_task
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sift_persist::{Store, Task, TaskId};
+
+use crate::{base_heads, format_heads, Report};
+
+#[cfg(test)]
+mod fake;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Request to `{0}` failed")]
+    Request(String, #[source] Box<ureq::Error>),
+    #[error("Invalid response from `{0}`")]
+    Response(String, #[source] serde_json::Error),
+    #[error("No task `{0}` on the server")]
+    NotFound(String),
+    #[error("Cannot read sync state `{1}`")]
+    ReadState(#[source] io::Error, PathBuf),
+    #[error("Cannot write sync state `{1}`")]
+    WriteState(#[source] io::Error, PathBuf),
+    #[error("Invalid sync state `{1}`")]
+    InvalidState(#[source] serde_json::Error, PathBuf),
+}
+
+const NEEDS_ACTION: &str = "needsAction";
+const COMPLETED: &str = "completed";
+
+/// A task as the Google Tasks API represents it, with the fields Sift syncs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteTask {
+    /// The task's id on the server, or empty for a task not yet created.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// When the task last changed.  Set by the server.
+    #[serde(default, skip_serializing)]
+    pub updated: Option<DateTime<Utc>>,
+    /// Where the task is in the list, which is in the order of positions as
+    /// strings.  Set by the server.
+    #[serde(default, skip_serializing)]
+    pub position: Option<String>,
+    /// `needsAction` or `completed`.
+    #[serde(default)]
+    pub status: String,
+    /// The due date, at midnight UTC: the API ignores the time of day.
+    pub due: Option<DateTime<Utc>>,
+    pub completed: Option<DateTime<Utc>>,
+    /// Whether the task was deleted, in listings that include deleted tasks.
+    #[serde(default, skip_serializing)]
+    pub deleted: bool,
+}
+
+impl From<&Task> for RemoteTask {
+    fn from(task: &Task) -> Self {
+        let status = if task.is_completed() {
+            COMPLETED
+        } else {
+            NEEDS_ACTION
+        };
+        RemoteTask {
+            title: task.title().to_string(),
+            notes: task.notes().to_string(),
+            status: status.to_string(),
+            due: task.due().map(|due| due.and_time(NaiveTime::MIN).and_utc()),
+            completed: task.completed(),
+            ..RemoteTask::default()
+        }
+    }
+}
+
+impl RemoteTask {
+    // The task with `id` that this is on the server, keeping the snooze date,
+    // priority, recurrence, list, tags and extensions of the `existing` local
+    // task.
+    fn to_task(&self, id: TaskId, existing: Option<&Task>) -> Task {
+        let completed = (self.status == COMPLETED).then(|| self.completed.unwrap_or_else(Utc::now));
+        let mut task = Task::new(
+            id,
+            self.title.clone(),
+            existing.and_then(Task::snoozed),
+            self.due.map(|due| due.date_naive()),
+            completed,
+        );
+        task.set_notes(self.notes.clone());
+        if let Some(existing) = existing {
+            task.set_priority(existing.priority());
+            task.set_recurrence(existing.recurrence().cloned());
+            task.set_list(existing.list());
+            task.set_tags(existing.tags().clone());
+            task.keep_extensions_of(existing);
+        }
+        task
+    }
+
+    // Whether this has the synced fields of `task`, given that the server
+    // keeps completion times to the second.
+    fn matches(&self, task: &Task) -> bool {
+        let other = RemoteTask::from(task);
+        self.title == other.title
+            && self.notes == other.notes
+            && self.due.map(|due| due.date_naive()) == task.due()
+            && (self.status == COMPLETED) == task.is_completed()
+            && (!task.is_completed()
+                || self.completed.map(|time| time.timestamp())
+                    == other.completed.map(|time| time.timestamp()))
+    }
+}
+
+/// The Google Tasks API, as far as syncing one task list needs it.
+pub trait TasksApi {
+    /// The id of the task list being synced.
+    fn task_list(&self) -> &str;
+
+    /// List the tasks updated at or after `updated_min`, or all of them,
+    /// including deleted ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    fn list(&mut self, updated_min: Option<DateTime<Utc>>) -> Result<Vec<RemoteTask>, Error>;
+
+    /// Create `task` after the task with id `previous`, or first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    fn insert(&mut self, task: &RemoteTask, previous: Option<&str>) -> Result<RemoteTask, Error>;
+
+    /// Replace the fields of the task with id `task.id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    fn update(&mut self, task: &RemoteTask) -> Result<RemoteTask, Error>;
+
+    /// Move the task with `id` after the task with id `previous`, or first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    fn move_task(&mut self, id: &str, previous: Option<&str>) -> Result<RemoteTask, Error>;
+
+    /// Delete the task with `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    fn delete(&mut self, id: &str) -> Result<(), Error>;
+}
+
+const API_URL: &str = "https://tasks.googleapis.com/tasks/v1";
+
+// A page of a task listing.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page {
+    #[serde(default)]
+    items: Vec<RemoteTask>,
+    next_page_token: Option<String>,
+}
+
+/// The Google Tasks REST API, authorized with an OAuth access token.
+pub struct RestClient {
+    agent: ureq::Agent,
+    authorization: String,
+    task_list: String,
+}
+
+impl RestClient {
+    /// Use the task list with id `task_list`, such as `@default`, with an
+    /// access token for the `https://www.googleapis.com/auth/tasks` scope.
+    #[must_use]
+    pub fn new(task_list: &str, token: &str) -> Self {
+        RestClient {
+            agent: ureq::AgentBuilder::new().build(),
+            authorization: format!("Bearer {}", token),
+            task_list: task_list.to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/lists/{}/tasks{}", API_URL, self.task_list, path)
+    }
+
+    fn send<T: DeserializeOwned>(
+        &self,
+        request: ureq::Request,
+        body: Option<&RemoteTask>,
+    ) -> Result<T, Error> {
+        let url = request.url().to_string();
+        let request = request.set("Authorization", &self.authorization);
+        let result = match body {
+            Some(body) => request
+                .set("Content-Type", "application/json")
+                .send_string(&serde_json::to_string(body).expect("tasks serialize")),
+            None => request.call(),
+        };
+        let response = result.map_err(|e| Error::Request(url.clone(), Box::new(e)))?;
+        serde_json::from_reader(response.into_reader()).map_err(|e| Error::Response(url, e))
+    }
+
+    fn with_previous(request: ureq::Request, previous: Option<&str>) -> ureq::Request {
+        match previous {
+            Some(previous) => request.query("previous", previous),
+            None => request,
+        }
+    }
+}
+
+impl TasksApi for RestClient {
+    fn task_list(&self) -> &str {
+        &self.task_list
+    }
+
+    fn list(&mut self, updated_min: Option<DateTime<Utc>>) -> Result<Vec<RemoteTask>, Error> {
+        let mut tasks = Vec::new();
+        let mut page_token = None;
+        loop {
+            let mut request = self
+                .agent
+                .get(&self.url(""))
+                .query("maxResults", "100")
+                .query("showCompleted", "true")
+                .query("showDeleted", "true")
+                .query("showHidden", "true");
+            if let Some(updated_min) = updated_min {
+                request = request.query("updatedMin", &updated_min.to_rfc3339());
+            }
+            if let Some(page_token) = &page_token {
+                request = request.query("pageToken", page_token);
+            }
+            let page: Page = self.send(request, None)?;
+            tasks.extend(page.items);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                return Ok(tasks);
+            }
+        }
+    }
+
+    fn insert(&mut self, task: &RemoteTask, previous: Option<&str>) -> Result<RemoteTask, Error> {
+        let request = Self::with_previous(self.agent.post(&self.url("")), previous);
+        self.send(request, Some(task))
+    }
+
+    fn update(&mut self, task: &RemoteTask) -> Result<RemoteTask, Error> {
+        let request = self.agent.put(&self.url(&format!("/{}", task.id)));
+        self.send(request, Some(task))
+    }
+
+    fn move_task(&mut self, id: &str, previous: Option<&str>) -> Result<RemoteTask, Error> {
+        let request = self.agent.post(&self.url(&format!("/{}/move", id)));
+        self.send(Self::with_previous(request, previous), None)
+    }
+
+    fn delete(&mut self, id: &str) -> Result<(), Error> {
+        let url = self.url(&format!("/{}", id));
+        self.agent
+            .delete(&url)
+            .set("Authorization", &self.authorization)
+            .call()
+            .map_err(|e| Error::Request(url, Box::new(e)))?;
+        Ok(())
+    }
+}
+
+// A task's id and position on the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Remote {
+    id: String,
+    position: Option<String>,
+}
+
+/// What is known about a task list as of the last sync with it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    task_list: String,
+    heads: Vec<String>,
+    updated_min: Option<DateTime<Utc>>,
+    tasks: BTreeMap<String, Remote>,
+}
+
+impl SyncState {
+    /// Read the sync state saved at `path`, or start afresh if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a sync state.
+    pub fn load(path: &Path) -> Result<SyncState, Error> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| Error::InvalidState(e, path.to_path_buf())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(e) => Err(Error::ReadState(e, path.to_path_buf())),
+        }
+    }
+
+    /// Save the sync state to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(self).expect("sync state serializes");
+        fs::write(path, json).map_err(|e| Error::WriteState(e, path.to_path_buf()))
+    }
+
+    // The tasks before the one with `id` on the server, nearest first.
+    fn before(&self, id: &str) -> Vec<(&str, &Remote)> {
+        let Some(position) = self
+            .tasks
+            .get(id)
+            .and_then(|remote| remote.position.as_ref())
+        else {
+            return Vec::new();
+        };
+        let mut before: Vec<_> = self
+            .tasks
+            .iter()
+            .filter(|(other, remote)| {
+                *other != id
+                    && remote
+                        .position
+                        .as_ref()
+                        .is_some_and(|other| other < position)
+            })
+            .map(|(other, remote)| (other.as_str(), remote))
+            .collect();
+        before.sort_by(|(_, a), (_, b)| b.position.cmp(&a.position));
+        before
+    }
+}
+
+/// Return where the Google Tasks sync state of the task file at `path` is
+/// kept.
+#[must_use]
+pub fn state_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".google.json");
+    PathBuf::from(name)
+}
+
+/// Sync `store` with the task list `api` is for, updating `state`.  Changes
+/// from the server are one undoable change to the store, which the caller
+/// saves before saving `state`.
+///
+/// # Errors
+///
+/// Returns any error from the server or the store.
+pub fn sync<S: Store, A: TasksApi>(
+    store: &mut S,
+    api: &mut A,
+    state: &mut SyncState,
+) -> anyhow::Result<Report> {
+    if state.task_list != api.task_list() {
+        *state = SyncState {
+            task_list: api.task_list().to_string(),
+            ..SyncState::default()
+        };
+    }
+    let mut report = Report::default();
+
+    let base = base_heads(store, &state.heads);
+    let base_tasks = store.list_tasks_at(&base)?;
+    let base_tasks: HashMap<TaskId, &Task> =
+        base_tasks.iter().map(|task| (task.id(), task)).collect();
+
+    // Fetch what changed on the server, in list order so that tasks are
+    // placed after the tasks before them.
+    let ids: HashMap<String, TaskId> = state
+        .tasks
+        .iter()
+        .filter_map(|(id, remote)| Some((remote.id.clone(), id.parse().ok()?)))
+        .collect();
+    let mut changes = api.list(state.updated_min)?;
+    let updated_min = changes.iter().filter_map(|task| task.updated).max();
+    changes.sort_by(|a, b| a.position.cmp(&b.position));
+    let mut pulled = HashMap::new();
+    let mut placed = Vec::new();
+    let mut removed = Vec::new();
+    for remote in changes {
+        let known = ids.get(&remote.id).copied();
+        if remote.deleted {
+            if let Some(id) = known {
+                state.tasks.remove(&id.to_string());
+                removed.push(id);
+            }
+            continue;
+        }
+        let id = known.unwrap_or_else(Task::new_id);
+        let entry = state.tasks.entry(id.to_string()).or_insert(Remote {
+            id: remote.id.clone(),
+            position: None,
+        });
+        if entry.position != remote.position {
+            entry.position.clone_from(&remote.position);
+            placed.push(id);
+        }
+        if !base_tasks.get(&id).is_some_and(|task| remote.matches(task)) {
+            pulled.insert(id, remote);
+        }
+    }
+    let placements: Vec<(TaskId, Vec<TaskId>)> = placed
+        .iter()
+        .map(|id| {
+            let before = state.before(&id.to_string());
+            let before = before.iter().filter_map(|(id, _)| id.parse().ok());
+            (*id, before.collect())
+        })
+        .collect();
+
+    // Apply them as of the last sync, to merge with local changes since.
+    if !pulled.is_empty() || !placed.is_empty() || !removed.is_empty() {
+        store.merge_at(&base, |txn| {
+            let mut inserted = HashSet::new();
+            for (id, before) in &placements {
+                let previous = before.iter().find(|task| txn.get_task(task).is_ok());
+                if let Ok(existing) = txn.get_task(id) {
+                    txn.move_task(existing.parent().as_ref(), previous, id)?;
+                } else if let Some(remote) = pulled.get(id) {
+                    txn.insert_task(None, previous, &remote.to_task(*id, None))?;
+                    inserted.insert(*id);
+                }
+            }
+            for (id, remote) in &pulled {
+                if let Ok(existing) = txn.get_task(id) {
+                    if !inserted.contains(id) {
+                        txn.put_task(&remote.to_task(*id, Some(&existing)))?;
+                    }
+                }
+            }
+            for id in &removed {
+                if txn.get_task(id).is_ok() {
+                    txn.delete_task(id)?;
+                }
+            }
+            Ok(())
+        })?;
+        let changed: HashSet<&TaskId> = pulled.keys().chain(&placed).collect();
+        report.pulled = changed.len();
+        report.removed = removed.len();
+    }
+
+    // Delete from the server what was deleted locally, before placing the
+    // tasks that were after them.
+    let tasks = store.list_tasks()?;
+    let local: HashSet<String> = tasks.iter().map(|task| task.id().to_string()).collect();
+    let deleted: Vec<String> = state
+        .tasks
+        .keys()
+        .filter(|id| !local.contains(*id))
+        .cloned()
+        .collect();
+    for id in deleted {
+        let remote = state.tasks.remove(&id).expect("deleted tasks are known");
+        api.delete(&remote.id)?;
+        report.deleted += 1;
+    }
+
+    // Write back what differs from the server's copy, in order, so that each
+    // task can be placed after the one before it.
+    let mut previous: Option<String> = None;
+    for task in &tasks {
+        let id = task.id();
+        let key = id.to_string();
+        let written = if let Some(remote) = state.tasks.get(&key) {
+            let unchanged = match pulled.get(&id) {
+                Some(server) => server.matches(task),
+                None => base_tasks
+                    .get(&id)
+                    .is_some_and(|base| RemoteTask::from(*base).matches(task)),
+            };
+            let server_previous = state
+                .before(&key)
+                .first()
+                .map(|(_, other)| other.id.clone());
+            let remote_id = remote.id.clone();
+            let placed = remote.position.is_some() && server_previous == previous;
+            let mut written = None;
+            if !unchanged {
+                let update = RemoteTask {
+                    id: remote_id.clone(),
+                    ..RemoteTask::from(task)
+                };
+                written = Some(api.update(&update)?);
+            }
+            if !placed {
+                written = Some(api.move_task(&remote_id, previous.as_deref())?);
+            }
+            written
+        } else {
+            Some(api.insert(&RemoteTask::from(task), previous.as_deref())?)
+        };
+        if let Some(written) = written {
+            report.pushed += 1;
+            let remote = Remote {
+                id: written.id,
+                position: written.position,
+            };
+            state.tasks.insert(key.clone(), remote);
+        }
+        previous = Some(state.tasks[&key].id.clone());
+    }
+
+    state.heads = format_heads(store);
+    if updated_min.is_some() {
+        state.updated_min = updated_min;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use sift_persist::MemoryStore;
+
+    use super::fake::FakeTasks;
+    use super::*;
+
+    fn task(title: &str) -> Task {
+        Task::new(Task::new_id(), title.to_string(), None, None, None)
+    }
+
+    fn store_with(titles: &[&str]) -> MemoryStore {
+        let mut store = MemoryStore::default();
+        store
+            .with_transaction(|txn| {
+                let mut previous = None;
+                for title in titles {
+                    let task = task(title);
+                    txn.insert_task(None, previous.as_ref(), &task)?;
+                    previous = Some(task.id());
+                }
+                Ok(())
+            })
+            .unwrap();
+        store
+    }
+
+    fn titles(store: &MemoryStore) -> Vec<String> {
+        let tasks = store.list_tasks().unwrap();
+        tasks.iter().map(|task| task.title().to_string()).collect()
+    }
+
+    fn local(store: &MemoryStore, title: &str) -> Task {
+        let tasks = store.list_tasks().unwrap();
+        tasks
+            .into_iter()
+            .find(|task| task.title() == title)
+            .unwrap()
+    }
+
+    fn edit(store: &mut MemoryStore, title: &str, edit: impl FnOnce(&mut Task)) {
+        let mut task = local(store, title);
+        edit(&mut task);
+        store.with_transaction(|txn| txn.put_task(&task)).unwrap();
+    }
+
+    fn synced(titles: &[&str]) -> (MemoryStore, FakeTasks, SyncState) {
+        let mut store = store_with(titles);
+        let mut api = FakeTasks::new();
+        let mut state = SyncState::default();
+        sync(&mut store, &mut api, &mut state).unwrap();
+        (store, api, state)
+    }
+
+    #[test]
+    fn first_sync_pushes_tasks_in_order() {
+        let (mut store, mut api, mut state) = synced(&["a", "b", "c"]);
+        assert_eq!(api.titles(), ["a", "b", "c"]);
+
+        let report = sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(report, Report::default());
+        assert_eq!(titles(&store), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn pulls_tasks_into_place_incrementally() {
+        let (mut store, mut api, mut state) = synced(&["a", "b", "c"]);
+        sync(&mut store, &mut api, &mut state).unwrap();
+
+        let a = api.id_of("a");
+        api.add("x", Some(&a));
+        let report = sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(titles(&store), ["a", "x", "b", "c"]);
+        assert!(api.listed() < 4);
+    }
+
+    #[test]
+    fn fields_map_both_ways() {
+        let (mut store, mut api, mut state) = synced(&["a", "b"]);
+        let completed: DateTime<Utc> = "2024-07-03T13:01:42Z".parse().unwrap();
+        edit(&mut store, "a", |task| {
+            task.set_due("2024-07-05".parse().ok());
+            task.set_completed(Some(completed));
+        });
+        sync(&mut store, &mut api, &mut state).unwrap();
+        let remote = api.get(&api.id_of("a")).clone();
+        assert_eq!(remote.status, COMPLETED);
+        assert_eq!(remote.completed, Some(completed));
+        assert_eq!(remote.due, "2024-07-05T00:00:00Z".parse().ok());
+
+        api.edit(&api.id_of("b"), |task| {
+            task.status = COMPLETED.to_string();
+            task.due = "2024-08-01T00:00:00Z".parse().ok();
+        });
+        sync(&mut store, &mut api, &mut state).unwrap();
+        let b = local(&store, "b");
+        assert!(b.is_completed());
+        assert_eq!(b.due(), "2024-08-01".parse().ok());
+    }
+
+    #[test]
+    fn concurrent_edits_are_merged() {
+        let (mut store, mut api, mut state) = synced(&["a"]);
+        edit(&mut store, "a", |task| {
+            task.set_snoozed("2024-07-02".parse().ok());
+            task.set_due("2024-07-05".parse().ok());
+        });
+        api.edit(&api.id_of("a"), |task| {
+            task.title = "edited on the server".to_string();
+        });
+
+        let report = sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(report.pushed, 1);
+        let merged = local(&store, "edited on the server");
+        assert_eq!(merged.snoozed(), "2024-07-02".parse().ok());
+        assert_eq!(merged.due(), "2024-07-05".parse().ok());
+        let remote = api.get(&api.id_of("edited on the server"));
+        assert!(remote.matches(&merged));
+    }
+
+    #[test]
+    fn notes_are_synced_both_ways() {
+        let (mut store, mut api, mut state) = synced(&["a"]);
+        edit(&mut store, "a", |task| {
+            task.set_notes("from sift".to_string())
+        });
+        sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(api.get(&api.id_of("a")).notes, "from sift");
+
+        api.edit(&api.id_of("a"), |task| {
+            task.notes = "from the server".to_string();
+        });
+        sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(local(&store, "a").notes(), "from the server");
+    }
+
+    #[test]
+    fn moves_are_synced_both_ways() {
+        let (mut store, mut api, mut state) = synced(&["a", "b", "c"]);
+        let c = local(&store, "c").id();
+        store
+            .with_transaction(|txn| txn.move_task(None, None, &c))
+            .unwrap();
+        sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(api.titles(), ["c", "a", "b"]);
+
+        let (a, b) = (api.id_of("a"), api.id_of("b"));
+        api.move_task(&a, Some(&b)).unwrap();
+        let report = sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(report.pushed, 0);
+        assert_eq!(titles(&store), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn deletions_are_synced_both_ways() {
+        let (mut store, mut api, mut state) = synced(&["a", "b", "c"]);
+        api.delete(&api.id_of("a")).unwrap();
+        let b = local(&store, "b").id();
+        store.with_transaction(|txn| txn.delete_task(&b)).unwrap();
+
+        let report = sync(&mut store, &mut api, &mut state).unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.deleted, 1);
+        assert_eq!(titles(&store), ["c"]);
+        assert_eq!(api.titles(), ["c"]);
+    }
+
+    #[test]
+    fn reads_the_api_representation() {
+        let page = r#"{
+            "kind": "tasks#tasks",
+            "nextPageToken": "next",
+            "items": [{
+                "kind": "tasks#task",
+                "id": "MTIzNDU2Nzg5",
+                "etag": "\"LTEyMzQ1Njc4OQ\"",
+                "title": "Call the plumber",
+                "updated": "2024-07-03T13:01:42.000Z",
+                "selfLink": "https://www.googleapis.com/tasks/v1/lists/x/tasks/MTIzNDU2Nzg5",
+                "position": "00000000000000000001",
+                "status": "completed",
+                "due": "2024-07-05T00:00:00.000Z",
+                "completed": "2024-07-03T13:01:42.000Z",
+                "links": []
+            }]
+        }"#;
+        let page: Page = serde_json::from_str(page).unwrap();
+        assert_eq!(page.next_page_token.as_deref(), Some("next"));
+        let id = Task::new_id();
+        let task = page.items[0].to_task(id, None);
+        assert_eq!(task.title(), "Call the plumber");
+        assert_eq!(task.due(), "2024-07-05".parse().ok());
+        assert_eq!(task.completed(), "2024-07-03T13:01:42Z".parse().ok());
+
+        let json = serde_json::to_value(RemoteTask::from(&task)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "title": "Call the plumber",
+                "status": "completed",
+                "due": "2024-07-05T00:00:00Z",
+                "completed": "2024-07-03T13:01:42Z",
+            })
+        );
+    }
+}
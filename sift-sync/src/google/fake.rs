@@ -0,0 +1,183 @@
+//! An in-process fake of the Google Tasks API for tests, keeping one task
+//! list in memory with the semantics [`super::sync`] relies on: ids and
+//! positions assigned by the server, an `updated` time bumped by every
+//! change, and deleted tasks kept and listed as such.
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::{Error, RemoteTask, TasksApi, COMPLETED, NEEDS_ACTION};
+
+// The gap between the positions of tasks added to the end of the list.
+const POSITION_STEP: u64 = 1 << 32;
+
+pub(super) struct FakeTasks {
+    // Every task ever created, deleted ones included.
+    tasks: Vec<RemoteTask>,
+    next_id: u64,
+    clock: DateTime<Utc>,
+    listed: usize,
+}
+
+fn position(task: &RemoteTask) -> u64 {
+    task.position
+        .as_deref()
+        .and_then(|position| position.parse().ok())
+        .unwrap_or_default()
+}
+
+impl FakeTasks {
+    pub(super) fn new() -> Self {
+        FakeTasks {
+            tasks: Vec::new(),
+            next_id: 1,
+            clock: "2024-07-01T00:00:00Z".parse().unwrap(),
+            listed: 0,
+        }
+    }
+
+    fn tick(&mut self) -> DateTime<Utc> {
+        self.clock += Duration::seconds(1);
+        self.clock
+    }
+
+    // The live tasks in list order.
+    fn live(&self) -> Vec<&RemoteTask> {
+        let mut live: Vec<_> = self.tasks.iter().filter(|task| !task.deleted).collect();
+        live.sort_by_key(|task| position(task));
+        live
+    }
+
+    // A position after the task with id `previous`, or first, and before the
+    // task after it, ignoring the task with id `moving`.
+    fn position_after(&self, previous: Option<&str>, moving: Option<&str>) -> String {
+        let live: Vec<_> = self
+            .live()
+            .into_iter()
+            .filter(|task| Some(task.id.as_str()) != moving)
+            .collect();
+        let index = previous
+            .and_then(|previous| live.iter().position(|task| task.id == previous))
+            .map_or(0, |index| index + 1);
+        let low = index
+            .checked_sub(1)
+            .map_or(0, |index| position(live[index]));
+        let high = live
+            .get(index)
+            .map_or(low + 2 * POSITION_STEP, |task| position(task));
+        format!("{:020}", low + (high - low) / 2)
+    }
+
+    fn find(&mut self, id: &str) -> Result<&mut RemoteTask, Error> {
+        self.tasks
+            .iter_mut()
+            .find(|task| task.id == id && !task.deleted)
+            .ok_or_else(|| Error::NotFound(id.to_string()))
+    }
+
+    /// Add a task titled `title`, as another client would.
+    pub(super) fn add(&mut self, title: &str, previous: Option<&str>) -> String {
+        let task = RemoteTask {
+            title: title.to_string(),
+            ..RemoteTask::default()
+        };
+        self.insert(&task, previous).unwrap().id
+    }
+
+    /// Change the task with `id`, as another client would.
+    pub(super) fn edit(&mut self, id: &str, edit: impl FnOnce(&mut RemoteTask)) {
+        let mut task = self.find(id).unwrap().clone();
+        edit(&mut task);
+        self.update(&task).unwrap();
+    }
+
+    /// The titles of the live tasks, in list order.
+    pub(super) fn titles(&self) -> Vec<&str> {
+        self.live()
+            .into_iter()
+            .map(|task| task.title.as_str())
+            .collect()
+    }
+
+    /// The id of the live task titled `title`.
+    pub(super) fn id_of(&self, title: &str) -> String {
+        let live = self.live();
+        let task = live.iter().find(|task| task.title == title).unwrap();
+        task.id.clone()
+    }
+
+    pub(super) fn get(&self, id: &str) -> &RemoteTask {
+        self.tasks.iter().find(|task| task.id == id).unwrap()
+    }
+
+    /// How many tasks the last listing returned.
+    pub(super) fn listed(&self) -> usize {
+        self.listed
+    }
+}
+
+impl TasksApi for FakeTasks {
+    fn task_list(&self) -> &str {
+        "@default"
+    }
+
+    fn list(&mut self, updated_min: Option<DateTime<Utc>>) -> Result<Vec<RemoteTask>, Error> {
+        let tasks: Vec<_> = self
+            .tasks
+            .iter()
+            .filter(|task| updated_min.map_or(true, |min| task.updated >= Some(min)))
+            .cloned()
+            .collect();
+        self.listed = tasks.len();
+        Ok(tasks)
+    }
+
+    fn insert(&mut self, task: &RemoteTask, previous: Option<&str>) -> Result<RemoteTask, Error> {
+        let position = self.position_after(previous, None);
+        let mut task = RemoteTask {
+            id: format!("task{}", self.next_id),
+            updated: Some(self.tick()),
+            position: Some(position),
+            ..task.clone()
+        };
+        self.next_id += 1;
+        if task.status != COMPLETED {
+            task.status = NEEDS_ACTION.to_string();
+            task.completed = None;
+        }
+        self.tasks.push(task.clone());
+        Ok(task)
+    }
+
+    fn update(&mut self, task: &RemoteTask) -> Result<RemoteTask, Error> {
+        let updated = self.tick();
+        let stored = self.find(&task.id)?;
+        stored.title.clone_from(&task.title);
+        stored.notes.clone_from(&task.notes);
+        stored.status.clone_from(&task.status);
+        stored.due = task.due;
+        stored.completed = if task.status == COMPLETED {
+            Some(task.completed.unwrap_or(updated))
+        } else {
+            None
+        };
+        stored.updated = Some(updated);
+        Ok(stored.clone())
+    }
+
+    fn move_task(&mut self, id: &str, previous: Option<&str>) -> Result<RemoteTask, Error> {
+        let position = self.position_after(previous, Some(id));
+        let updated = self.tick();
+        let stored = self.find(id)?;
+        stored.position = Some(position);
+        stored.updated = Some(updated);
+        Ok(stored.clone())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<(), Error> {
+        let updated = self.tick();
+        let stored = self.find(id)?;
+        stored.deleted = true;
+        stored.updated = Some(updated);
+        Ok(())
+    }
+}
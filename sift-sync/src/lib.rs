@@ -0,0 +1,49 @@
+/*!
+Two-way sync between a Sift task file and a task server.
+
+Changes made on the server since the last sync are applied to the task file's
+Automerge document as of that sync, so they merge with local changes made
+meanwhile rather than overwriting them, and the merged tasks are then written
+back to the server.  A `sift-relay` server instead holds an Automerge document
+itself, and syncs with the task file over the Automerge sync protocol.
+*/
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::uninlined_format_args)]
+
+use sift_persist::{ChangeHash, Store};
+
+pub mod caldav;
+pub mod google;
+pub mod relay;
+
+/// What a sync did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Tasks added or changed on the server and updated locally.
+    pub pulled: usize,
+    /// Tasks deleted on the server and so deleted locally.
+    pub removed: usize,
+    /// Tasks added or changed locally and written to the server.
+    pub pushed: usize,
+    /// Tasks deleted locally and so deleted on the server.
+    pub deleted: usize,
+    /// Writes the server refused because the task changed there meanwhile.
+    /// They are merged on the next sync.
+    pub conflicts: usize,
+}
+
+// Return the heads of the last sync, saved as `heads`, to find local changes
+// since by comparing with the tasks then.  Without them, as on the first
+// sync, the current heads are used and every task is taken as unchanged.
+pub(crate) fn base_heads<S: Store>(store: &mut S, heads: &[String]) -> Vec<ChangeHash> {
+    let saved: Option<Vec<ChangeHash>> = heads.iter().map(|head| head.parse().ok()).collect();
+    match saved {
+        Some(heads) if !heads.is_empty() && store.list_tasks_at(&heads).is_ok() => heads,
+        _ => store.heads(),
+    }
+}
+
+// Return the store's heads as saved in sync state.
+pub(crate) fn format_heads<S: Store>(store: &mut S) -> Vec<String> {
+    store.heads().iter().map(ToString::to_string).collect()
+}
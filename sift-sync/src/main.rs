@@ -0,0 +1,260 @@
+/*!
+Sync the Sift task file with a task server, or directly with another Sift
+instance.
+*/
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::uninlined_format_args)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use sift_core::save_name;
+use sift_persist::{sync_with_peer, Access, MemoryStore, Passphrase, PeerReport, Side, Store};
+use sift_sync::caldav::{self, Collection};
+use sift_sync::{google, relay, Report};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Two-way sync with a CalDAV task collection, such as one on a Radicale
+    /// server.
+    Caldav {
+        /// The URL of the collection.
+        url: String,
+
+        /// The user name to log in with.  The password is asked for.
+        #[arg(long)]
+        username: Option<String>,
+    },
+    /// Two-way sync with a task list in Google Tasks.
+    Google {
+        /// The id of the task list.
+        #[arg(default_value = "@default")]
+        task_list: String,
+
+        /// An OAuth access token for the Google Tasks API.  Asked for if not
+        /// given.
+        #[arg(long, env = "SIFT_GOOGLE_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Sync through a `sift-relay` server.
+    Relay {
+        /// The URL of your document on the relay, as
+        /// `ws://host:port/<user>`.
+        url: String,
+
+        /// The relay's shared token.  Asked for if not given.
+        #[arg(long, env = "SIFT_RELAY_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+    },
+    /// Wait for other Sift instances to connect and sync with each in turn.
+    Serve {
+        /// The address to listen on.
+        #[arg(default_value = DEFAULT_ADDRESS)]
+        address: String,
+
+        /// Listen on a Unix socket at this path instead.
+        #[cfg(unix)]
+        #[arg(long, conflicts_with = "address")]
+        unix: Option<PathBuf>,
+    },
+    /// Sync with a Sift instance running `sift-sync serve`.
+    Connect {
+        /// The address the other instance listens on, as `host:port`.
+        #[cfg_attr(unix, arg(required_unless_present = "unix"))]
+        #[cfg_attr(not(unix), arg(required = true))]
+        address: Option<String>,
+
+        /// Connect to a Unix socket at this path instead.
+        #[cfg(unix)]
+        #[arg(long, conflicts_with = "address")]
+        unix: Option<PathBuf>,
+    },
+}
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7383";
+
+// How many times to ask for the passphrase of an encrypted file before giving
+// up.
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+// Open the task file at `path`, creating it if there is none yet.  If it is
+// encrypted, ask for its passphrase, unless `passphrase` already holds the
+// one that opened it last; the one that opens it is kept there.
+fn open_store(path: &Path, passphrase: &mut Option<Passphrase>) -> Result<MemoryStore> {
+    if !path.exists() {
+        let mut store = MemoryStore::default();
+        store.save(path)?;
+        return Ok(store);
+    }
+    let mut attempts = 0;
+    loop {
+        let error =
+            match MemoryStore::open_with_passphrase(path, Access::ReadWrite, passphrase.clone()) {
+                Ok(store) => return Ok(store),
+                Err(error) => error,
+            };
+        match error.downcast_ref::<sift_persist::Error>() {
+            Some(
+                sift_persist::Error::PassphraseRequired | sift_persist::Error::WrongPassphrase,
+            ) if attempts < PASSPHRASE_ATTEMPTS => {
+                if passphrase.is_some() {
+                    eprintln!("{}", error);
+                }
+                attempts += 1;
+                let prompt = format!("Passphrase for {}: ", path.display());
+                *passphrase = Some(Passphrase::from(rpassword::prompt_password(prompt)?));
+            }
+            _ => return Err(error),
+        }
+    }
+}
+
+// Sync the task file at `path` with the peer at the other end of `stream`.
+// The file is only opened for the length of the session, so that the Sift
+// apps can use it in between.
+fn sync_peer<T: Read + Write>(
+    path: &Path,
+    passphrase: &mut Option<Passphrase>,
+    stream: &mut T,
+    side: Side,
+) -> Result<()> {
+    let mut store = open_store(path, passphrase)?;
+    let report = sync_with_peer(&mut store, stream, side)?;
+    if report.changed {
+        store.flush()?;
+    }
+    print_peer_report(&report);
+    Ok(())
+}
+
+fn print_peer_report(report: &PeerReport) {
+    println!(
+        "Sent {} and received {} sync messages; {}",
+        report.sent,
+        report.received,
+        if report.changed {
+            "the task file was updated"
+        } else {
+            "the task file was already up to date"
+        }
+    );
+}
+
+// Sync with the peer on each of `connections` in turn.  A failed session is
+// reported and the next peer waited for.  The passphrase of an encrypted file
+// is asked for once, at the first session.
+fn serve<T: Read + Write>(path: &Path, connections: impl Iterator<Item = std::io::Result<T>>) {
+    let mut passphrase = None;
+    for stream in connections {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|mut stream| sync_peer(path, &mut passphrase, &mut stream, Side::Listening));
+        if let Err(e) = result {
+            eprintln!("Sync failed: {:#}", e);
+        }
+    }
+}
+
+fn print_report(report: &Report) {
+    println!(
+        "Pulled {}, removed {}, pushed {} and deleted {} tasks",
+        report.pulled, report.removed, report.pushed, report.deleted
+    );
+    if report.conflicts > 0 {
+        println!(
+            "{} tasks changed on the server during the sync; sync again to merge them",
+            report.conflicts
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let path = save_name();
+    let mut passphrase = None;
+    match args.command {
+        Command::Caldav { url, username } => {
+            let mut collection = Collection::new(&url)?;
+            if let Some(username) = username {
+                let prompt = format!("Password for {}: ", username);
+                let password = rpassword::prompt_password(prompt)?;
+                collection = collection.with_basic_auth(&username, &password);
+            }
+            let mut store = open_store(&path, &mut passphrase)?;
+            let state_path = caldav::state_path(&path);
+            let mut state = caldav::SyncState::load(&state_path)?;
+            let report = caldav::sync(&mut store, &collection, &mut state)?;
+            // Save the tasks first: if saving the state then fails, the next
+            // sync only repeats work.
+            store.flush()?;
+            state.save(&state_path)?;
+            print_report(&report);
+        }
+        Command::Google { task_list, token } => {
+            let token = match token {
+                Some(token) => token,
+                None => rpassword::prompt_password("Access token: ")?,
+            };
+            let mut api = google::RestClient::new(&task_list, &token);
+            let mut store = open_store(&path, &mut passphrase)?;
+            let state_path = google::state_path(&path);
+            let mut state = google::SyncState::load(&state_path)?;
+            let report = google::sync(&mut store, &mut api, &mut state)?;
+            store.flush()?;
+            state.save(&state_path)?;
+            print_report(&report);
+        }
+        Command::Relay { url, token } => {
+            let token = match token {
+                Some(token) => token,
+                None => rpassword::prompt_password("Relay token: ")?,
+            };
+            let mut store = open_store(&path, &mut passphrase)?;
+            let report = relay::sync_with_relay(&mut store, &url, &token)?;
+            if report.changed {
+                store.flush()?;
+            }
+            print_peer_report(&report);
+        }
+        #[cfg(unix)]
+        Command::Serve {
+            unix: Some(socket), ..
+        } => {
+            let listener = UnixListener::bind(&socket)?;
+            println!("Listening on {}", socket.display());
+            serve(&path, listener.incoming());
+        }
+        Command::Serve { address, .. } => {
+            let listener = TcpListener::bind(&address)?;
+            println!("Listening on {}", listener.local_addr()?);
+            serve(&path, listener.incoming());
+        }
+        #[cfg(unix)]
+        Command::Connect {
+            unix: Some(socket), ..
+        } => {
+            let mut stream = UnixStream::connect(socket)?;
+            sync_peer(&path, &mut passphrase, &mut stream, Side::Connecting)?;
+        }
+        Command::Connect { address, .. } => {
+            let address = address.expect("clap requires an address");
+            let mut stream = TcpStream::connect(address)?;
+            sync_peer(&path, &mut passphrase, &mut stream, Side::Connecting)?;
+        }
+    }
+    Ok(())
+}
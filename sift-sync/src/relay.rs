@@ -0,0 +1,117 @@
+//! Sync with a `sift-relay` server, which keeps each user's task document
+//! and syncs it with all of their devices.
+//!
+//! A session is one WebSocket connection to `ws://host:port/<user>`,
+//! authorized with an `Authorization: Bearer <token>` header.  Each binary
+//! message carries one Automerge sync message, empty when the sender has
+//! nothing to send, and the client sends first; see
+//! [`sift_persist::sync_over`].
+
+use std::io::{Read, Write};
+
+use sift_persist::{sync_over, PeerReport, Side, Store, SyncChannel};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::header::AUTHORIZATION;
+use tungstenite::http::{HeaderValue, StatusCode};
+use tungstenite::{Message, WebSocket};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Cannot connect to `{0}`")]
+    Connect(String, #[source] Box<tungstenite::Error>),
+    #[error("The relay at `{0}` refused the token")]
+    Unauthorized(String),
+    #[error("The token is not a valid header value")]
+    InvalidToken,
+    #[error("WebSocket connection failed")]
+    WebSocket(#[source] Box<tungstenite::Error>),
+    #[error("Unexpected text message from the peer")]
+    UnexpectedText,
+    #[error("The peer closed the connection")]
+    Closed,
+}
+
+fn websocket_error(e: tungstenite::Error) -> Error {
+    Error::WebSocket(Box::new(e))
+}
+
+/// Sync messages carried by a WebSocket connection.
+pub struct WebSocketChannel<S: Read + Write> {
+    socket: WebSocket<S>,
+}
+
+impl<S: Read + Write> WebSocketChannel<S> {
+    #[must_use]
+    pub fn new(socket: WebSocket<S>) -> Self {
+        WebSocketChannel { socket }
+    }
+
+    /// Close the connection once a session is over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the closing handshake fails.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.socket.close(None).map_err(websocket_error)?;
+        loop {
+            match self.socket.read() {
+                Ok(_) => {}
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(());
+                }
+                Err(e) => return Err(websocket_error(e)),
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> SyncChannel for WebSocketChannel<S> {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        let message = Message::Binary(message.to_vec());
+        self.socket.send(message).map_err(websocket_error)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> anyhow::Result<Vec<u8>> {
+        loop {
+            match self.socket.read().map_err(websocket_error)? {
+                Message::Binary(message) => return Ok(message),
+                Message::Text(_) => return Err(Error::UnexpectedText.into()),
+                Message::Close(_) => return Err(Error::Closed.into()),
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+}
+
+/// Sync `store` with the document at `url` on a relay, such as
+/// `ws://localhost:7384/alice`.  The caller saves the store afterwards.
+///
+/// # Errors
+///
+/// Returns an error if the relay cannot be reached, refuses `token`, or the
+/// session fails.
+pub fn sync_with_relay<S: Store>(
+    store: &mut S,
+    url: &str,
+    token: &str,
+) -> anyhow::Result<PeerReport> {
+    let connect_error = |e| Error::Connect(url.to_string(), Box::new(e));
+    let mut request = url.into_client_request().map_err(connect_error)?;
+    let authorization =
+        HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| Error::InvalidToken)?;
+    request.headers_mut().insert(AUTHORIZATION, authorization);
+    let socket = match tungstenite::connect(request) {
+        Ok((socket, _)) => socket,
+        Err(tungstenite::Error::Http(response))
+            if response.status() == StatusCode::UNAUTHORIZED =>
+        {
+            return Err(Error::Unauthorized(url.to_string()).into());
+        }
+        Err(e) => return Err(connect_error(e).into()),
+    };
+    let mut channel = WebSocketChannel::new(socket);
+    let report = sync_over(store, &mut channel, Side::Connecting)?;
+    channel.close()?;
+    Ok(report)
+}
@@ -7,12 +7,27 @@ use serde::Deserialize;
 #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub(crate) enum Command {
     Edit,
+    EditNotes,
     Toggle,
+    ToggleCascade,
     Snooze,
     Next,
     Previous,
     MoveUp,
     MoveDown,
+    Indent,
+    Outdent,
+    Collapse,
+    Expand,
+    FilterTag,
+    NextSortMode,
+    CyclePriority,
+    CycleRecurrence,
+    NextList,
+    NewList,
+    RenameList,
+    DeleteList,
+    MoveToNextList,
     Add,
     Delete,
     Undo,
@@ -42,6 +57,6 @@ mod tests {
     #[test]
     fn test() {
         let bindings = default_bindings();
-        assert_eq!(bindings.len(), 16);
+        assert_eq!(bindings.len(), 33);
     }
 }
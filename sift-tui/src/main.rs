@@ -6,23 +6,145 @@ This is a toy todo list application I have written to explore Rust.
 #![deny(unused_crate_dependencies)]
 #![deny(unused_extern_crates)]
 
+use std::path::{Path, PathBuf};
+
 use anyhow::Result;
+use clap::{Parser, Subcommand};
 use cli_log::{debug, init_cli_log, warn};
 use sift_core::save_name;
+use sift_persist::{Format, Recovered, SalvageReport, SaveOptions};
 
 mod keys;
+mod passphrase;
 mod screen;
 mod terminal_input;
 mod toplevel;
+mod transfer;
 mod tui;
 mod ui_state;
 
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Before starting, replace the task file with its Nth most recent
+    /// backup.  The replaced file becomes the newest backup.
+    #[arg(long, value_name = "N")]
+    restore_backup: Option<usize>,
+
+    /// How many previous versions of the task file to keep, as `<file>.1`
+    /// (the most recent), `<file>.2` and so on.
+    #[arg(long, value_name = "N", default_value_t = SaveOptions::default().backups)]
+    backups: usize,
+
+    /// Encrypt the task file with a new passphrase, which is asked for
+    /// before starting.  An encrypted file asks for its passphrase whenever
+    /// it is opened.
+    #[arg(long)]
+    encrypt: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Merge two copies of a task file that were edited independently, such
+    /// as a "conflicted copy" left behind by a file sync tool.
+    Merge {
+        /// The first copy.
+        a: PathBuf,
+        /// The second copy.
+        b: PathBuf,
+        /// Where to write the merged result.  May be one of the copies.
+        out: PathBuf,
+    },
+    /// Recover what can be recovered from a task file that fails to load.
+    /// The damaged file is kept, renamed to `<file>.corrupt-<timestamp>`.
+    Salvage,
+    /// Write the tasks to standard output, to pipe into other programs.
+    Export {
+        /// The format to write: json, csv, md (a Markdown checklist), todotxt
+        /// or ics (iCalendar).
+        #[arg(long, short)]
+        format: Format,
+    },
+    /// Add the tasks in a JSON, CSV, Markdown checklist, todo.txt or
+    /// iCalendar file to the end of the list.  Tasks with the id of an existing
+    /// task replace it instead.
+    Import {
+        /// The file to read, or `-` for standard input.
+        file: PathBuf,
+        /// The format of the file: json, csv, md, todotxt or ics.  Defaults to
+        /// the format its extension names, with `.txt` taken as
+        /// todo.txt.
+        #[arg(long, short)]
+        format: Option<Format>,
+    },
+}
+
+fn print_salvage_report(report: &SalvageReport) {
+    println!(
+        "Moved the damaged file to {}",
+        report.corrupt_path.display()
+    );
+    match report.recovered {
+        Recovered::All => println!("Recovered the whole document"),
+        Recovered::Partial => println!("Recovered the document up to the first damaged change"),
+        Recovered::Nothing => println!("Could not recover the document"),
+    }
+    if let Some(backup) = &report.backup {
+        println!(
+            "Merged in backup {}; changes made after it was saved are lost",
+            backup.display()
+        );
+    }
+    println!("Salvaged {} changes", report.changes);
+    if report.missing_changes > 0 {
+        println!(
+            "Lost {} changes that others depend on; those others are not applied",
+            report.missing_changes
+        );
+    }
+}
+
+fn salvage(path: &Path) -> Result<()> {
+    let report = match sift_persist::salvage_file(path, None) {
+        Err(sift_persist::Error::PassphraseRequired) => {
+            sift_persist::salvage_file(path, Some(passphrase::prompt(path)?))?
+        }
+        result => result?,
+    };
+    print_salvage_report(&report);
+    Ok(())
+}
+
 fn main() -> Result<()> {
     init_cli_log!();
-    let save_name = save_name();
-    debug!("save name {}", save_name.display());
+    let args = Args::parse();
 
-    toplevel::run(&save_name)?;
+    match args.command {
+        None => {
+            let save_name = save_name();
+            debug!("save name {}", save_name.display());
+            if let Some(n) = args.restore_backup {
+                passphrase::retry(&save_name, |passphrase| {
+                    Ok(sift_persist::restore_backup(&save_name, n, passphrase)?)
+                })?;
+                debug!("restored backup {}", n);
+            }
+            toplevel::run(&save_name, args.encrypt, args.backups)?;
+        }
+        Some(Command::Merge { a, b, out }) => {
+            passphrase::retry(&a, |passphrase| {
+                Ok(sift_persist::merge_files(&a, &b, &out, passphrase)?)
+            })?;
+        }
+        Some(Command::Salvage) => salvage(&save_name())?,
+        Some(Command::Export { format }) => transfer::export(&save_name(), format)?,
+        Some(Command::Import { file, format }) => {
+            transfer::import(&save_name(), &file, format)?;
+        }
+    }
 
     Ok(())
 }
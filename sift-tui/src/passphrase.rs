@@ -0,0 +1,65 @@
+//! Prompting for the passphrase of an encrypted task file.
+//!
+//! Prompts are read from the terminal before the user interface starts, with
+//! echo turned off.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use sift_persist::{Access, MemoryStore, Passphrase, Store as _};
+
+// How many times to ask for the passphrase of an encrypted file before giving
+// up.
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+/// Ask for the passphrase of the encrypted file at `path`.
+pub fn prompt(path: &Path) -> Result<Passphrase> {
+    let passphrase = rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))?;
+    Ok(Passphrase::from(passphrase))
+}
+
+/// Ask for a new passphrase to encrypt a file with, twice, to guard against
+/// typos.
+pub fn prompt_new() -> Result<Passphrase> {
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("the passphrase must not be empty");
+    }
+    if rpassword::prompt_password("Repeat the passphrase: ")? != passphrase {
+        bail!("the passphrases do not match");
+    }
+    Ok(Passphrase::from(passphrase))
+}
+
+/// Call `f` with no passphrase, and again with one asked for each time it
+/// fails for want of the right passphrase for the encrypted file at `path`.
+pub fn retry<T>(path: &Path, mut f: impl FnMut(Option<Passphrase>) -> Result<T>) -> Result<T> {
+    let mut passphrase = None;
+    let mut attempts = 0;
+    loop {
+        let error = match f(passphrase.clone()) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        match error.downcast_ref::<sift_persist::Error>() {
+            Some(
+                sift_persist::Error::PassphraseRequired | sift_persist::Error::WrongPassphrase,
+            ) if attempts < PASSPHRASE_ATTEMPTS => {
+                if passphrase.is_some() {
+                    eprintln!("{}", error);
+                }
+                attempts += 1;
+                passphrase = Some(prompt(path)?);
+            }
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Open the task file at `path`, asking for its passphrase if it is
+/// encrypted.
+pub fn open_store(path: &Path, access: Access) -> Result<MemoryStore> {
+    retry(path, |passphrase| {
+        MemoryStore::open_with_passphrase(path, access, passphrase)
+    })
+}
@@ -7,8 +7,16 @@ use tui_prompts::{State as _, TextPrompt};
 
 use crate::screen;
 
+/// What the prompt edits.
+pub(crate) enum Target {
+    /// The title of a task, with its tags as `#tag` words.
+    Title(TaskId),
+    /// The name of the list being shown.
+    ListName,
+}
+
 pub(crate) struct State {
-    id: TaskId,
+    target: Target,
     // TODO: in upstream make the 'static workaround used here more
     // discoverable.  See
     // https://github.com/rhysd/tui-textarea/issues/46
@@ -16,8 +24,11 @@ pub(crate) struct State {
 }
 
 impl State {
-    pub(crate) fn new(id: TaskId, text: RefCell<tui_prompts::prelude::TextState<'static>>) -> Self {
-        Self { id, text }
+    pub(crate) fn new(
+        target: Target,
+        text: RefCell<tui_prompts::prelude::TextState<'static>>,
+    ) -> Self {
+        Self { target, text }
     }
 
     fn do_handle_key_event(
@@ -36,12 +47,14 @@ impl State {
                 Some(Box::new(screen::main::State::new()))
             }
             tui_prompts::Status::Done => {
-                let title = text_state.value();
-                let id = &self.id;
-                context
-                    .store
-                    .with_transaction(|txn| set_title(txn, id, title))
-                    .expect("TODO: handle error");
+                let value = text_state.value();
+                match &self.target {
+                    Target::Title(id) => context
+                        .store
+                        .with_transaction(|txn| set_title(txn, id, value))
+                        .expect("TODO: handle error"),
+                    Target::ListName => context.rename_list(value),
+                }
                 Some(Box::new(screen::main::State::new()))
             }
         }
@@ -50,7 +63,7 @@ impl State {
 
 fn set_title(txn: &mut dyn Transaction, id: &TaskId, title: &str) -> Result<(), anyhow::Error> {
     let mut task = txn.get_task(id).unwrap();
-    task.set_title(title.to_string());
+    task.set_title_with_tags(title);
     txn.put_task(&task)
 }
 
@@ -68,7 +81,11 @@ impl screen::Screen for State {
     }
 
     fn render(&self, _conext: &mut sift_state::State, frame: &mut ratatui::Frame) {
-        let prompt = TextPrompt::new(Cow::Borrowed("edit"));
+        let label = match self.target {
+            Target::Title(_) => "edit",
+            Target::ListName => "list name",
+        };
+        let prompt = TextPrompt::new(Cow::Borrowed(label));
         frame.render_stateful_widget(prompt, frame.area(), &mut self.text.borrow_mut());
         let (x, y) = self.text.borrow().cursor();
         frame.set_cursor_position((x, y));
@@ -1,15 +1,54 @@
 use std::borrow::Cow;
 use std::cell::RefCell;
 
-use ratatui::widgets::{Block, Borders, List, ListItem, ListState, StatefulWidget};
-use sift_persist::{Store, Task};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, StatefulWidget, Tabs};
+use sift_persist::{Frequency, Priority, Store, Task};
+use sift_state::{DisplayedTask, SortMode};
 
 use crate::screen::Screen;
 use crate::{keys, screen};
 
-fn render_task(s: &Task) -> ListItem<'_> {
+fn render_task(displayed: &DisplayedTask) -> ListItem<'_> {
+    let s = &displayed.task;
     let check = if s.completed().is_some() { 'x' } else { ' ' };
-    ListItem::new(format!("[{}] {}", check, s.title()))
+    let fold = match (displayed.has_subtasks, displayed.collapsed) {
+        (false, _) => ' ',
+        (true, false) => '▾',
+        (true, true) => '▸',
+    };
+    let indent = "  ".repeat(displayed.depth);
+    let priority = match s.priority() {
+        None => "",
+        Some(Priority::Low) => "! ",
+        Some(Priority::Medium) => "!! ",
+        Some(Priority::High) => "!!! ",
+    };
+    let mut spans = vec![Span::raw(format!(
+        "{}{} [{}] {}{}",
+        indent,
+        fold,
+        check,
+        priority,
+        s.title()
+    ))];
+    if let Some(recurrence) = s.recurrence() {
+        let every = match recurrence.frequency {
+            Frequency::Daily => "daily",
+            Frequency::Weekly => "weekly",
+            Frequency::Monthly => "monthly",
+            Frequency::Yearly => "yearly",
+        };
+        spans.push(Span::raw(format!(" ↻ {}", every)));
+    }
+    // Tags follow the title, set apart by colour.
+    let tag_style = Style::new().fg(Color::Cyan);
+    for tag in s.tags() {
+        spans.push(Span::styled(format!(" #{}", tag), tag_style));
+    }
+    ListItem::new(Line::from(spans))
 }
 
 #[derive(Default)]
@@ -26,32 +65,57 @@ impl State {
 fn add(common_state: &mut sift_state::State) -> Option<Box<dyn Screen>> {
     // FIXME: make generating new tasks less cumbersome
     // FIXME: handle error
-    let task = Task::new(Task::new_id(), String::new(), None, None, None);
+    let mut task = Task::new(Task::new_id(), String::new(), None, None, None);
+    task.set_list(common_state.list);
+    // The new task is a sibling of the selected one, just after it.
+    let parent = common_state
+        .selected
+        .and_then(|id| common_state.get_task(&id))
+        .and_then(|selected| selected.parent());
     common_state
         .store
-        .with_transaction(|txn| txn.insert_task(common_state.selected.as_ref(), &task))
+        .with_transaction(|txn| {
+            txn.insert_task(parent.as_ref(), common_state.selected.as_ref(), &task)
+        })
         .expect("FIXME: handle error");
     common_state.selected = Some(task.id());
     edit(common_state)
 }
 
+fn prompt(target: screen::edit::Target, value: String) -> Option<Box<dyn Screen>> {
+    let text = tui_prompts::TextState::new()
+        .with_value(Cow::Owned(value))
+        .with_focus(tui_prompts::FocusState::Focused);
+    let edit = screen::edit::State::new(target, RefCell::new(text));
+    Some(Box::new(edit))
+}
+
 fn edit(common_state: &mut sift_state::State) -> Option<Box<dyn Screen>> {
-    if let Some((id, text)) = {
-        if let Some(id) = common_state.selected {
-            let title = common_state.store.get_task(&id).unwrap().title().into();
-            let text = tui_prompts::TextState::new()
-                .with_value(Cow::Owned(title))
-                .with_focus(tui_prompts::FocusState::Focused);
-            let text = RefCell::new(text);
-            Some((id, text))
-        } else {
-            None
-        }
-    } {
-        let edit = screen::edit::State::new(id, text);
-        return Some(Box::new(edit));
-    }
-    None
+    let id = common_state.selected?;
+    let title = common_state.store.get_task(&id).unwrap().title_with_tags();
+    prompt(screen::edit::Target::Title(id), title)
+}
+
+fn edit_notes(common_state: &mut sift_state::State) -> Option<Box<dyn Screen>> {
+    let task = common_state.get_task(&common_state.selected?)?;
+    let notes = screen::notes::State::new(task.id(), task.title(), task.notes());
+    Some(Box::new(notes))
+}
+
+fn snooze(common_state: &mut sift_state::State) -> Option<Box<dyn Screen>> {
+    let task = common_state.get_task(&common_state.selected?)?;
+    let snooze = screen::snooze::State::new(task.id(), task.title());
+    Some(Box::new(snooze))
+}
+
+fn new_list(common_state: &mut sift_state::State) -> Option<Box<dyn Screen>> {
+    common_state.create_list("");
+    prompt(screen::edit::Target::ListName, String::new())
+}
+
+fn rename_list(common_state: &mut sift_state::State) -> Option<Box<dyn Screen>> {
+    let name = common_state.current_list().name;
+    prompt(screen::edit::Target::ListName, name)
 }
 
 fn do_handle_key_event(
@@ -68,11 +132,17 @@ fn do_handle_key_event(
             keys::Command::Toggle => {
                 common_state.toggle();
             }
+            keys::Command::ToggleCascade => {
+                common_state.cascade_completion = !common_state.cascade_completion;
+            }
             keys::Command::Edit => {
                 return edit(common_state);
             }
+            keys::Command::EditNotes => {
+                return edit_notes(common_state);
+            }
             keys::Command::Snooze => {
-                common_state.snooze();
+                return snooze(common_state);
             }
             keys::Command::Next => {
                 common_state.next();
@@ -86,6 +156,45 @@ fn do_handle_key_event(
             keys::Command::MoveDown => {
                 common_state.move_down();
             }
+            keys::Command::Indent => {
+                common_state.indent();
+            }
+            keys::Command::Outdent => {
+                common_state.outdent();
+            }
+            keys::Command::Collapse => {
+                common_state.collapse();
+            }
+            keys::Command::Expand => {
+                common_state.expand();
+            }
+            keys::Command::FilterTag => {
+                common_state.next_tag_filter();
+            }
+            keys::Command::NextSortMode => {
+                common_state.sort_mode = common_state.sort_mode.next();
+            }
+            keys::Command::CyclePriority => {
+                common_state.cycle_priority();
+            }
+            keys::Command::CycleRecurrence => {
+                common_state.cycle_recurrence();
+            }
+            keys::Command::NextList => {
+                common_state.next_list();
+            }
+            keys::Command::NewList => {
+                return new_list(common_state);
+            }
+            keys::Command::RenameList => {
+                return rename_list(common_state);
+            }
+            keys::Command::DeleteList => {
+                common_state.delete_list();
+            }
+            keys::Command::MoveToNextList => {
+                common_state.move_to_next_list();
+            }
             keys::Command::Add => {
                 return add(common_state);
             }
@@ -119,12 +228,38 @@ impl screen::Screen for State {
         let state: &mut ListState = &mut self.list.borrow_mut();
         state.select(common_state.index_of_id(common_state.selected));
 
-        let tasks = common_state.list_tasks_for_display();
+        // A tab for each list, with the one shown selected.
+        let [tabs_area, list_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+        let lists = common_state.lists();
+        let current = lists
+            .iter()
+            .position(|list| list.id == common_state.list)
+            .unwrap_or_default();
+        let names: Vec<String> = lists.into_iter().map(|list| list.name).collect();
+        frame.render_widget(Tabs::new(names).select(current), tabs_area);
+
+        let tasks = common_state.list_displayed_tasks();
         let items: Vec<_> = tasks.iter().map(render_task).collect();
+        let mut name = common_state.current_list().name;
+        if let Some(tag) = common_state.tag_filter() {
+            name = format!("{} #{}", name, tag);
+        }
+        if common_state.sort_mode != SortMode::Manual {
+            name = format!("{} (by {})", name, common_state.sort_mode);
+        }
+        if common_state.cascade_completion {
+            name = format!("{} (completing subtasks)", name);
+        }
+        let title = if common_state.store.is_read_only() {
+            format!("{} (read-only)", name)
+        } else {
+            name
+        };
         let items = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("Tasks"))
+            .block(Block::default().borders(Borders::ALL).title(title))
             .highlight_symbol("> ");
 
-        items.render(frame.area(), frame.buffer_mut(), state);
+        items.render(list_area, frame.buffer_mut(), state);
     }
 }
@@ -1,6 +1,8 @@
 pub mod edit;
 pub mod main;
+pub mod notes;
 pub mod quit;
+pub mod snooze;
 
 pub trait Screen {
     // TODO: return an Option<Box<dyn Screen>> instead.
@@ -0,0 +1,60 @@
+use ratatui::crossterm;
+use ratatui::widgets::{Block, Borders};
+use sift_persist::TaskId;
+use tui_textarea::TextArea;
+
+use crate::screen;
+
+/// Edits the notes of a task over several lines.  `Esc` keeps the notes and
+/// returns to the task list.
+pub(crate) struct State {
+    id: TaskId,
+    textarea: TextArea<'static>,
+}
+
+impl State {
+    pub(crate) fn new(id: TaskId, title: &str, notes: &str) -> Self {
+        let mut textarea: TextArea = notes.split('\n').map(str::to_string).collect();
+        textarea.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Notes: {} (Esc to close)", title)),
+        );
+        textarea.move_cursor(tui_textarea::CursorMove::Bottom);
+        textarea.move_cursor(tui_textarea::CursorMove::End);
+        Self { id, textarea }
+    }
+
+    fn do_handle_key_event(
+        &mut self,
+        context: &mut sift_state::State,
+        key_combination: crokey::KeyCombination,
+    ) -> Option<Box<dyn screen::Screen>> {
+        let key_event: crossterm::event::KeyEvent = key_combination.into();
+        if key_event.code == crossterm::event::KeyCode::Esc {
+            let notes = self.textarea.lines().join("\n");
+            context.set_notes(&self.id, &notes);
+            return Some(Box::new(screen::main::State::new()));
+        }
+        self.textarea.input(key_event);
+        None
+    }
+}
+
+impl screen::Screen for State {
+    fn handle_key_event(
+        mut self: Box<Self>,
+        context: &mut sift_state::State,
+        key_combination: crokey::KeyCombination,
+    ) -> Box<dyn screen::Screen> {
+        if let Some(screen) = self.do_handle_key_event(context, key_combination) {
+            screen
+        } else {
+            self
+        }
+    }
+
+    fn render(&self, _context: &mut sift_state::State, frame: &mut ratatui::Frame) {
+        frame.render_widget(&self.textarea, frame.area());
+    }
+}
@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use ratatui::crossterm;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, List, ListState, Paragraph, StatefulWidget};
+use sift_persist::TaskId;
+use sift_state::SNOOZE_PRESETS;
+use tui_prompts::{State as _, TextPrompt};
+
+use crate::screen;
+
+// The choices after the presets: not snoozing, then the date field.
+const UNSNOOZE: usize = SNOOZE_PRESETS.len();
+const DATE_FIELD: usize = UNSNOOZE + 1;
+
+/// Picks when a task is snoozed until, from presets or a date typed in words
+/// such as "in 3 days" or "jul 5".  Up and down choose, `Enter` snoozes and
+/// `Esc` returns to the task list unchanged.  Typing goes to the date field.
+pub(crate) struct State {
+    id: TaskId,
+    title: String,
+    choice: usize,
+    text: RefCell<tui_prompts::TextState<'static>>,
+    error: Option<String>,
+}
+
+impl State {
+    pub(crate) fn new(id: TaskId, title: &str) -> Self {
+        Self {
+            id,
+            title: title.to_string(),
+            choice: 0,
+            text: RefCell::new(tui_prompts::TextState::new()),
+            error: None,
+        }
+    }
+
+    fn choose(&mut self, choice: usize) {
+        self.choice = choice;
+        let mut text_state = self.text.borrow_mut();
+        if choice == DATE_FIELD {
+            text_state.focus();
+        } else {
+            text_state.blur();
+        }
+    }
+
+    fn do_handle_key_event(
+        &mut self,
+        context: &mut sift_state::State,
+        key_combination: crokey::KeyCombination,
+    ) -> Option<Box<dyn screen::Screen>> {
+        let key_event: crossterm::event::KeyEvent = key_combination.into();
+        match key_event.code {
+            crossterm::event::KeyCode::Esc => return Some(Box::new(screen::main::State::new())),
+            crossterm::event::KeyCode::Up => self.choose(self.choice.saturating_sub(1)),
+            crossterm::event::KeyCode::Down => self.choose((self.choice + 1).min(DATE_FIELD)),
+            crossterm::event::KeyCode::Enter => {
+                let today = sift_state::today();
+                let date = match self.choice {
+                    UNSNOOZE => None,
+                    DATE_FIELD => {
+                        let text = self.text.borrow().value().to_string();
+                        match sift_state::parse_date(&text, today) {
+                            Some(date) => Some(date),
+                            None => {
+                                self.error = Some(format!("Not a date: {:?}", text));
+                                return None;
+                            }
+                        }
+                    }
+                    preset => sift_state::parse_date(SNOOZE_PRESETS[preset], today),
+                };
+                context.snooze_until(&self.id, date);
+                return Some(Box::new(screen::main::State::new()));
+            }
+            _ => {
+                self.choose(DATE_FIELD);
+                self.error = None;
+                self.text.borrow_mut().handle_key_event(key_event);
+            }
+        }
+        None
+    }
+}
+
+impl screen::Screen for State {
+    fn handle_key_event(
+        mut self: Box<Self>,
+        context: &mut sift_state::State,
+        key_combination: crokey::KeyCombination,
+    ) -> Box<dyn screen::Screen> {
+        if let Some(screen) = self.do_handle_key_event(context, key_combination) {
+            screen
+        } else {
+            self
+        }
+    }
+
+    fn render(&self, _context: &mut sift_state::State, frame: &mut ratatui::Frame) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Snooze: {} (Esc to cancel)", self.title));
+        let inner = block.inner(frame.area());
+        frame.render_widget(block, frame.area());
+        let [list_area, field_area, error_area] = Layout::vertical([
+            Constraint::Length(u16::try_from(DATE_FIELD).unwrap_or(u16::MAX)),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .areas(inner);
+
+        // Show the date each preset stands for today.
+        let today = sift_state::today();
+        let mut items: Vec<_> = SNOOZE_PRESETS
+            .iter()
+            .map(|preset| match sift_state::parse_date(preset, today) {
+                Some(date) => format!("{} ({})", preset, date.format("%a %Y-%m-%d")),
+                None => (*preset).to_string(),
+            })
+            .collect();
+        items.push("not snoozed".to_string());
+        let mut state =
+            ListState::default().with_selected((self.choice < DATE_FIELD).then_some(self.choice));
+        List::new(items)
+            .highlight_symbol("> ")
+            .render(list_area, frame.buffer_mut(), &mut state);
+
+        let prompt = TextPrompt::new(Cow::Borrowed("date"));
+        frame.render_stateful_widget(prompt, field_area, &mut self.text.borrow_mut());
+        if self.choice == DATE_FIELD {
+            let (x, y) = self.text.borrow().cursor();
+            frame.set_cursor_position((x, y));
+        }
+        if let Some(error) = &self.error {
+            let error = Paragraph::new(error.as_str()).style(Style::new().fg(Color::Red));
+            frame.render_widget(error, error_area);
+        }
+    }
+}
@@ -1,11 +1,13 @@
 //! Terminal events handler
 
+use std::path::Path;
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
 
 use crossterm::event;
 use ratatui::crossterm;
+use sift_persist::FileWatch;
 
 /// Terminal events.
 #[derive(Clone, Copy, Debug)]
@@ -18,6 +20,8 @@ pub enum Event {
     Mouse(event::MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// The watched task file was changed by another process.
+    FileChanged,
 }
 
 /// Terminal event source.
@@ -30,16 +34,18 @@ pub struct Reader {
 }
 
 impl Reader {
-    /// Constructs a new instance of [`EventHandler`].
+    /// Constructs a new instance of [`EventHandler`].  The file at
+    /// `watched` is checked for changes on every tick.
     ///
     /// # Panics
     ///
     /// Will panic on various I/O errors.
     #[must_use]
-    pub fn new(tick_rate: u64) -> Self {
+    pub fn new(tick_rate: u64, watched: &Path) -> Self {
         let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::channel();
-        let handler = { thread::spawn(move || read_loop(tick_rate, &sender)) };
+        let watch = FileWatch::new(watched);
+        let handler = { thread::spawn(move || read_loop(tick_rate, watch, &sender)) };
         Self { receiver, handler }
     }
 
@@ -52,7 +58,7 @@ impl Reader {
     }
 }
 
-fn read_loop(tick_rate: Duration, sender: &mpsc::Sender<Event>) {
+fn read_loop(tick_rate: Duration, mut watch: FileWatch, sender: &mpsc::Sender<Event>) {
     let mut last_tick = Instant::now();
     loop {
         let timeout = tick_rate
@@ -79,6 +85,11 @@ fn read_loop(tick_rate: Duration, sender: &mpsc::Sender<Event>) {
 
         if last_tick.elapsed() >= tick_rate {
             sender.send(Event::Tick).expect("failed to send tick event");
+            if watch.changed() {
+                sender
+                    .send(Event::FileChanged)
+                    .expect("failed to send file change event");
+            }
             last_tick = Instant::now();
         }
     }
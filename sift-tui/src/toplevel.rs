@@ -1,11 +1,13 @@
+use std::io::{self, Write};
 use std::path::Path;
 
 use anyhow::Result;
 use cli_log::{debug, warn};
 use ratatui::backend::CrosstermBackend;
 use ratatui::{crossterm, Terminal};
+use sift_persist::SaveOptions;
 
-use crate::{terminal_input, tui, ui_state};
+use crate::{passphrase, terminal_input, tui, ui_state};
 
 fn handle_key_event(state: &mut ui_state::State, key_event: crossterm::event::KeyEvent) {
     // TODO: do this combining earlier, properly.
@@ -17,29 +19,75 @@ fn handle_key_event(state: &mut ui_state::State, key_event: crossterm::event::Ke
     }
 }
 
+// Ask on the terminal whether to replace `path`, which failed to load, with
+// the tasks edited since.
+fn confirm_overwrite(path: &Path) -> Result<bool> {
+    eprint!(
+        "{} could not be loaded. Overwrite it with the tasks from this session? [y/N] ",
+        path.display()
+    );
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn reload(state: &mut ui_state::State) {
+    match state.common_state.reload() {
+        Ok(true) => debug!("merged changes from disk"),
+        Ok(false) => {}
+        Err(error) => warn!("reloading todos failed: {}", error),
+    }
+}
+
 /// # Errors
 ///
 /// TODO: write me
-pub fn run(save_name: &Path) -> Result<()> {
-    // Create an application.
+pub fn run(save_name: &Path, encrypt: bool, backups: usize) -> Result<()> {
+    // Create an application.  Remember whether an existing file failed to
+    // load, so that it is not silently replaced by the default set of todos.
+    let mut load_failed = false;
     let mut state = match ui_state::State::load(save_name) {
         Ok(app) => {
             debug!("loaded state from disk");
             app
         }
+        // Starting afresh would overwrite the encrypted file on exit.
+        Err(error)
+            if matches!(
+                error.downcast_ref::<sift_persist::Error>(),
+                Some(
+                    sift_persist::Error::PassphraseRequired | sift_persist::Error::WrongPassphrase
+                )
+            ) =>
+        {
+            return Err(error);
+        }
         Err(error) => {
             warn!(
                 "loading todos failed: {}; using a default set of todos",
                 error
             );
+            load_failed = save_name.exists();
             ui_state::State::new()
         }
     };
 
+    state.common_state.store.set_save_options(SaveOptions {
+        backups,
+        ..SaveOptions::default()
+    });
+    if encrypt {
+        state
+            .common_state
+            .store
+            .set_passphrase(Some(passphrase::prompt_new()?));
+    }
+
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(std::io::stderr());
     let terminal = Terminal::new(backend)?;
-    let events = terminal_input::Reader::new(250);
+    let events = terminal_input::Reader::new(250, save_name);
     let mut tui = tui::Tui::new(terminal, events);
     tui.enter()?;
 
@@ -59,6 +107,7 @@ pub fn run(save_name: &Path) -> Result<()> {
             terminal_input::Event::Resize(width, height) => {
                 debug!("Resize({}, {})", width, height);
             }
+            terminal_input::Event::FileChanged => reload(&mut state),
         }
 
         match &state.current_screen {
@@ -78,6 +127,17 @@ pub fn run(save_name: &Path) -> Result<()> {
     // Exit the user interface.
     tui.exit()?;
 
-    state.save(save_name)?;
+    // Pick up any change made since the last tick rather than overwrite it.
+    reload(&mut state);
+    if state.is_read_only() {
+        debug!("opened read-only; not saving");
+    } else if load_failed && !confirm_overwrite(save_name)? {
+        eprintln!(
+            "Left {} as it was; `sift-tui salvage` may recover its tasks",
+            save_name.display()
+        );
+    } else {
+        state.save(save_name)?;
+    }
     Ok(())
 }
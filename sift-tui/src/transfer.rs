@@ -0,0 +1,52 @@
+//! Exporting tasks to, and importing them from, formats other programs can
+//! read.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use sift_persist::{Access, Format, MemoryStore, Store as _, TaskList};
+
+use crate::passphrase;
+
+/// Write the tasks in the task file at `path` to standard output in `format`.
+pub fn export(path: &Path, format: Format) -> Result<()> {
+    // Read-only, so that tasks can be exported while they are being edited.
+    let store = passphrase::open_store(path, Access::ReadOnly)?;
+    let tasks = TaskList {
+        tasks: store.list_tasks()?,
+    };
+    sift_persist::export_tasks(&tasks, format, io::stdout().lock())?;
+    Ok(())
+}
+
+/// Add the tasks in `file`, or standard input if it is `-`, to the task file
+/// at `path`.  Unless `format` is given, it is taken from the extension of
+/// `file`.
+pub fn import(path: &Path, file: &Path, format: Option<Format>) -> Result<()> {
+    let format = format
+        .or_else(|| Format::from_path(file))
+        .ok_or_else(|| anyhow!("cannot tell the format of {}", file.display()))?;
+    let tasks = if file == Path::new("-") {
+        sift_persist::import_tasks(io::stdin().lock(), format)?
+    } else {
+        let reader =
+            File::open(file).map_err(|e| sift_persist::Error::OpenFile(e, file.to_owned()))?;
+        sift_persist::import_tasks(BufReader::new(reader), format)?
+    };
+
+    let mut store = if path.exists() {
+        passphrase::open_store(path, Access::ReadWrite)?
+    } else {
+        MemoryStore::default()
+    };
+    sift_persist::add_tasks(&mut store, &tasks)?;
+    store.save(path)?;
+    eprintln!(
+        "Imported {} tasks into {}",
+        tasks.tasks.len(),
+        path.display()
+    );
+    Ok(())
+}
@@ -8,8 +8,10 @@ central data structure for the application.
 use std::path::Path;
 
 use anyhow::Result;
-use sift_persist::MemoryStore;
+use cli_log::warn;
+use sift_persist::{Access, Store as _};
 
+use crate::passphrase;
 use crate::screen::{self, Screen};
 
 pub(crate) struct State {
@@ -35,12 +37,29 @@ impl State {
         State::default()
     }
 
-    pub fn save(&self, path: &Path) -> Result<()> {
-        self.common_state.store.save(path)
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.common_state.save(path)
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.common_state.store.is_read_only()
+    }
+
+    /// Load the task file at `path`.  If another process has it locked, open
+    /// it read-only instead.  If it is encrypted, ask for its passphrase.
     pub fn load(path: &Path) -> Result<State> {
-        let store = MemoryStore::load(path)?;
+        let store = match passphrase::open_store(path, Access::ReadWrite) {
+            Err(error)
+                if matches!(
+                    error.downcast_ref::<sift_persist::Error>(),
+                    Some(sift_persist::Error::Locked { .. })
+                ) =>
+            {
+                warn!("{}; opening read-only", error);
+                passphrase::open_store(path, Access::ReadOnly)?
+            }
+            result => result?,
+        };
         let common_state = sift_state::State::new(store);
         let state = State {
             common_state,
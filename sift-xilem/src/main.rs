@@ -4,15 +4,20 @@
 // On Windows platform, don't show a console when opening the app.
 #![windows_subsystem = "windows"]
 
+use std::time::Duration;
+
 use sift_core::save_name;
-use sift_persist::{MemoryStore, Store, Task, TaskId};
+use sift_persist::{Store, Task, TaskId, Watcher};
 use sift_state::State;
-use xilem::view::{button, checkbox, flex, label, portal, textbox, Axis, CrossAxisAlignment};
+use xilem::core::{fork, MessageProxy};
+use xilem::view::{button, checkbox, flex, label, portal, task, textbox, Axis, CrossAxisAlignment};
 use xilem::{EventLoop, WidgetView, Xilem};
 
 enum Screen {
     Main,
     Edit { id: TaskId },
+    // The notes are as edited so far, and kept when going back.
+    Details { id: TaskId, notes: String },
 }
 
 struct App {
@@ -24,62 +29,124 @@ impl App {
     fn add_task(&mut self) {
         let title = String::default();
         let id = Task::new_id();
-        let task = Task::new(id, title, None, None, None);
+        let mut task = Task::new(id, title, None, None, None);
+        task.set_list(self.state.list);
         let previous = None;
         self.state
             .store
-            .with_transaction(|txn| txn.insert_task(previous, &task))
+            .with_transaction(|txn| txn.insert_task(None, previous, &task))
             .expect("FIXME: handle error");
         self.screen = Screen::Edit { id };
     }
 
-    fn save(&self) {
-        self.state
-            .store
-            .save(&save_name())
-            .expect("TODO: handle this error");
+    fn save(&mut self) {
+        self.state.flush().expect("TODO: handle this error");
+    }
+
+    // Merge in the save file as changed on disk, going back to the main
+    // screen if the task shown was deleted there.
+    fn reload(&mut self) {
+        if let Err(error) = self.state.reload() {
+            eprintln!("reloading todos failed: {}", error);
+        }
+        if let Screen::Edit { id } | Screen::Details { id, .. } = &self.screen {
+            if self.state.get_task(id).is_none() {
+                self.screen = Screen::Main;
+            }
+        }
     }
 }
 
+// Send a message each time the save file changes on disk, for as long as the
+// task runs.
+async fn watch_save_file(proxy: MessageProxy<()>) {
+    let _watcher = Watcher::new(&save_name(), Duration::from_secs(1), move || {
+        let _ = proxy.message(());
+    });
+    std::future::pending::<()>().await;
+}
+
 fn app_logic(app: &mut App) -> impl WidgetView<App> {
-    match &app.screen {
+    let screen = match &app.screen {
         Screen::Main => main_app_logic(app).boxed(),
         Screen::Edit { id } => edit_app_logic(*id, app).boxed(),
-    }
+        Screen::Details { id, notes } => details_app_logic(*id, notes.clone(), app).boxed(),
+    };
+    fork(
+        screen,
+        task(watch_save_file, |app: &mut App, ()| app.reload()),
+    )
 }
 
 fn main_app_logic(app: &mut App) -> impl WidgetView<App> {
-    let add_task = button("Add task", |app: &mut App| {
-        app.add_task();
+    // A file opened read-only gets no buttons that would change it.
+    let editable = !app.state.store.is_read_only();
+    let add_task = editable.then(|| {
+        button("Add task", |app: &mut App| {
+            app.add_task();
+        })
     });
 
+    // A button for each list, the one shown marked.
+    let lists = app
+        .state
+        .lists()
+        .into_iter()
+        .map(|list| {
+            let text = if list.id == app.state.list {
+                format!("▸ {}", list.name)
+            } else {
+                list.name
+            };
+            button(text, move |app: &mut App| app.state.select_list(list.id))
+        })
+        .collect::<Vec<_>>();
+
     let tasks = app
         .state
         .list_tasks_for_display()
         .iter()
         .map(|task| {
             let id = task.id();
-            let checkbox = checkbox(
-                task.title(),
-                task.is_completed(),
-                move |app: &mut App, checked| {
-                    if let Some(task) = app.state.get_task(&id) {
-                        if checked != task.is_completed() {
-                            app.state.toggle_id(&id);
-                            app.save();
+            let checkbox = if editable {
+                checkbox(
+                    task.title_with_tags(),
+                    task.is_completed(),
+                    move |app: &mut App, checked| {
+                        if let Some(task) = app.state.get_task(&id) {
+                            if checked != task.is_completed() {
+                                app.state.toggle_id(&id);
+                                app.save();
+                            }
                         }
-                    }
-                },
-            );
-            let delete_button = button("Delete", move |app: &mut App| {
-                app.state.delete_task(&id);
-                app.save();
+                    },
+                )
+                .boxed()
+            } else {
+                let mark = if task.is_completed() { "☑" } else { "☐" };
+                label(format!("{} {}", mark, task.title_with_tags())).boxed()
+            };
+            let details_button = button("Details", move |app: &mut App| {
+                if let Some(task) = app.state.get_task(&id) {
+                    let notes = task.notes().to_string();
+                    app.screen = Screen::Details { id, notes };
+                }
             });
-            flex((checkbox, delete_button)).direction(Axis::Horizontal)
+            let delete_button = editable.then(|| {
+                button("Delete", move |app: &mut App| {
+                    app.state.delete_task(&id);
+                    app.save();
+                })
+            });
+            flex((checkbox, details_button, delete_button)).direction(Axis::Horizontal)
         })
         .collect::<Vec<_>>();
 
+    let read_only = app.state.store.is_read_only().then(|| label("Read-only"));
+
     flex((
+        read_only,
+        flex(lists).direction(Axis::Horizontal),
         add_task,
         portal(
             flex(tasks)
@@ -107,17 +174,47 @@ fn edit_app_logic(id: TaskId, app: &mut App) -> impl WidgetView<App> {
             })
             .expect("FIXME: handle error");
     })
-    .on_enter(|app: &mut App, _| {
+    .on_enter(move |app: &mut App, _| {
+        app.state.take_tags_from_title(&id);
         app.save();
         app.screen = Screen::Main;
     });
     flex((label, input_box)).direction(Axis::Vertical)
 }
 
+fn details_app_logic(id: TaskId, notes: String, app: &mut App) -> impl WidgetView<App> {
+    let task = app.state.get_task(&id).expect("FIXME: task must exist");
+    let title = label(task.title());
+    let notes_label = label("Notes");
+    let notes = if app.state.store.is_read_only() {
+        label(notes).boxed()
+    } else {
+        textbox(notes, move |app: &mut App, new_value| {
+            app.screen = Screen::Details {
+                id,
+                notes: new_value,
+            };
+        })
+        .boxed()
+    };
+    let back = button("Back", move |app: &mut App| {
+        if let Screen::Details { notes, .. } = &app.screen {
+            let notes = notes.clone();
+            if app.state.set_notes(&id, &notes) {
+                app.save();
+            }
+        }
+        app.screen = Screen::Main;
+    });
+    flex((title, notes_label, notes, back))
+        .direction(Axis::Vertical)
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+}
+
 fn main() {
     let app = App {
         screen: Screen::Main,
-        state: State::new(MemoryStore::load(&save_name()).unwrap()),
+        state: State::new(sift_state::open_store(&save_name()).unwrap()),
     };
 
     let app = Xilem::new(app, app_logic);